@@ -0,0 +1,182 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime};
+
+use crate::{time, TicketQuery, Trac, TracChange, TracError, TracTicket};
+
+/// A stretch of time during which a field held one value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Interval {
+    pub value: String,
+    pub start: SystemTime,
+    /// `None` while the field still holds the value.
+    pub end: Option<SystemTime>,
+}
+
+impl Interval {
+    /// How long the interval lasted, or has lasted so far as of `now`.
+    pub fn duration(&self, now: SystemTime) -> Duration {
+        self.end
+            .unwrap_or(now)
+            .duration_since(self.start)
+            .unwrap_or_default()
+    }
+}
+
+/// The statuses and owners a ticket has had, and for how long.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TicketAging {
+    pub id: i32,
+    pub statuses: Vec<Interval>,
+    pub owners: Vec<Interval>,
+    /// When the history was read; intervals still open are measured up to it.
+    pub as_of: SystemTime,
+}
+
+// Splits a field's history into intervals, starting at the ticket's
+// creation with the value the first change replaced.
+fn intervals(ticket: &TracTicket, changes: &[TracChange], field: &str) -> Vec<Interval> {
+    let mut changes = changes.iter().filter(|c| c.field == field).peekable();
+    let initial = match changes.peek() {
+        Some(first) => first.old_value.to_owned(),
+        None => ticket.field(field).to_owned(),
+    };
+
+    let mut intervals = vec![Interval {
+        value: initial,
+        start: ticket.created,
+        end: None,
+    }];
+    for change in changes {
+        if let Some(current) = intervals.last_mut() {
+            current.end = Some(change.time);
+        }
+        intervals.push(Interval {
+            value: change.new_value.to_owned(),
+            start: change.time,
+            end: None,
+        });
+    }
+    intervals
+}
+
+fn totals(intervals: &[Interval], now: SystemTime) -> BTreeMap<String, Duration> {
+    let mut totals = BTreeMap::new();
+    for interval in intervals {
+        *totals.entry(interval.value.to_owned()).or_default() += interval.duration(now);
+    }
+    totals
+}
+
+impl TicketAging {
+    pub(crate) fn from_changelog(ticket: &TracTicket, changes: &[TracChange]) -> Self {
+        TicketAging {
+            id: ticket.id,
+            statuses: intervals(ticket, changes, "status"),
+            owners: intervals(ticket, changes, "owner"),
+            as_of: SystemTime::now(),
+        }
+    }
+
+    /// Total time spent in each status, over every visit to it.
+    pub fn status_totals(&self) -> BTreeMap<String, Duration> {
+        totals(&self.statuses, self.as_of)
+    }
+
+    /// Total time spent with each owner.
+    pub fn owner_totals(&self) -> BTreeMap<String, Duration> {
+        totals(&self.owners, self.as_of)
+    }
+
+    pub fn time_in_status(&self, status: &str) -> Duration {
+        self.status_totals().remove(status).unwrap_or_default()
+    }
+
+    pub fn time_with_owner(&self, owner: &str) -> Duration {
+        self.owner_totals().remove(owner).unwrap_or_default()
+    }
+
+    /// How long the ticket has been in its current status.
+    pub fn current_status_age(&self) -> Duration {
+        self.statuses
+            .last()
+            .map(|i| i.duration(self.as_of))
+            .unwrap_or_default()
+    }
+}
+
+/// Aging across a set of tickets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgingReport {
+    pub tickets: Vec<TicketAging>,
+}
+
+fn average(durations: &[Duration]) -> Option<Duration> {
+    if durations.is_empty() {
+        return None;
+    }
+    Some(durations.iter().sum::<Duration>() / durations.len() as u32)
+}
+
+impl AgingReport {
+    // Every finished visit to `status`, as (when it ended, how long it took).
+    fn visits<'a>(&'a self, status: &'a str) -> impl Iterator<Item = (SystemTime, Duration)> + 'a {
+        self.tickets
+            .iter()
+            .flat_map(|t| t.statuses.iter())
+            .filter(move |i| i.value == status)
+            .filter_map(|i| i.end.map(|end| (end, i.duration(end))))
+    }
+
+    /// The average length of a visit to `status`, counting only visits
+    /// that have ended.
+    pub fn average_in_status(&self, status: &str) -> Option<Duration> {
+        average(&self.visits(status).map(|(_, d)| d).collect::<Vec<_>>())
+    }
+
+    /// Like `average_in_status`, grouped by the month (`YYYY-MM`, UTC) in
+    /// which each visit ended.
+    pub fn average_in_status_by_month(&self, status: &str) -> BTreeMap<String, Duration> {
+        let mut months: BTreeMap<String, Vec<Duration>> = BTreeMap::new();
+        for (end, duration) in self.visits(status) {
+            let month = time::format_date(end)[..7].to_string();
+            months.entry(month).or_default().push(duration);
+        }
+        months
+            .into_iter()
+            .filter_map(|(month, durations)| average(&durations).map(|a| (month, a)))
+            .collect()
+    }
+
+    /// The tickets that have been in their current status for longer than
+    /// `age`, oldest first.
+    pub fn stale(&self, age: Duration) -> Vec<&TicketAging> {
+        let mut stale: Vec<&TicketAging> = self
+            .tickets
+            .iter()
+            .filter(|t| t.current_status_age() > age)
+            .collect();
+        stale.sort_by_key(|t| std::cmp::Reverse(t.current_status_age()));
+        stale
+    }
+}
+
+impl TracTicket {
+    pub fn aging(&self, trac: &Trac) -> Result<TicketAging, TracError> {
+        Ok(TicketAging::from_changelog(self, &self.changelog(trac)?))
+    }
+}
+
+impl Trac {
+    /// Reads the history of every ticket matching `query` for an aging report.
+    pub fn aging_report(&self, query: &TicketQuery) -> Result<AgingReport, TracError> {
+        let tickets = query.fetch(self)?;
+        let changelogs = self.changelogs(&tickets)?;
+        Ok(AgingReport {
+            tickets: tickets
+                .iter()
+                .zip(changelogs)
+                .map(|(ticket, changes)| TicketAging::from_changelog(ticket, &changes))
+                .collect(),
+        })
+    }
+}
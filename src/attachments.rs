@@ -0,0 +1,444 @@
+use std::fs;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use reqwest::blocking::Response;
+use reqwest::{StatusCode, Url};
+use xmlrpc::{Request, Value};
+
+use crate::bulk::outcomes;
+use crate::executor;
+use crate::{
+    scalar_to_string, time, BulkOutcome, BulkStatus, CancelToken, Trac, TracConfig, TracError,
+    TracTicket,
+};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+// Types recognised by extension, with the leading bytes files of that type
+// must start with, where there are any.
+const MIME_TYPES: &[(&str, &str, &[u8])] = &[
+    ("png", "image/png", b"\x89PNG"),
+    ("jpg", "image/jpeg", b"\xff\xd8\xff"),
+    ("jpeg", "image/jpeg", b"\xff\xd8\xff"),
+    ("gif", "image/gif", b"GIF8"),
+    ("pdf", "application/pdf", b"%PDF"),
+    ("zip", "application/zip", b"PK\x03\x04"),
+    ("gz", "application/gzip", b"\x1f\x8b"),
+    ("tgz", "application/gzip", b"\x1f\x8b"),
+    ("svg", "image/svg+xml", b""),
+    ("html", "text/html", b""),
+    ("xml", "application/xml", b""),
+    ("json", "application/json", b""),
+    ("csv", "text/csv", b""),
+    ("diff", "text/x-diff", b""),
+    ("patch", "text/x-diff", b""),
+    ("txt", "text/plain", b""),
+    ("log", "text/plain", b""),
+];
+
+fn known_type(filename: &str) -> Option<&'static (&'static str, &'static str, &'static [u8])> {
+    let (_, extension) = filename.rsplit_once('.')?;
+    let extension = extension.to_lowercase();
+    MIME_TYPES.iter().find(|(ext, _, _)| *ext == extension)
+}
+
+// The type whose signature `data` starts with, if any.
+fn signature_type(data: &[u8]) -> Option<&'static str> {
+    MIME_TYPES
+        .iter()
+        .find(|(_, _, magic)| !magic.is_empty() && data.starts_with(magic))
+        .map(|(_, mime, _)| *mime)
+}
+
+/// Guesses the MIME type of a file from its extension, falling back to its
+/// content: a known signature, else `text/plain` for UTF-8 text, else
+/// `application/octet-stream`. An empty `filename` goes by content alone.
+pub fn detect_mime_type(filename: &str, data: &[u8]) -> &'static str {
+    if let Some((_, mime, _)) = known_type(filename) {
+        return mime;
+    }
+    if let Some(mime) = signature_type(data) {
+        return mime;
+    }
+    if std::str::from_utf8(data).is_ok() {
+        "text/plain"
+    } else {
+        "application/octet-stream"
+    }
+}
+
+// Trac keeps only the last path component of an uploaded file's name.
+fn normalize_filename(filename: &str) -> String {
+    let name = filename.rsplit(['/', '\\']).next().unwrap_or("");
+    name.chars()
+        .filter(|c| !c.is_control())
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+fn validate_attachment(filename: &str, data: &[u8], config: &TracConfig) -> Result<(), TracError> {
+    if filename.is_empty() || filename == "." || filename == ".." {
+        return Err(TracError::InvalidAttachment(
+            "the file name is empty".to_string(),
+        ));
+    }
+    if let Some(max) = config.attachment_max_size {
+        if data.len() as u64 > max {
+            return Err(TracError::InvalidAttachment(format!(
+                "{} is {} bytes, over the limit of {}",
+                filename,
+                data.len(),
+                max
+            )));
+        }
+    }
+
+    if !config.attachment_check_content {
+        return Ok(());
+    }
+    // Types with a signature must carry it; the others must not look like
+    // one of those.
+    let (expected, detected) = match known_type(filename) {
+        Some((_, mime, magic)) if !magic.is_empty() => (Some(*mime), signature_type(data)),
+        Some(_) => (None, signature_type(data)),
+        None => return Ok(()),
+    };
+    if detected != expected {
+        return Err(TracError::InvalidAttachment(format!(
+            "{} should be {} but looks like {}",
+            filename,
+            detect_mime_type(filename, b""),
+            detect_mime_type("", data)
+        )));
+    }
+    Ok(())
+}
+
+/// Where the content of a new attachment comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttachmentSource {
+    /// A local file, attached under its file name.
+    Path(PathBuf),
+    Bytes {
+        filename: String,
+        data: Vec<u8>,
+    },
+}
+
+/// A file attached to a ticket, as listed by `ticket.listAttachments`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TracAttachment {
+    pub filename: String,
+    pub description: String,
+    pub size: u64,
+    pub time: SystemTime,
+    pub author: String,
+}
+
+impl TracAttachment {
+    fn from_value(entry: &Value) -> Option<Self> {
+        let text = |i: usize| entry.get(i).and_then(scalar_to_string).unwrap_or_default();
+        Some(TracAttachment {
+            filename: entry.get(0).and_then(scalar_to_string)?,
+            description: text(1),
+            size: text(2).parse().unwrap_or(0),
+            time: time::from_datetime(&entry.get(3)?.as_datetime()?),
+            author: text(4),
+        })
+    }
+}
+
+impl TracTicket {
+    pub fn attachments(&self, trac: &Trac) -> Result<Vec<TracAttachment>, TracError> {
+        match trac.call(&Request::new("ticket.listAttachments").arg(self.id))? {
+            Value::Array(entries) => Ok(entries
+                .iter()
+                .filter_map(TracAttachment::from_value)
+                .collect()),
+            _ => Err(TracError::invalid_response("ticket.listAttachments")),
+        }
+    }
+
+    /// Reads a whole attachment into memory through XML-RPC. For large files
+    /// use `download_attachment`, which streams.
+    pub fn get_attachment(&self, filename: &str, trac: &Trac) -> Result<Vec<u8>, TracError> {
+        match trac.call(
+            &Request::new("ticket.getAttachment")
+                .arg(self.id)
+                .arg(filename),
+        )? {
+            Value::Base64(data) => Ok(data),
+            _ => Err(TracError::invalid_response("ticket.getAttachment")),
+        }
+    }
+
+    /// Uploads an attachment from `source` without replacing an existing
+    /// one, and returns the name it was stored under.
+    pub fn put_attachment_from(
+        &self,
+        source: &AttachmentSource,
+        description: &str,
+        trac: &Trac,
+    ) -> Result<String, TracError> {
+        match source {
+            AttachmentSource::Path(path) => {
+                let data = fs::read(path)
+                    .map_err(|e| TracError::Io(format!("{}: {}", path.display(), e)))?;
+                let filename = path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                self.put_attachment(&filename, description, &data, false, trac)
+            }
+            AttachmentSource::Bytes { filename, data } => {
+                self.put_attachment(filename, description, data, false, trac)
+            }
+        }
+    }
+
+    /// Uploads a file, replacing any attachment of the same name if
+    /// `replace` is set, and returns the name the server stored it under.
+    /// The name is reduced to its last path component, and the upload is
+    /// refused up front if the file is over `TracConfig::attachment_max_size`
+    /// or, with `TracConfig::attachment_check_content`, its content does not
+    /// match its extension.
+    pub fn put_attachment(
+        &self,
+        filename: &str,
+        description: &str,
+        data: &[u8],
+        replace: bool,
+        trac: &Trac,
+    ) -> Result<String, TracError> {
+        let filename = normalize_filename(filename);
+        validate_attachment(&filename, data, &trac.config)?;
+
+        let request = Request::new("ticket.putAttachment")
+            .arg(self.id)
+            .arg(filename.as_str())
+            .arg(description)
+            .arg(Value::Base64(data.to_vec()))
+            .arg(replace);
+        match trac.call(&request)? {
+            Value::String(stored) => Ok(stored),
+            _ => Ok(filename),
+        }
+    }
+
+    /// Streams an attachment into `sink` and returns the number of bytes
+    /// written.
+    pub fn download_attachment<W: Write>(
+        &self,
+        filename: &str,
+        sink: &mut W,
+        trac: &Trac,
+    ) -> Result<u64, TracError> {
+        self.download_attachment_with_progress(filename, sink, |_, _| true, trac)
+    }
+
+    /// Like `download_attachment`, calling `progress` with the bytes written
+    /// so far and the total size, if the server sent it, after each chunk.
+    /// Returning `false` from `progress` cancels the download.
+    ///
+    /// The file is fetched from the `raw-attachment` URL rather than through
+    /// XML-RPC, so the server must accept HTTP basic authentication there.
+    pub fn download_attachment_with_progress<W, P>(
+        &self,
+        filename: &str,
+        sink: &mut W,
+        mut progress: P,
+        trac: &Trac,
+    ) -> Result<u64, TracError>
+    where
+        W: Write,
+        P: FnMut(u64, Option<u64>) -> bool,
+    {
+        let mut response = trac.raw_attachment(self.id, filename)?;
+        let total = response.content_length();
+        let mut buffer = vec![0; CHUNK_SIZE];
+        let mut written: u64 = 0;
+
+        loop {
+            let n = response
+                .read(&mut buffer)
+                .map_err(|e| TracError::Transport(e.to_string()))?;
+            if n == 0 {
+                break;
+            }
+            sink.write_all(&buffer[..n])
+                .map_err(|e| TracError::Io(e.to_string()))?;
+            written += n as u64;
+            if !progress(written, total) {
+                return Err(TracError::Cancelled);
+            }
+        }
+
+        sink.flush().map_err(|e| TracError::Io(e.to_string()))?;
+        Ok(written)
+    }
+}
+
+impl Trac {
+    /// Uploads the same file to every ticket in `ids` using multicall, such
+    /// as a log that several reports share. The file is checked once, as
+    /// for `TracTicket::put_attachment`; a ticket that already has an
+    /// attachment of that name fails unless `replace` is set.
+    pub fn bulk_attach(
+        &self,
+        ids: &[i32],
+        filename: &str,
+        description: &str,
+        data: &[u8],
+        replace: bool,
+    ) -> Result<Vec<BulkOutcome>, TracError> {
+        let filename = normalize_filename(filename);
+        validate_attachment(&filename, data, &self.config)?;
+
+        let jobs = ids
+            .iter()
+            .map(|id| {
+                let request = Request::new("ticket.putAttachment")
+                    .arg(*id)
+                    .arg(filename.as_str())
+                    .arg(description)
+                    .arg(Value::Base64(data.to_vec()))
+                    .arg(replace);
+                executor::call(request, |_| Ok(BulkStatus::Applied))
+            })
+            .collect();
+        Ok(outcomes(ids, self.execute(jobs, &CancelToken::new())))
+    }
+
+    // A page of the web interface, e.g. `["raw-attachment", "ticket", "1"]`
+    // for `<server>/raw-attachment/ticket/1`.
+    pub(crate) fn web_url(&self, segments: &[&str]) -> Result<Url, TracError> {
+        let mut url = Url::parse(&self.url()).map_err(|e| TracError::Config(e.to_string()))?;
+        url.path_segments_mut()
+            .map_err(|_| TracError::Config(format!("invalid server URL: {}", self.url())))?
+            .pop_if_empty()
+            .extend(segments);
+        Ok(url)
+    }
+
+    // Fetches a page of the web interface with the client's credentials,
+    // whatever its status.
+    pub(crate) fn web_get(&self, url: &Url) -> Result<Response, TracError> {
+        let get = || {
+            let user = self.user();
+            let mut request = self
+                .client
+                .get(url.clone())
+                .basic_auth(&user.username, Some(&user.password));
+            if let Some(timeout) = self.call_options().timeout {
+                request = request.timeout(timeout);
+            }
+            request
+                .send()
+                .map_err(|e| TracError::Transport(e.to_string()))
+        };
+
+        let mut response = get()?;
+        if response.status() == StatusCode::UNAUTHORIZED && self.reauthenticate() {
+            response = get()?;
+        }
+        Ok(response)
+    }
+
+    fn raw_attachment(&self, id: i32, filename: &str) -> Result<Response, TracError> {
+        let url = self.web_url(&["raw-attachment", "ticket", &id.to_string(), filename])?;
+        let response = self.web_get(&url)?;
+        match response.status() {
+            s if s.is_success() => Ok(response),
+            StatusCode::NOT_FOUND => Err(TracError::Fault {
+                code: 404,
+                message: format!("attachment '{}' not found on ticket #{}", filename, id),
+            }),
+            StatusCode::FORBIDDEN => {
+                Err(TracError::PermissionDenied("ATTACHMENT_VIEW".to_string()))
+            }
+            s => Err(TracError::Http {
+                status: s.as_u16(),
+                message: url.to_string(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn detects_by_extension_then_content() {
+        assert_eq!(detect_mime_type("a.PNG", b""), "image/png");
+        assert_eq!(detect_mime_type("a", b"%PDF-1.4"), "application/pdf");
+        assert_eq!(detect_mime_type("a", b"hello"), "text/plain");
+        assert_eq!(
+            detect_mime_type("a", b"\xff\xfe\x00"),
+            "application/octet-stream"
+        );
+    }
+
+    #[test]
+    fn keeps_last_path_component() {
+        assert_eq!(normalize_filename("C:\\logs\\build.log"), "build.log");
+        assert_eq!(normalize_filename("/tmp/a\nb.txt "), "ab.txt");
+    }
+
+    #[test]
+    fn no_limit_by_default() {
+        let config = testing::config();
+        assert!(validate_attachment("big.bin", &vec![0; 1 << 20], &config).is_ok());
+        assert!(validate_attachment("..", b"", &config).is_err());
+    }
+
+    #[test]
+    fn applies_configured_limit() {
+        let mut config = testing::config();
+        config.attachment_max_size = Some(4);
+        assert!(validate_attachment("a.txt", b"1234", &config).is_ok());
+        assert!(validate_attachment("a.txt", b"12345", &config).is_err());
+    }
+
+    #[test]
+    fn content_check_is_opt_in() {
+        let mut config = testing::config();
+        let empty_zip = b"PK\x05\x06";
+        assert!(validate_attachment("empty.zip", empty_zip, &config).is_ok());
+
+        config.attachment_check_content = true;
+        assert!(validate_attachment("shot.png", b"\x89PNG\r\n", &config).is_ok());
+        match validate_attachment("shot.png", b"%PDF-1.4", &config) {
+            Err(TracError::InvalidAttachment(message)) => {
+                assert_eq!(
+                    message,
+                    "shot.png should be image/png but looks like application/pdf"
+                )
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn content_check_compares_detected_types() {
+        let mut config = testing::config();
+        config.attachment_check_content = true;
+        assert!(validate_attachment("photo.JPEG", b"\xff\xd8\xff\xe0", &config).is_ok());
+        assert!(validate_attachment("build.log", b"\xff\xfe latin-1", &config).is_ok());
+        assert!(validate_attachment("data.bin", b"\x89PNG", &config).is_ok());
+        match validate_attachment("notes.txt", b"\x89PNG\r\n", &config) {
+            Err(TracError::InvalidAttachment(message)) => {
+                assert_eq!(
+                    message,
+                    "notes.txt should be text/plain but looks like image/png"
+                )
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(validate_attachment("shot.gif", b"", &config).is_err());
+    }
+}
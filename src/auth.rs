@@ -0,0 +1,132 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use std::thread;
+use std::time::Instant;
+
+use reqwest::blocking::Response;
+use reqwest::StatusCode;
+use xmlrpc::{Request, Transport, Value};
+
+use crate::transport::{is_read_only, method_name, CallPriority, StatusTransport};
+use crate::{Trac, TracError, TracUser};
+
+/// Supplies new credentials when the server rejects the current ones, e.g.
+/// after a password rotation. Returning `None` gives up and lets the call
+/// fail.
+pub trait CredentialProvider {
+    fn refresh(&self, rejected: &TracUser) -> Option<TracUser>;
+}
+
+impl<F> CredentialProvider for F
+where
+    F: Fn(&TracUser) -> Option<TracUser>,
+{
+    fn refresh(&self, rejected: &TracUser) -> Option<TracUser> {
+        self(rejected)
+    }
+}
+
+impl Trac {
+    pub fn set_credential_provider<P: CredentialProvider + 'static>(&mut self, provider: P) {
+        self.credentials = Some(Box::new(provider));
+    }
+
+    /// The credentials currently sent with each request.
+    pub fn user(&self) -> Rc<TracUser> {
+        Rc::clone(&self.user.borrow())
+    }
+
+    /// Sends `request`. If the server answers 401 and the credential
+    /// provider supplies new credentials, they replace the old ones and the
+    /// request is retried once. Other failures of read-only calls are
+    /// retried as the current `CallOptions` allow.
+    pub(crate) fn call(&self, request: &Request) -> Result<Value, TracError> {
+        self.call_repeatable(request, is_read_only(&method_name(request)))
+    }
+
+    /// Like `call`, with `repeatable` saying whether the request is safe to
+    /// retry, for requests such as multicalls whose method name does not
+    /// tell.
+    pub(crate) fn call_repeatable(
+        &self,
+        request: &Request,
+        repeatable: bool,
+    ) -> Result<Value, TracError> {
+        let options = self.call_options();
+        let retry = match options.retry {
+            Some(policy) if repeatable || options.retry_writes => Some(policy),
+            _ => None,
+        };
+        let mut attempt = 0;
+        loop {
+            let status = Cell::new(None);
+            let started = Instant::now();
+            let result = self
+                .call_once(request, &status)
+                .map_err(|e| TracError::from_call(e, status.get()));
+            if options.priority == CallPriority::Background {
+                thread::sleep(started.elapsed());
+            }
+            match (&result, &retry) {
+                (Err(e), Some(policy)) if attempt < policy.retries && e.is_retryable() => {
+                    attempt += 1;
+                    thread::sleep(policy.delay(attempt));
+                }
+                _ => return result,
+            }
+        }
+    }
+
+    fn call_once(
+        &self,
+        request: &Request,
+        status: &Cell<Option<StatusCode>>,
+    ) -> Result<Value, xmlrpc::Error> {
+        let result = request.call(StatusTransport::new(self.get_transport(), status));
+
+        if result.is_err()
+            && status.get() == Some(StatusCode::UNAUTHORIZED)
+            && self.reauthenticate()
+        {
+            return request.call(StatusTransport::new(self.get_transport(), status));
+        }
+        result
+    }
+
+    /// Like `call`, but returns the HTTP response unread, for callers that
+    /// parse large responses as they arrive.
+    pub(crate) fn send(&self, request: &Request) -> Result<Response, TracError> {
+        let status = Cell::new(None);
+        let transmit = |status: &Cell<Option<StatusCode>>| {
+            StatusTransport::new(self.get_transport(), status)
+                .transmit(request)
+                .map_err(|e| match status.get() {
+                    Some(s) if !s.is_success() => TracError::from_status(s),
+                    _ => TracError::Transport(e.to_string()),
+                })
+        };
+
+        let result = transmit(&status);
+        if result.is_err()
+            && status.get() == Some(StatusCode::UNAUTHORIZED)
+            && self.reauthenticate()
+        {
+            return transmit(&status);
+        }
+        result
+    }
+
+    pub(crate) fn reauthenticate(&self) -> bool {
+        let provider = match &self.credentials {
+            Some(p) => p,
+            None => return false,
+        };
+        match provider.refresh(&self.user()) {
+            Some(user) => {
+                *self.user.borrow_mut() = Rc::new(user);
+                true
+            }
+            None => false,
+        }
+    }
+}
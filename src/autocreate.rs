@@ -0,0 +1,57 @@
+use std::collections::BTreeMap;
+
+use xmlrpc::{Request, Value};
+
+use crate::{scalar_to_string, Trac, TracError};
+
+// Ticket fields whose values are managed objects, and the RPC namespace
+// that manages them.
+const MANAGED_FIELDS: &[(&str, &str)] = &[
+    ("milestone", "ticket.milestone"),
+    ("component", "ticket.component"),
+    ("version", "ticket.version"),
+];
+
+impl Trac {
+    /// Makes sure `value` exists on the server if `field` is a milestone,
+    /// component or version, creating it with default attributes if not.
+    /// Returns whether it had to be created.
+    pub fn ensure_exists(&self, field: &str, value: &str) -> Result<bool, TracError> {
+        let namespace = match MANAGED_FIELDS.iter().find(|(f, _)| *f == field) {
+            Some((_, namespace)) => namespace,
+            None => return Ok(false),
+        };
+        if value.is_empty() {
+            return Ok(false);
+        }
+
+        let get_all = format!("{}.getAll", namespace);
+        let existing: Vec<String> = match self.call(&Request::new(&get_all))? {
+            Value::Array(names) => names.iter().filter_map(scalar_to_string).collect(),
+            _ => return Err(TracError::invalid_response(&get_all)),
+        };
+        if existing.iter().any(|name| name == value) {
+            return Ok(false);
+        }
+
+        let create = format!("{}.create", namespace);
+        self.call(
+            &Request::new(&create)
+                .arg(value)
+                .arg(Value::Struct(BTreeMap::new())),
+        )?;
+        // The field's options now include the new value.
+        self.invalidate_fields();
+        Ok(true)
+    }
+
+    pub(crate) fn ensure_all_exist<'a, I>(&self, attributes: I) -> Result<(), TracError>
+    where
+        I: IntoIterator<Item = (&'a String, &'a String)>,
+    {
+        for (field, value) in attributes {
+            self.ensure_exists(field, value)?;
+        }
+        Ok(())
+    }
+}
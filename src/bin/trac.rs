@@ -0,0 +1,187 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::rc::Rc;
+
+use trac::{
+    validate_commit_message, CommitPolicy, PlainFormatter, ReviewNotification, TemplateFormatter,
+    TicketFormatter, TicketQuery, Trac, TracConfig, TracError,
+};
+
+const USAGE: &str = "\
+usage: trac [--config FILE] [--format FORMAT] COMMAND [ARGS]
+
+commands:
+    get ID                      show a ticket
+    query QUERY                 list the tickets matching a Trac query string
+    comment ID TEXT             add a comment
+    review request ID REVIEWER  send a ticket for review
+    review pass ID [COMMENT]    approve a ticket under review
+    review fail ID REASON       send a ticket back from review
+    close ID [COMMENT]          close a ticket
+    attach ID FILE [DESC]       attach a file to a ticket
+    check-commit FILE           check the tickets a commit message refers to
+
+The config file defaults to $TRAC_CONFIG, then ~/.trac.ini.
+
+FORMAT changes how get and query print tickets, e.g. '{id}\\t{owner}\\t{summary}'.
+Fields are named in braces, and \\t, \\n and \\\\ stand for a tab, a newline
+and a backslash.";
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2)
+}
+
+fn config_path(explicit: Option<String>) -> PathBuf {
+    if let Some(path) = explicit.or_else(|| env::var("TRAC_CONFIG").ok()) {
+        return PathBuf::from(path);
+    }
+    match env::var("HOME") {
+        Ok(home) => Path::new(&home).join(".trac.ini"),
+        Err(_) => PathBuf::from(".trac.ini"),
+    }
+}
+
+// Lets a format given on the command line spell tabs and newlines.
+fn unescape_format(format: &str) -> String {
+    let mut out = String::with_capacity(format.len());
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('\\') => out.push('\\'),
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+fn parse_id(arg: &str) -> i32 {
+    match arg.trim_start_matches('#').parse() {
+        Ok(id) => id,
+        Err(_) => {
+            eprintln!("not a ticket id: {}", arg);
+            process::exit(2)
+        }
+    }
+}
+
+fn run(trac: &Trac, args: &[String], formatter: &dyn TicketFormatter) -> Result<(), TracError> {
+    let arg = |i: usize| args.get(i).map(|s| s.as_str());
+    let rest = |i: usize| match args.get(i..) {
+        Some(words) if !words.is_empty() => Some(words.join(" ")),
+        _ => None,
+    };
+
+    match (arg(0), arg(1)) {
+        (Some("get"), Some(id)) => {
+            println!(
+                "{}",
+                formatter.format_detail(&trac.get_ticket(parse_id(id))?)
+            );
+        }
+        (Some("query"), Some(_)) => {
+            let query = TicketQuery::parse(&rest(1).unwrap_or_default())?;
+            let tickets = query.fetch(trac)?;
+            if !tickets.is_empty() {
+                println!("{}", formatter.format_list(&tickets));
+            }
+        }
+        (Some("comment"), Some(id)) => {
+            let text = rest(2).unwrap_or_else(|| usage());
+            trac.get_ticket(parse_id(id))?.comment(&text, None, trac)?;
+        }
+        (Some("review"), Some(step)) => {
+            let ticket = trac.get_ticket(parse_id(arg(2).unwrap_or_else(|| usage())))?;
+            match step {
+                "request" => {
+                    let reviewer = rest(3).unwrap_or_else(|| usage());
+                    if let ReviewNotification::Failed(e) = ticket.request_review(reviewer, trac)? {
+                        eprintln!("Review requested, but the email failed: {}", e);
+                    }
+                }
+                "pass" => ticket.review_pass(rest(3), trac)?,
+                "fail" => ticket.review_fail(rest(3).unwrap_or_else(|| usage()), trac)?,
+                _ => usage(),
+            }
+        }
+        (Some("close"), Some(id)) => {
+            trac.get_ticket(parse_id(id))?.close(rest(2), trac)?;
+        }
+        (Some("attach"), Some(id)) => {
+            let path = arg(2).unwrap_or_else(|| usage());
+            let data = fs::read(path).map_err(|e| TracError::Io(format!("{}: {}", path, e)))?;
+            let filename = Path::new(path)
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_else(|| path.to_string());
+            let description = rest(3).unwrap_or_default();
+            let ticket = trac.get_ticket(parse_id(id))?;
+            let stored = ticket.put_attachment(&filename, &description, &data, false, trac)?;
+            println!("{}", stored);
+        }
+        (Some("check-commit"), Some(path)) => {
+            let text =
+                fs::read_to_string(path).map_err(|e| TracError::Io(format!("{}: {}", path, e)))?;
+            let violations = validate_commit_message(&text, &CommitPolicy::default(), trac)?;
+            for violation in &violations {
+                eprintln!("{}", violation);
+            }
+            if !violations.is_empty() {
+                process::exit(1)
+            }
+        }
+        _ => usage(),
+    }
+    Ok(())
+}
+
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let mut config = None;
+    let mut format = None;
+    while let Some(option) = args.first().map(|a| a.to_string()) {
+        let target = match option.as_str() {
+            "--config" => &mut config,
+            "--format" => &mut format,
+            _ => break,
+        };
+        if args.len() < 2 {
+            usage();
+        }
+        *target = Some(args.remove(1));
+        args.remove(0);
+    }
+    let formatter: Box<dyn TicketFormatter> = match format {
+        Some(format) => Box::new(TemplateFormatter::new(&unescape_format(&format))),
+        None => Box::new(PlainFormatter),
+    };
+    if args.is_empty() || args[0] == "--help" || args[0] == "-h" {
+        usage();
+    }
+
+    let path = config_path(config);
+    let trac = match TracConfig::from_file(&path).and_then(|c| Trac::try_new(Rc::new(c))) {
+        Ok(trac) => trac,
+        Err(e) => {
+            eprintln!("{}: {}", path.display(), e);
+            process::exit(1)
+        }
+    };
+
+    if let Err(e) = run(&trac, &args, formatter.as_ref()) {
+        eprintln!("Error: {}", e);
+        process::exit(1)
+    }
+}
@@ -0,0 +1,254 @@
+use std::rc::Rc;
+use std::time::Duration;
+
+use reqwest::Url;
+
+use crate::{CredentialProvider, RetryPolicy, RpcEndpoint, Trac, TracConfig, TracError, TracUser};
+
+/// Sets up a `Trac` client without building the config by hand:
+///
+/// ```ignore
+/// let trac = Trac::builder()
+///     .host("trac.example.com")
+///     .path("/projects/foo/")
+///     .basic_auth("alice", "secret")
+///     .timeout(Duration::from_secs(30))
+///     .build()?;
+/// ```
+pub struct TracBuilder {
+    host: Option<String>,
+    path: String,
+    user: Option<TracUser>,
+    timeout: Option<Duration>,
+    retry: Option<RetryPolicy>,
+    endpoint: Option<RpcEndpoint>,
+    batch_size: Option<usize>,
+    compression: Option<bool>,
+    credentials: Option<Box<dyn CredentialProvider>>,
+}
+
+// The path with exactly one slash at each end and none doubled, as
+// `Trac::url` expects.
+fn normalize_path(path: &str) -> String {
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    if segments.is_empty() {
+        "/".to_string()
+    } else {
+        format!("/{}/", segments.join("/"))
+    }
+}
+
+fn check_host(host: &str) -> Result<(), TracError> {
+    let invalid = |reason: &str| {
+        Err(TracError::Config(format!(
+            "invalid host '{}': {}",
+            host, reason
+        )))
+    };
+    if host.is_empty() {
+        return invalid("it is empty");
+    }
+    if host.contains("://") {
+        return invalid("give the host name without a scheme");
+    }
+    match Url::parse(&format!("https://{}", host)) {
+        Ok(url) if url.path() != "/" || url.query().is_some() => {
+            invalid("give the path separately with `path`")
+        }
+        Ok(url) if !url.username().is_empty() || url.password().is_some() => {
+            invalid("give credentials with `basic_auth`")
+        }
+        Ok(url) if url.host_str().is_some() => Ok(()),
+        Ok(_) => invalid("no host name"),
+        Err(e) => invalid(&e.to_string()),
+    }
+}
+
+impl TracBuilder {
+    pub fn new() -> Self {
+        Self {
+            host: None,
+            path: "/".to_string(),
+            user: None,
+            timeout: None,
+            retry: None,
+            endpoint: None,
+            batch_size: None,
+            compression: None,
+            credentials: None,
+        }
+    }
+
+    /// The server's host name, with a port if needed.
+    pub fn host(mut self, host: &str) -> Self {
+        self.host = Some(host.trim().to_string());
+        self
+    }
+
+    /// Where Trac is on the server; slashes are added or removed as needed.
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = normalize_path(path);
+        self
+    }
+
+    pub fn basic_auth(mut self, username: &str, password: &str) -> Self {
+        self.user = Some(TracUser {
+            username: username.to_string(),
+            password: password.to_string(),
+        });
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// The XML-RPC path, as for the `rpc_path` setting.
+    pub fn rpc_path(mut self, path: &str) -> Self {
+        self.endpoint = Some(RpcEndpoint::from_setting(path));
+        self
+    }
+
+    pub fn batch_size(mut self, size: usize) -> Self {
+        self.batch_size = Some(size);
+        self
+    }
+
+    pub fn compression(mut self, enabled: bool) -> Self {
+        self.compression = Some(enabled);
+        self
+    }
+
+    pub fn credential_provider<P: CredentialProvider + 'static>(mut self, provider: P) -> Self {
+        self.credentials = Some(Box::new(provider));
+        self
+    }
+
+    /// Checks the settings and creates the client. Everything not set keeps
+    /// the `TracConfig::new` default.
+    pub fn build(self) -> Result<Trac, TracError> {
+        let host = self
+            .host
+            .ok_or_else(|| TracError::Config("no host given".to_string()))?;
+        check_host(&host)?;
+        let user = self
+            .user
+            .ok_or_else(|| TracError::Config("no credentials given".to_string()))?;
+        if self.batch_size == Some(0) {
+            return Err(TracError::Config(
+                "batch size must be at least 1".to_string(),
+            ));
+        }
+
+        let mut config = TracConfig::new(Rc::new(user), &host, &self.path);
+        config.call.timeout = self.timeout.or(config.call.timeout);
+        config.call.retry = self.retry.or(config.call.retry);
+        if let Some(endpoint) = self.endpoint {
+            config.endpoint = endpoint;
+        }
+        if let Some(size) = self.batch_size {
+            config.batch_size = size;
+        }
+        if let Some(enabled) = self.compression {
+            config.compression = enabled;
+        }
+
+        let mut trac = Trac::try_new(Rc::new(config))?;
+        trac.credentials = self.credentials;
+        Ok(trac)
+    }
+}
+
+impl Default for TracBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Trac {
+    pub fn builder() -> TracBuilder {
+        TracBuilder::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host_error(host: &str) -> String {
+        match check_host(host) {
+            Err(TracError::Config(message)) => message,
+            other => panic!("{}: {:?}", host, other),
+        }
+    }
+
+    #[test]
+    fn normalizes_paths() {
+        assert_eq!(normalize_path(""), "/");
+        assert_eq!(normalize_path("/"), "/");
+        assert_eq!(normalize_path("trac"), "/trac/");
+        assert_eq!(normalize_path("/trac"), "/trac/");
+        assert_eq!(normalize_path("//projects//foo///"), "/projects/foo/");
+    }
+
+    #[test]
+    fn checks_hosts() {
+        assert_eq!(check_host("trac.example.com"), Ok(()));
+        assert_eq!(check_host("127.0.0.1:8080"), Ok(()));
+        assert!(host_error("").contains("empty"));
+        assert!(host_error("https://trac.example.com").contains("scheme"));
+        assert!(host_error("trac.example.com/foo").contains("path"));
+        assert!(host_error("trac.example.com?x=1").contains("path"));
+        assert!(host_error("alice:secret@trac.example.com").contains("basic_auth"));
+        assert!(host_error("trac.example.com:http").starts_with("invalid host"));
+    }
+
+    #[test]
+    fn builds_the_config() {
+        let trac = Trac::builder()
+            .host(" trac.example.com ")
+            .path("projects/foo")
+            .basic_auth("alice", "secret")
+            .timeout(Duration::from_secs(30))
+            .batch_size(10)
+            .compression(false)
+            .build()
+            .unwrap();
+        assert_eq!(trac.config.host, "trac.example.com");
+        assert_eq!(trac.config.path, "/projects/foo/");
+        assert_eq!(trac.config.user.username, "alice");
+        assert_eq!(trac.config.call.timeout, Some(Duration::from_secs(30)));
+        assert_eq!(trac.config.batch_size, 10);
+        assert!(!trac.config.compression);
+    }
+
+    #[test]
+    fn refuses_incomplete_settings() {
+        let error = |builder: TracBuilder| match builder.build() {
+            Err(TracError::Config(message)) => message,
+            Err(e) => panic!("{:?}", e),
+            Ok(_) => panic!("built"),
+        };
+        assert_eq!(
+            error(Trac::builder().basic_auth("alice", "secret")),
+            "no host given"
+        );
+        assert_eq!(
+            error(Trac::builder().host("trac.example.com")),
+            "no credentials given"
+        );
+        assert!(error(
+            Trac::builder()
+                .host("trac.example.com")
+                .basic_auth("alice", "secret")
+                .batch_size(0)
+        )
+        .contains("batch size"));
+    }
+}
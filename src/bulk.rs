@@ -0,0 +1,220 @@
+use xmlrpc::{Request, Value};
+
+use crate::executor::{Job, Step};
+use crate::template::substitute;
+use crate::{update_request, CancelToken, Trac, TracAction, TracError, TracTicket, UpdateOptions};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BulkStatus {
+    Applied,
+    /// The ticket does not offer the action in its current state; lists the
+    /// actions it does offer.
+    Unavailable(Vec<String>),
+    Failed(TracError),
+}
+
+#[derive(Debug, Clone)]
+pub struct BulkOutcome {
+    pub id: i32,
+    pub status: BulkStatus,
+}
+
+pub(crate) fn outcomes(
+    ids: &[i32],
+    results: Vec<Result<BulkStatus, TracError>>,
+) -> Vec<BulkOutcome> {
+    ids.iter()
+        .zip(results)
+        .map(|(id, result)| BulkOutcome {
+            id: *id,
+            status: result.unwrap_or_else(BulkStatus::Failed),
+        })
+        .collect()
+}
+
+// How far an `ActionJob` has got: the call it made last.
+#[derive(Clone, Copy)]
+enum ActionStage {
+    Start,
+    Fetched,
+    Offered,
+    Sent,
+}
+
+// Fetches the ticket if a `TransitionGuard` covers the action and checks
+// it, looks up the actions the ticket offers, then applies the action if it
+// is one of them.
+struct ActionJob<'a> {
+    id: i32,
+    action: &'a TracAction,
+    comment: &'a Option<String>,
+    guarded: bool,
+    stage: ActionStage,
+    trac: &'a Trac,
+}
+
+impl Job for ActionJob<'_> {
+    type Output = BulkStatus;
+
+    fn step(&mut self, previous: Option<Result<Value, TracError>>) -> Step<BulkStatus> {
+        let result = match previous {
+            None => Value::Nil,
+            Some(Err(e)) => return Step::Done(Err(e)),
+            Some(Ok(result)) => result,
+        };
+        let items = match (self.stage, result) {
+            (ActionStage::Start, _) if self.guarded => {
+                self.stage = ActionStage::Fetched;
+                return Step::Call(Request::new("ticket.get").arg(self.id));
+            }
+            (ActionStage::Start, _) => {
+                self.stage = ActionStage::Offered;
+                return Step::Call(Request::new("ticket.getActions").arg(self.id));
+            }
+            (ActionStage::Fetched, ticket) => {
+                let ticket = TracTicket::from_value(&ticket);
+                if let Err(e) = ticket.check_guards(self.action, &[], self.trac) {
+                    return Step::Done(Err(e));
+                }
+                self.stage = ActionStage::Offered;
+                return Step::Call(Request::new("ticket.getActions").arg(self.id));
+            }
+            (ActionStage::Sent, _) => return Step::Done(Ok(BulkStatus::Applied)),
+            (ActionStage::Offered, Value::Array(items)) => items,
+            (ActionStage::Offered, _) => {
+                return Step::Done(Err(TracError::invalid_response("ticket.getActions")))
+            }
+        };
+
+        let offered: Vec<TracAction> = items.iter().map(TracAction::from_value).collect();
+        match offered.iter().find(|a| a.name == self.action.name) {
+            Some(server_action) => {
+                let mut merged = self.action.clone();
+                for input in &server_action.inputs {
+                    if !merged.inputs.iter().any(|i| i.name == input.name) {
+                        merged.inputs.push(input.clone());
+                    }
+                }
+                self.stage = ActionStage::Sent;
+                Step::Call(update_request(
+                    self.id,
+                    vec![],
+                    Some(merged),
+                    self.comment.clone(),
+                    &UpdateOptions::new(),
+                ))
+            }
+            None => Step::Done(Ok(BulkStatus::Unavailable(
+                offered.into_iter().map(|a| a.name).collect(),
+            ))),
+        }
+    }
+}
+
+// Posts the comment, fetching the ticket first if its placeholders need
+// filling in.
+struct CommentJob<'a> {
+    id: i32,
+    text: &'a str,
+    comment: Option<String>,
+    options: &'a UpdateOptions,
+    sent: bool,
+}
+
+impl Job for CommentJob<'_> {
+    type Output = BulkStatus;
+
+    fn step(&mut self, previous: Option<Result<Value, TracError>>) -> Step<BulkStatus> {
+        let comment = match (previous, self.comment.take()) {
+            (None, Some(comment)) => comment,
+            (None, None) => return Step::Call(Request::new("ticket.get").arg(self.id)),
+            (Some(Err(e)), _) => return Step::Done(Err(e)),
+            (Some(Ok(_)), _) if self.sent => return Step::Done(Ok(BulkStatus::Applied)),
+            (Some(Ok(r)), _) => TracTicket::from_value(&r).fmt_template(self.text),
+        };
+        self.sent = true;
+        Step::Call(update_request(
+            self.id,
+            vec![],
+            None,
+            Some(comment),
+            self.options,
+        ))
+    }
+}
+
+impl Trac {
+    /// Applies `action` to every ticket in `ids` using multicall. Each
+    /// ticket's available actions are checked first, so tickets that cannot
+    /// take the action are reported rather than sent. Input values not set
+    /// on `action` fall back to the defaults the server offers per ticket.
+    /// If a `TransitionGuard` covers the action, each ticket is fetched and
+    /// checked first, and one that fails is `Failed` with
+    /// `TracError::TransitionDenied`.
+    pub fn bulk_action(
+        &self,
+        ids: &[i32],
+        action: TracAction,
+        comment: Option<String>,
+    ) -> Result<Vec<BulkOutcome>, TracError> {
+        self.bulk_action_cancellable(ids, action, comment, &CancelToken::new())
+    }
+
+    /// Like `bulk_action`, but stops before the next batch once `cancel` is
+    /// set. The outcomes still cover every ticket: those not reached are
+    /// `Failed(TracError::Cancelled)` and were left untouched. Likewise, if
+    /// a batch fails as a whole, the tickets in it and those not reached
+    /// are `Failed` with its error, and the outcomes of earlier batches are
+    /// kept.
+    pub fn bulk_action_cancellable(
+        &self,
+        ids: &[i32],
+        action: TracAction,
+        comment: Option<String>,
+        cancel: &CancelToken,
+    ) -> Result<Vec<BulkOutcome>, TracError> {
+        let guarded = self.guards_for(&action.name).next().is_some();
+        let jobs = ids
+            .iter()
+            .map(|id| ActionJob {
+                id: *id,
+                action: &action,
+                comment: &comment,
+                guarded,
+                stage: ActionStage::Start,
+                trac: self,
+            })
+            .collect();
+        Ok(outcomes(ids, self.execute(jobs, cancel)))
+    }
+
+    /// Posts `text` as a comment on every ticket in `ids` using multicall.
+    /// `{name}` placeholders are filled in from each ticket's own fields, as
+    /// for `TracTicket::fmt_template`; the tickets are only fetched if the
+    /// text has any.
+    pub fn bulk_comment(&self, ids: &[i32], text: &str) -> Result<Vec<BulkOutcome>, TracError> {
+        self.bulk_comment_with(ids, text, &UpdateOptions::new())
+    }
+
+    /// Like `bulk_comment`, with control over notification and the recorded
+    /// author.
+    pub fn bulk_comment_with(
+        &self,
+        ids: &[i32],
+        text: &str,
+        options: &UpdateOptions,
+    ) -> Result<Vec<BulkOutcome>, TracError> {
+        let comment = substitute(text, |_| None).ok();
+        let jobs = ids
+            .iter()
+            .map(|id| CommentJob {
+                id: *id,
+                text,
+                comment: comment.clone(),
+                options,
+                sent: false,
+            })
+            .collect();
+        Ok(outcomes(ids, self.execute(jobs, &CancelToken::new())))
+    }
+}
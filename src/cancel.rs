@@ -0,0 +1,35 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::TracError;
+
+/// Asks a long-running operation to stop. Clones share the same flag, so one
+/// can be handed to a Ctrl-C handler or UI thread while the operation holds
+/// another. Operations check it between batches, never in the middle of a
+/// request, and say in their documentation what is left when they stop.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn check(&self) -> Result<(), TracError> {
+        if self.is_cancelled() {
+            Err(TracError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,586 @@
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::time::{Duration, SystemTime};
+
+use xml::reader::{EventReader, ParserConfig, XmlEvent};
+use xmlrpc::{Fault, Request, Value};
+
+use crate::executor;
+use crate::{scalar_to_string, time, CancelToken, Trac, TracError, TracTicket};
+
+/// One field change from `ticket.changeLog`. Comments appear as changes to
+/// the `comment` field, with the comment number as the old value.
+#[derive(Debug, Clone)]
+pub struct TracChange {
+    pub time: SystemTime,
+    pub author: String,
+    pub field: String,
+    pub old_value: String,
+    pub new_value: String,
+    pub permanent: bool,
+}
+
+impl TracChange {
+    pub(crate) fn from_value(entry: &Value) -> Option<Self> {
+        let text = |i: usize| entry.get(i).and_then(scalar_to_string).unwrap_or_default();
+        Some(TracChange {
+            time: time::from_datetime(&entry.get(0)?.as_datetime()?),
+            author: text(1),
+            field: text(2),
+            old_value: text(3),
+            new_value: text(4),
+            permanent: match entry.get(5) {
+                Some(Value::Bool(b)) => *b,
+                Some(Value::Int(i)) => *i != 0,
+                _ => true,
+            },
+        })
+    }
+}
+
+impl TracTicket {
+    pub fn changelog(&self, trac: &Trac) -> Result<Vec<TracChange>, TracError> {
+        let xmlrpc_req = Request::new("ticket.changeLog").arg(self.id);
+
+        match trac.call(&xmlrpc_req) {
+            Ok(r) => match r.as_array() {
+                Some(entries) => Ok(entries.iter().filter_map(TracChange::from_value).collect()),
+                None => Ok(vec![]),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The changes made at exactly `when`, i.e. one changelog entry's worth,
+    /// which the server looks up without sending the rest of the log.
+    pub fn changelog_at(
+        &self,
+        when: SystemTime,
+        trac: &Trac,
+    ) -> Result<Vec<TracChange>, TracError> {
+        let xmlrpc_req = Request::new("ticket.changeLog")
+            .arg(self.id)
+            .arg(Value::DateTime(time::to_datetime(when)));
+
+        match trac.call(&xmlrpc_req) {
+            Ok(r) => match r.as_array() {
+                Some(entries) => Ok(entries.iter().filter_map(TracChange::from_value).collect()),
+                None => Ok(vec![]),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Reads the changelog as it arrives, one change at a time, instead of
+    /// parsing the whole response first. The server still sends the full
+    /// log; only the client side is incremental.
+    pub fn changelog_stream(&self, trac: &Trac) -> Result<ChangelogStream, TracError> {
+        let response = trac.send(&Request::new("ticket.changeLog").arg(self.id))?;
+        Ok(ChangelogStream::new(response))
+    }
+
+    /// The changes made from `from` up to, but not including, `to`. The
+    /// changelog is in time order, so reading stops at `to`.
+    pub fn changelog_between(
+        &self,
+        from: SystemTime,
+        to: SystemTime,
+        trac: &Trac,
+    ) -> Result<Vec<TracChange>, TracError> {
+        let mut changes = vec![];
+        for change in self.changelog_stream(trac)? {
+            let change = change?;
+            if change.time >= to {
+                break;
+            }
+            if change.time >= from {
+                changes.push(change);
+            }
+        }
+        Ok(changes)
+    }
+}
+
+/// The changes from `TracTicket::changelog_stream`, oldest first.
+pub struct ChangelogStream {
+    reader: ValueReader<Box<dyn Read>>,
+    started: bool,
+    done: bool,
+    cancel: Option<CancelToken>,
+}
+
+impl ChangelogStream {
+    fn new<R: Read + 'static>(source: R) -> Self {
+        Self {
+            reader: ValueReader::new(Box::new(source)),
+            started: false,
+            done: false,
+            cancel: None,
+        }
+    }
+
+    /// Ends the stream with `TracError::Cancelled` once `cancel` is set;
+    /// the changes already read are unaffected.
+    pub fn cancel_on(mut self, cancel: &CancelToken) -> Self {
+        self.cancel = Some(cancel.clone());
+        self
+    }
+
+    /// Groups the changes into pages, each covering up to `window` from the
+    /// time of its first change.
+    pub fn windows(self, window: Duration) -> ChangelogWindows {
+        ChangelogWindows {
+            stream: self,
+            window,
+            pending: None,
+        }
+    }
+
+    // Skips ahead to the entries of the result array, or reads the fault.
+    fn start(&mut self) -> Result<bool, TracError> {
+        loop {
+            match self.reader.next()? {
+                XmlEvent::StartElement { name, .. } if name.local_name == "fault" => {
+                    self.reader.expect_start("value")?;
+                    let fault = self.reader.read_value()?;
+                    return Err(match Fault::from_value(&fault) {
+                        Some(fault) => fault.into(),
+                        None => TracError::invalid_response("ticket.changeLog"),
+                    });
+                }
+                XmlEvent::StartElement { name, .. } if name.local_name == "data" => {
+                    return Ok(true)
+                }
+                XmlEvent::StartElement { .. } => continue,
+                _ => return Ok(false),
+            }
+        }
+    }
+
+    fn read_change(&mut self) -> Result<Option<TracChange>, TracError> {
+        if !self.started {
+            self.started = true;
+            if !self.start()? {
+                return Ok(None);
+            }
+        }
+        match self.reader.next()? {
+            XmlEvent::StartElement { name, .. } if name.local_name == "value" => {
+                let entry = self.reader.read_value()?;
+                match TracChange::from_value(&entry) {
+                    Some(change) => Ok(Some(change)),
+                    None => Err(TracError::invalid_response("ticket.changeLog")),
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+impl Iterator for ChangelogStream {
+    type Item = Result<TracChange, TracError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        if let Some(cancel) = &self.cancel {
+            if let Err(e) = cancel.check() {
+                self.done = true;
+                return Some(Err(e));
+            }
+        }
+        let change = self.read_change().transpose();
+        if !matches!(change, Some(Ok(_))) {
+            self.done = true;
+        }
+        change
+    }
+}
+
+/// Pages of changes from `ChangelogStream::windows`.
+pub struct ChangelogWindows {
+    stream: ChangelogStream,
+    window: Duration,
+    pending: Option<TracChange>,
+}
+
+impl Iterator for ChangelogWindows {
+    type Item = Result<Vec<TracChange>, TracError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let first = match self.pending.take() {
+            Some(change) => change,
+            None => match self.stream.next()? {
+                Ok(change) => change,
+                Err(e) => return Some(Err(e)),
+            },
+        };
+        let end = first.time + self.window;
+        let mut page = vec![first];
+        for change in &mut self.stream {
+            match change {
+                Ok(change) if change.time < end => page.push(change),
+                Ok(change) => {
+                    self.pending = Some(change);
+                    break;
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        Some(Ok(page))
+    }
+}
+
+// Reads XML-RPC values from a stream of XML events, one value at a time.
+struct ValueReader<R: Read> {
+    events: EventReader<R>,
+}
+
+impl<R: Read> ValueReader<R> {
+    fn new(source: R) -> Self {
+        let config = ParserConfig::new().cdata_to_characters(true);
+        Self {
+            events: EventReader::new_with_config(source, config),
+        }
+    }
+
+    fn next(&mut self) -> Result<XmlEvent, TracError> {
+        loop {
+            match self.next_with_whitespace()? {
+                XmlEvent::Whitespace(_) => continue,
+                event => return Ok(event),
+            }
+        }
+    }
+
+    // Like `next`, but keeps whitespace, which is the text of values such
+    // as `<string> </string>`.
+    fn next_with_whitespace(&mut self) -> Result<XmlEvent, TracError> {
+        loop {
+            match self.events.next() {
+                Ok(XmlEvent::StartDocument { .. })
+                | Ok(XmlEvent::Comment(_))
+                | Ok(XmlEvent::ProcessingInstruction { .. }) => continue,
+                Ok(event) => return Ok(event),
+                Err(e) => return Err(TracError::InvalidResponse(e.to_string())),
+            }
+        }
+    }
+
+    fn expect_start(&mut self, tag: &str) -> Result<(), TracError> {
+        match self.next()? {
+            XmlEvent::StartElement { name, .. } if name.local_name == tag => Ok(()),
+            other => Err(unexpected(tag, &other)),
+        }
+    }
+
+    fn expect_end(&mut self, tag: &str) -> Result<(), TracError> {
+        match self.next()? {
+            XmlEvent::EndElement { name } if name.local_name == tag => Ok(()),
+            other => Err(unexpected(&format!("/{}", tag), &other)),
+        }
+    }
+
+    // The text up to the end of `tag`, which may be empty.
+    fn text(&mut self, tag: &str) -> Result<String, TracError> {
+        match self.next_with_whitespace()? {
+            XmlEvent::Characters(text) | XmlEvent::Whitespace(text) => {
+                self.expect_end(tag)?;
+                Ok(text)
+            }
+            XmlEvent::EndElement { name } if name.local_name == tag => Ok(String::new()),
+            other => Err(unexpected(tag, &other)),
+        }
+    }
+
+    // Reads the rest of a value whose `<value>` has just been read.
+    // Whitespace before a type tag is layout, but on its own it is the
+    // value of a bare string.
+    fn read_value(&mut self) -> Result<Value, TracError> {
+        let mut space = String::new();
+        let tag = loop {
+            match self.next_with_whitespace()? {
+                XmlEvent::Whitespace(text) => space = text,
+                XmlEvent::EndElement { .. } => return Ok(Value::String(space)),
+                XmlEvent::Characters(text) => {
+                    self.expect_end("value")?;
+                    return Ok(Value::String(text));
+                }
+                XmlEvent::StartElement { name, .. } => break name.local_name,
+                other => return Err(unexpected("value", &other)),
+            }
+        };
+        let invalid = |text: &str| TracError::InvalidResponse(format!("invalid {}: {}", tag, text));
+
+        let value = match tag.as_str() {
+            "array" => {
+                self.expect_start("data")?;
+                let mut items = vec![];
+                loop {
+                    match self.next()? {
+                        XmlEvent::StartElement { name, .. } if name.local_name == "value" => {
+                            items.push(self.read_value()?)
+                        }
+                        XmlEvent::EndElement { name } if name.local_name == "data" => break,
+                        other => return Err(unexpected("value", &other)),
+                    }
+                }
+                self.expect_end("array")?;
+                Value::Array(items)
+            }
+            "struct" => {
+                let mut members = BTreeMap::new();
+                loop {
+                    match self.next()? {
+                        XmlEvent::StartElement { name, .. } if name.local_name == "member" => {
+                            self.expect_start("name")?;
+                            let name = self.text("name")?;
+                            self.expect_start("value")?;
+                            members.insert(name, self.read_value()?);
+                            self.expect_end("member")?;
+                        }
+                        XmlEvent::EndElement { name } if name.local_name == "struct" => break,
+                        other => return Err(unexpected("member", &other)),
+                    }
+                }
+                Value::Struct(members)
+            }
+            "nil" => {
+                self.expect_end("nil")?;
+                Value::Nil
+            }
+            "string" => Value::String(self.text(&tag)?),
+            "i4" | "int" => {
+                let text = self.text(&tag)?;
+                Value::Int(text.trim().parse().map_err(|_| invalid(&text))?)
+            }
+            "i8" => {
+                let text = self.text(&tag)?;
+                Value::Int64(text.trim().parse().map_err(|_| invalid(&text))?)
+            }
+            "boolean" => match self.text(&tag)?.trim() {
+                "0" => Value::Bool(false),
+                "1" => Value::Bool(true),
+                text => return Err(invalid(text)),
+            },
+            "double" => {
+                let text = self.text(&tag)?;
+                Value::Double(text.trim().parse().map_err(|_| invalid(&text))?)
+            }
+            "dateTime.iso8601" => {
+                let text = self.text(&tag)?;
+                Value::DateTime(iso8601::datetime(text.trim()).map_err(|_| invalid(&text))?)
+            }
+            // Changelogs hold no binary data, so base64 is not decoded.
+            _ => return Err(invalid("unsupported type")),
+        };
+        self.expect_end("value")?;
+        Ok(value)
+    }
+}
+
+fn unexpected(expected: &str, found: &XmlEvent) -> TracError {
+    TracError::InvalidResponse(format!("expected <{}>, found {:?}", expected, found))
+}
+
+impl Trac {
+    /// Fetches the changelogs of `tickets` through multicall, in order.
+    pub(crate) fn changelogs(
+        &self,
+        tickets: &[TracTicket],
+    ) -> Result<Vec<Vec<TracChange>>, TracError> {
+        let jobs = tickets
+            .iter()
+            .map(|t| {
+                executor::call(Request::new("ticket.changeLog").arg(t.id), |log| {
+                    Ok(log
+                        .as_array()
+                        .map(|log| log.iter().filter_map(TracChange::from_value).collect())
+                        .unwrap_or_default())
+                })
+            })
+            .collect();
+        self.execute(jobs, &CancelToken::new())
+            .into_iter()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A changelog entry at `minute` past midnight, with the author given
+    // as a bare value so both string forms are covered.
+    fn entry(minute: u32, field: &str, new_value: &str) -> String {
+        format!(
+            "<value><array><data>\
+             <value><dateTime.iso8601>20200101T00:{:02}:00</dateTime.iso8601></value>\
+             <value>alice</value>\
+             <value><string>{}</string></value>\
+             <value><string></string></value>\
+             <value><string>{}</string></value>\
+             <value><boolean>1</boolean></value>\
+             </data></array></value>",
+            minute, field, new_value
+        )
+    }
+
+    fn response(entries: &[String]) -> String {
+        format!(
+            "<?xml version='1.0'?>\n<methodResponse>\n<params>\n<param>\n\
+             <value><array><data>\n{}\n</data></array></value>\n\
+             </param>\n</params>\n</methodResponse>\n",
+            entries.join("\n")
+        )
+    }
+
+    fn stream(xml: String) -> ChangelogStream {
+        ChangelogStream::new(std::io::Cursor::new(xml.into_bytes()))
+    }
+
+    fn value(xml: &str) -> Result<Value, TracError> {
+        let mut reader = ValueReader::new(xml.as_bytes());
+        reader.expect_start("value")?;
+        reader.read_value()
+    }
+
+    fn minute(change: &TracChange) -> u64 {
+        time::to_unix(change.time) as u64 % 3600 / 60
+    }
+
+    #[test]
+    fn streams_changes_in_order() {
+        let xml = response(&[entry(1, "status", "assigned"), entry(2, "comment", "Done")]);
+        let changes: Vec<TracChange> = stream(xml).collect::<Result<_, _>>().unwrap();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].author, "alice");
+        assert_eq!(changes[0].field, "status");
+        assert_eq!(changes[0].old_value, "");
+        assert_eq!(changes[1].new_value, "Done");
+        assert!(changes[1].permanent);
+        assert_eq!(minute(&changes[1]), 2);
+    }
+
+    #[test]
+    fn handles_an_empty_changelog() {
+        assert_eq!(stream(response(&[])).count(), 0);
+    }
+
+    #[test]
+    fn reports_faults() {
+        let xml = "<?xml version='1.0'?><methodResponse><fault><value><struct>\
+                   <member><name>faultCode</name><value><int>404</int></value></member>\
+                   <member><name>faultString</name><value><string>Ticket 9 does not exist.</string></value></member>\
+                   </struct></value></fault></methodResponse>";
+        let results: Vec<_> = stream(xml.to_string()).collect();
+        assert_eq!(results.len(), 1);
+        assert!(matches!(results[0], Err(TracError::NoSuchTicket(9))));
+    }
+
+    #[test]
+    fn reads_nested_values() {
+        let parsed = value(
+            "<value><struct>\
+             <member><name>list</name><value><array><data>\
+             <value><i4>1</i4></value><value><i8>5000000000</i8></value>\
+             <value><double>1.5</double></value><value><nil/></value>\
+             </data></array></value></member>\
+             <member><name>inner</name><value><struct>\
+             <member><name>ok</name><value><boolean>0</boolean></value></member>\
+             </struct></value></member>\
+             </struct></value>",
+        )
+        .unwrap();
+        let mut inner = BTreeMap::new();
+        inner.insert("ok".to_string(), Value::Bool(false));
+        let mut outer = BTreeMap::new();
+        outer.insert(
+            "list".to_string(),
+            Value::Array(vec![
+                Value::Int(1),
+                Value::Int64(5_000_000_000),
+                Value::Double(1.5),
+                Value::Nil,
+            ]),
+        );
+        outer.insert("inner".to_string(), Value::Struct(inner));
+        assert_eq!(parsed, Value::Struct(outer));
+    }
+
+    #[test]
+    fn reads_string_forms() {
+        assert_eq!(value("<value/>"), Ok(Value::String(String::new())));
+        assert_eq!(value("<value></value>"), Ok(Value::String(String::new())));
+        assert_eq!(
+            value("<value>text</value>"),
+            Ok(Value::String("text".into()))
+        );
+        assert_eq!(
+            value("<value><string/></value>"),
+            Ok(Value::String(String::new()))
+        );
+        assert_eq!(
+            value("<value><string>a &amp; b &lt;c&gt; <![CDATA[<d> & e]]></string></value>"),
+            Ok(Value::String("a & b <c> <d> & e".into()))
+        );
+        assert_eq!(
+            value("<value><string>  </string></value>"),
+            Ok(Value::String("  ".into()))
+        );
+        assert_eq!(value("<value> </value>"), Ok(Value::String(" ".into())));
+        assert_eq!(value("<value>\n  <i4>3</i4>\n</value>"), Ok(Value::Int(3)));
+    }
+
+    #[test]
+    fn rejects_malformed_values() {
+        assert!(matches!(
+            value("<value><int>x</int></value>"),
+            Err(TracError::InvalidResponse(_))
+        ));
+        assert!(matches!(
+            value("<value><boolean>2</boolean></value>"),
+            Err(TracError::InvalidResponse(_))
+        ));
+        assert!(matches!(
+            value("<value><array><data><value>1</value>"),
+            Err(TracError::InvalidResponse(_))
+        ));
+    }
+
+    #[test]
+    fn pages_by_window() {
+        let xml = response(&[
+            entry(0, "status", "a"),
+            entry(4, "status", "b"),
+            entry(5, "status", "c"),
+            entry(9, "status", "d"),
+            entry(20, "status", "e"),
+        ]);
+        let pages: Vec<Vec<u64>> = stream(xml)
+            .windows(Duration::from_secs(5 * 60))
+            .map(|page| page.unwrap().iter().map(minute).collect())
+            .collect();
+        // Each page runs from its first change, so 5 starts a new one.
+        assert_eq!(pages, [vec![0, 4], vec![5, 9], vec![20]]);
+    }
+
+    #[test]
+    fn stops_when_cancelled() {
+        let cancel = CancelToken::new();
+        let xml = response(&[
+            entry(1, "status", "a"),
+            entry(2, "status", "b"),
+            entry(3, "status", "c"),
+        ]);
+        let mut changes = stream(xml).cancel_on(&cancel);
+        assert!(matches!(changes.next(), Some(Ok(_))));
+        cancel.cancel();
+        assert_eq!(
+            changes.next().map(|r| r.err()),
+            Some(Some(TracError::Cancelled))
+        );
+        assert!(changes.next().is_none());
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::BTreeMap;
+
+use crate::{Trac, TracTicket};
+
+// How much of the description a chat message shows.
+const DESCRIPTION_LENGTH: usize = 280;
+
+pub(crate) fn default_status_emoji() -> BTreeMap<String, String> {
+    [
+        ("new", ":new:"),
+        ("assigned", ":bust_in_silhouette:"),
+        ("accepted", ":hammer_and_wrench:"),
+        ("reopened", ":repeat:"),
+        ("closed", ":white_check_mark:"),
+    ]
+    .iter()
+    .map(|(status, emoji)| (status.to_string(), emoji.to_string()))
+    .collect()
+}
+
+fn escape_slack(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+// Slack has no escape for `*`, which would end the bold early, so inside it
+// asterisks become the lookalike U+2217.
+fn escape_slack_bold(text: &str) -> String {
+    escape_slack(text).replace('*', "\u{2217}")
+}
+
+pub(crate) fn escape_markdown(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\`*_[]()#<>~|".contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn truncate(text: &str, max: usize) -> String {
+    let text = text.trim();
+    match text.char_indices().nth(max) {
+        Some((end, _)) => format!("{}…", text[..end].trim_end()),
+        None => text.to_string(),
+    }
+}
+
+impl TracTicket {
+    fn chat_message(
+        &self,
+        trac: &Trac,
+        link: String,
+        summary: String,
+        escape: fn(&str) -> String,
+    ) -> String {
+        let mut heading = String::new();
+        if let Some(emoji) = trac.config.status_emoji.get(&self.status) {
+            heading.push_str(emoji);
+            heading.push(' ');
+        }
+        heading.push_str(&format!("{} {}", link, summary));
+
+        let mut details = vec![escape(&self.status)];
+        if !self.owner.is_empty() {
+            details.push(format!("owner: {}", escape(&self.owner)));
+        }
+        if !self.milestone.is_empty() {
+            details.push(format!("milestone: {}", escape(&self.milestone)));
+        }
+
+        let mut message = format!("{}\n{}", heading, details.join(" · "));
+        let description = truncate(&self.description, DESCRIPTION_LENGTH);
+        for line in description.lines() {
+            message.push_str(&format!("\n>{}", escape(line)));
+        }
+        message
+    }
+
+    /// Formats the ticket as a Slack `mrkdwn` message: status emoji, linked
+    /// id and summary, then the start of the description as a quote.
+    pub fn fmt_slack(&self, trac: &Trac) -> String {
+        let link = format!("<{}|#{}>", TracTicket::url(self.id, trac), self.id);
+        let summary = format!("*{}*", escape_slack_bold(&self.summary));
+        self.chat_message(trac, link, summary, escape_slack)
+    }
+
+    /// Formats the ticket like `fmt_slack`, in Mattermost's Markdown.
+    pub fn fmt_mattermost(&self, trac: &Trac) -> String {
+        let link = format!("[#{}]({})", self.id, TracTicket::url(self.id, trac));
+        let summary = format!("**{}**", escape_markdown(&self.summary));
+        self.chat_message(trac, link, summary, escape_markdown)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn ticket(status: &str, summary: &str, description: &str) -> TracTicket {
+        testing::ticket(
+            7,
+            &[
+                ("status", status),
+                ("summary", summary),
+                ("owner", "bob"),
+                ("milestone", ""),
+                ("description", description),
+            ],
+        )
+    }
+
+    #[test]
+    fn escapes_text() {
+        assert_eq!(
+            escape_slack("a < b && c > d"),
+            "a &lt; b &amp;&amp; c &gt; d"
+        );
+        assert_eq!(escape_slack_bold("*nix <b>"), "\u{2217}nix &lt;b&gt;");
+        assert_eq!(escape_markdown(r"a*b_[c](d)|\"), r"a\*b\_\[c\]\(d\)\|\\");
+    }
+
+    #[test]
+    fn truncates_on_characters() {
+        assert_eq!(truncate("  short  ", 10), "short");
+        assert_eq!(truncate("héllo wörld", 6), "héllo…");
+        assert_eq!(truncate("日本語のテキスト", 3), "日本語…");
+        assert_eq!(truncate("🦀🦀🦀", 3), "🦀🦀🦀");
+    }
+
+    #[test]
+    fn formats_slack_messages() {
+        let trac = testing::offline(testing::config());
+        assert_eq!(
+            ticket("new", "Fix *all* <things>", "First\nsecond").fmt_slack(&trac),
+            ":new: <https://127.0.0.1:1/trac/ticket/7|#7> \
+             *Fix \u{2217}all\u{2217} &lt;things&gt;*\n\
+             new · owner: bob\n>First\n>second"
+        );
+    }
+
+    #[test]
+    fn formats_mattermost_messages() {
+        let trac = testing::offline(testing::config());
+        assert_eq!(
+            ticket("accepted", "Fix *all*", "").fmt_mattermost(&trac),
+            ":hammer_and_wrench: [#7](https://127.0.0.1:1/trac/ticket/7) \
+             **Fix \\*all\\***\naccepted · owner: bob"
+        );
+    }
+
+    #[test]
+    fn leaves_out_unknown_status_emoji() {
+        let mut config = testing::config();
+        config
+            .status_emoji
+            .insert("testing".to_string(), ":test_tube:".to_string());
+        config.status_emoji.remove("new");
+        let trac = testing::offline(config);
+        assert!(ticket("testing", "x", "")
+            .fmt_slack(&trac)
+            .starts_with(":test_tube: <"));
+        assert!(ticket("new", "x", "").fmt_slack(&trac).starts_with("<"));
+        assert!(ticket("review", "x", "").fmt_slack(&trac).starts_with("<"));
+    }
+}
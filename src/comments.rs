@@ -0,0 +1,152 @@
+use std::time::SystemTime;
+
+use regex::Regex;
+
+use crate::{Trac, TracChange, TracError, TracTicket};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TracComment {
+    pub author: String,
+    pub time: SystemTime,
+    /// The number shown as `comment:N` in the web UI.
+    pub number: u32,
+    pub text: String,
+    /// The comment this one replies to, if any.
+    pub replying_to: Option<u32>,
+}
+
+// Trac stores a comment's number as the old value of its `comment` change,
+// prefixed with the number it replies to: "4", or "2.4" for a reply to 2.
+fn parse_comment_number(value: &str) -> (Option<u32>, Option<u32>) {
+    match value.rsplit_once('.') {
+        Some((parent, number)) => (number.parse().ok(), parent.parse().ok()),
+        None => (value.parse().ok(), None),
+    }
+}
+
+/// Picks the comments out of a changelog. Every change has a `comment`
+/// entry, empty if nothing was said, so changes without text still take up
+/// a number but are left out.
+pub(crate) fn comments_from_changelog(changes: &[TracChange]) -> Vec<TracComment> {
+    let mut comments = Vec::new();
+    let mut next_number = 1;
+
+    for change in changes.iter().filter(|c| c.field == "comment") {
+        let (number, replying_to) = parse_comment_number(&change.old_value);
+        let number = number.unwrap_or(next_number);
+        next_number = number + 1;
+
+        if !change.new_value.trim().is_empty() {
+            comments.push(TracComment {
+                author: change.author.to_owned(),
+                time: change.time,
+                number,
+                text: change.new_value.to_owned(),
+                replying_to,
+            });
+        }
+    }
+
+    comments
+}
+
+impl TracTicket {
+    pub fn comments(&self, trac: &Trac) -> Result<Vec<TracComment>, TracError> {
+        Ok(comments_from_changelog(&self.changelog(trac)?))
+    }
+}
+
+/// Narrows down a list of comments, e.g.
+/// `ticket.comments(&trac)?.by("buildbot").since(yesterday)`.
+pub trait CommentFilters: Sized {
+    fn by(self, author: &str) -> Self;
+    /// Keeps comments made at or after `time`.
+    fn since(self, time: SystemTime) -> Self;
+    fn matching(self, pattern: &Regex) -> Self;
+    fn containing(self, marker: &str) -> Self;
+}
+
+impl CommentFilters for Vec<TracComment> {
+    fn by(mut self, author: &str) -> Self {
+        self.retain(|c| c.author == author);
+        self
+    }
+
+    fn since(mut self, time: SystemTime) -> Self {
+        self.retain(|c| c.time >= time);
+        self
+    }
+
+    fn matching(mut self, pattern: &Regex) -> Self {
+        self.retain(|c| pattern.is_match(&c.text));
+        self
+    }
+
+    fn containing(mut self, marker: &str) -> Self {
+        self.retain(|c| c.text.contains(marker));
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn change(minute: u64, author: &str, field: &str, old: &str, new: &str) -> TracChange {
+        TracChange {
+            time: UNIX_EPOCH + Duration::from_secs(minute * 60),
+            author: author.to_string(),
+            field: field.to_string(),
+            old_value: old.to_string(),
+            new_value: new.to_string(),
+            permanent: true,
+        }
+    }
+
+    fn changelog() -> Vec<TracChange> {
+        vec![
+            change(1, "alice", "status", "new", "assigned"),
+            change(1, "alice", "comment", "1", "Taking this."),
+            change(2, "bob", "comment", "2", ""),
+            change(3, "buildbot", "comment", "1.3", "Build failed."),
+            // Older servers leave the number out.
+            change(4, "alice", "comment", "", "Fixed in r12."),
+        ]
+    }
+
+    #[test]
+    fn numbers_comments_and_replies() {
+        let comments = comments_from_changelog(&changelog());
+        let summary: Vec<(u32, Option<u32>, &str)> = comments
+            .iter()
+            .map(|c| (c.number, c.replying_to, c.text.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (1, None, "Taking this."),
+                (3, Some(1), "Build failed."),
+                (4, None, "Fixed in r12."),
+            ]
+        );
+    }
+
+    #[test]
+    fn filters_comments() {
+        let comments = comments_from_changelog(&changelog());
+        assert_eq!(comments.clone().by("alice").len(), 2);
+        assert_eq!(
+            comments
+                .clone()
+                .since(UNIX_EPOCH + Duration::from_secs(180))
+                .len(),
+            2
+        );
+        assert_eq!(comments.clone().containing("r12").len(), 1);
+        assert_eq!(
+            comments.matching(&Regex::new("^Build").unwrap())[0].author,
+            "buildbot"
+        );
+    }
+}
@@ -0,0 +1,435 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::{
+    chat, CallOptions, CallPriority, HoursTracking, PoolOptions, RetryPolicy, RpcEndpoint,
+    SlaPolicy, TicketTemplate, TlsOptions, TlsVersion, TracConfig, TracError, TracReviewer,
+    TracUser, TransitionGuard, Webhook, WorkflowProfile,
+};
+
+/// A parsed INI document, using the same layout as `trac.ini`: `[section]`
+/// headers followed by `key = value` lines, with `#` or `;` comments.
+/// Indented lines continue the previous value on a new line.
+#[derive(Debug, Default)]
+pub(crate) struct Ini {
+    sections: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl Ini {
+    pub(crate) fn parse(text: &str) -> Result<Self, TracError> {
+        let mut ini = Ini::default();
+        let mut current: Option<String> = None;
+        let mut last_key: Option<String> = None;
+
+        for (n, raw) in text.lines().enumerate() {
+            let line = raw.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            if raw.starts_with(char::is_whitespace) {
+                if let (Some(section), Some(key)) = (&current, &last_key) {
+                    if let Some(value) = ini.sections.get_mut(section).and_then(|s| s.get_mut(key))
+                    {
+                        value.push('\n');
+                        value.push_str(line);
+                        continue;
+                    }
+                }
+            }
+
+            if line.starts_with('[') && line.ends_with(']') {
+                let name = line[1..line.len() - 1].trim().to_string();
+                ini.sections.entry(name.clone()).or_default();
+                current = Some(name);
+                last_key = None;
+                continue;
+            }
+
+            let (key, value) = match (line.find('='), &current) {
+                (Some(i), Some(_)) => (line[..i].trim(), line[i + 1..].trim()),
+                _ => {
+                    return Err(TracError::Config(format!(
+                        "invalid config line {}: {}",
+                        n + 1,
+                        raw
+                    )))
+                }
+            };
+
+            if let Some(section) = current.as_ref().and_then(|s| ini.sections.get_mut(s)) {
+                section.insert(key.to_string(), value.to_string());
+                last_key = Some(key.to_string());
+            }
+        }
+
+        Ok(ini)
+    }
+
+    pub(crate) fn sections(&self) -> impl Iterator<Item = (&String, &BTreeMap<String, String>)> {
+        self.sections.iter()
+    }
+
+    pub(crate) fn section(&self, name: &str) -> Option<&BTreeMap<String, String>> {
+        self.sections.get(name)
+    }
+
+    pub(crate) fn get(&self, section: &str, key: &str) -> Option<&str> {
+        self.section(section)
+            .and_then(|s| s.get(key))
+            .map(|v| v.as_str())
+    }
+
+    pub(crate) fn get_parsed<T: FromStr>(
+        &self,
+        section: &str,
+        key: &str,
+    ) -> Result<Option<T>, TracError> {
+        match self.get(section, key) {
+            Some(value) => match value.parse() {
+                Ok(v) => Ok(Some(v)),
+                Err(_) => Err(TracError::Config(format!(
+                    "invalid value for [{}] {}: {}",
+                    section, key, value
+                ))),
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn require(&self, section: &str, key: &str) -> Result<String, TracError> {
+        match self.get(section, key) {
+            Some(v) => Ok(v.to_string()),
+            None => Err(TracError::Config(format!(
+                "config is missing [{}] {}",
+                section, key
+            ))),
+        }
+    }
+}
+
+fn optional_secs(secs: u64) -> Option<Duration> {
+    if secs == 0 {
+        None
+    } else {
+        Some(Duration::from_secs(secs))
+    }
+}
+
+impl TracConfig {
+    pub fn new(user: Rc<TracUser>, host: &str, path: &str) -> Self {
+        Self {
+            user,
+            host: host.to_string(),
+            path: path.to_string(),
+            workflow: WorkflowProfile::default(),
+            templates: BTreeMap::new(),
+            parent_field: "parent".to_string(),
+            hours_tracking: HoursTracking::Plugin,
+            fields_ttl: Duration::from_secs(300),
+            compression: true,
+            pool: PoolOptions::default(),
+            tls: TlsOptions::default(),
+            call: CallOptions::default(),
+            endpoint: RpcEndpoint::default(),
+            batch_size: 100,
+            concurrency: 100,
+            auto_create: false,
+            attachment_max_size: None,
+            attachment_check_content: false,
+            sla: vec![],
+            status_emoji: chat::default_status_emoji(),
+            closed_statuses: vec!["closed".to_string()],
+            reviewers: vec![],
+            guards: vec![],
+            webhooks: vec![],
+            #[cfg(feature = "email")]
+            email: None,
+        }
+    }
+
+    /// The configured reviewer with this name or alias.
+    pub fn find_reviewer(&self, name: &str) -> Option<&TracReviewer> {
+        self.reviewers
+            .iter()
+            .find(|r| r.name == name || r.aliases.iter().any(|a| a == name))
+    }
+
+    pub fn add_template(&mut self, template: TicketTemplate) {
+        self.templates.insert(template.name.clone(), template);
+    }
+
+    /// Loads a config file. The `[trac]` section holds `host`, `path`,
+    /// `username` and `password`, plus the optional settings below; the
+    /// `[workflow]`, `[template:<name>]`, `[sla:<name>]`, `[guard:<name>]`
+    /// and `[webhook:<name>]` sections are described on `WorkflowProfile`,
+    /// `TicketTemplate`, `SlaPolicy`, `TransitionGuard` and `Webhook`, and
+    /// with the `email` feature, `[email]` on `EmailSettings`. Each
+    /// `[reviewer:<name>]` section gives a reviewer's `email` and
+    /// comma-separated `aliases`.
+    ///
+    /// Optional `[trac]` settings: `parent_field`, `hours_tracking`,
+    /// `fields_ttl` (seconds), `compression` (true/false), `rpc_path` (e.g.
+    /// `xmlrpc`, or `auto` to detect it; `login/xmlrpc` by default),
+    /// `batch_size` (calls per multicall), `concurrency` (tickets a bulk
+    /// operation works on at once), `auto_create` (true/false).
+    ///
+    /// The `[status_emoji]` section maps statuses to the emoji used in chat
+    /// messages, such as `closed = :white_check_mark:`; an empty value
+    /// removes a default.
+    ///
+    /// `[status] closed` lists the statuses that count as closed,
+    /// comma-separated; just `closed` by default.
+    ///
+    /// `[attachment] max_size` is read as in `trac.ini`, negative meaning no
+    /// limit, and `check_content` (true/false) turns on the content check;
+    /// see `attachment_max_size` and `attachment_check_content`.
+    ///
+    /// The optional `[pool]` section sets `max_idle_per_host`, and
+    /// `idle_timeout` and `tcp_keepalive` in seconds, 0 meaning none.
+    ///
+    /// The optional `[tls]` section sets `min_version` (`1.0` to `1.3`) and
+    /// comma-separated `alpn` protocols; see `TlsOptions`.
+    ///
+    /// The optional `[call]` section sets the `timeout` in seconds, 0
+    /// meaning none, `retries` with `retry_backoff`, in seconds (1 by
+    /// default), `retry_writes` (true/false) and `priority` (`normal` or
+    /// `background`); see `RetryPolicy` and `CallPriority`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, TracError> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::from_ini(&text),
+            Err(e) => Err(TracError::Config(format!("{}", e))),
+        }
+    }
+
+    pub fn from_ini(text: &str) -> Result<Self, TracError> {
+        let ini = Ini::parse(text)?;
+
+        let user = TracUser {
+            username: ini.require("trac", "username")?,
+            password: ini.require("trac", "password")?,
+        };
+        let mut config = Self::new(
+            Rc::new(user),
+            &ini.require("trac", "host")?,
+            ini.get("trac", "path").unwrap_or("/"),
+        );
+
+        if let Some(field) = ini.get("trac", "parent_field") {
+            config.parent_field = field.to_string();
+        }
+
+        if let Some(mode) = ini.get("trac", "hours_tracking") {
+            config.hours_tracking = HoursTracking::from_name(mode)?;
+        }
+
+        if let Some(secs) = ini.get_parsed("trac", "fields_ttl")? {
+            config.fields_ttl = Duration::from_secs(secs);
+        }
+
+        if let Some(enabled) = ini.get_parsed("trac", "compression")? {
+            config.compression = enabled;
+        }
+
+        if let Some(path) = ini.get("trac", "rpc_path") {
+            config.endpoint = RpcEndpoint::from_setting(path);
+        }
+
+        if let Some(size) = ini.get_parsed("trac", "batch_size")? {
+            config.batch_size = size;
+        }
+
+        if let Some(concurrency) = ini.get_parsed("trac", "concurrency")? {
+            config.concurrency = concurrency;
+        }
+
+        if let Some(enabled) = ini.get_parsed("trac", "auto_create")? {
+            config.auto_create = enabled;
+        }
+
+        if let Some(max) = ini.get_parsed::<i64>("attachment", "max_size")? {
+            config.attachment_max_size = if max < 0 { None } else { Some(max as u64) };
+        }
+        if let Some(check) = ini.get_parsed("attachment", "check_content")? {
+            config.attachment_check_content = check;
+        }
+
+        if let Some(max) = ini.get_parsed("pool", "max_idle_per_host")? {
+            config.pool.max_idle_per_host = max;
+        }
+        if let Some(secs) = ini.get_parsed("pool", "idle_timeout")? {
+            config.pool.idle_timeout = optional_secs(secs);
+        }
+        if let Some(secs) = ini.get_parsed("pool", "tcp_keepalive")? {
+            config.pool.tcp_keepalive = optional_secs(secs);
+        }
+
+        if let Some(version) = ini.get("tls", "min_version") {
+            config.tls.min_version = Some(TlsVersion::from_name(version)?);
+        }
+        if let Some(protocols) = ini.get("tls", "alpn") {
+            config.tls.alpn = protocols
+                .split(',')
+                .map(str::trim)
+                .filter(|p| !p.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+
+        if let Some(secs) = ini.get_parsed("call", "timeout")? {
+            config.call.timeout = optional_secs(secs);
+        }
+        if let Some(retries) = ini.get_parsed("call", "retries")? {
+            let backoff = ini.get_parsed("call", "retry_backoff")?.unwrap_or(1);
+            config.call.retry = Some(RetryPolicy::new(retries, Duration::from_secs(backoff)));
+        }
+        if let Some(retry_writes) = ini.get_parsed("call", "retry_writes")? {
+            config.call.retry_writes = retry_writes;
+        }
+        if let Some(priority) = ini.get("call", "priority") {
+            config.call.priority = CallPriority::from_name(priority)?;
+        }
+
+        if let Some(section) = ini.section("status_emoji") {
+            for (status, emoji) in section {
+                if emoji.is_empty() {
+                    config.status_emoji.remove(status);
+                } else {
+                    config
+                        .status_emoji
+                        .insert(status.to_owned(), emoji.to_owned());
+                }
+            }
+        }
+
+        if let Some(statuses) = ini.get("status", "closed") {
+            config.closed_statuses = statuses
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+
+        #[cfg(feature = "email")]
+        {
+            if let Some(section) = ini.section("email") {
+                config.email = Some(crate::EmailSettings::from_section(section)?);
+            }
+        }
+
+        if let Some(section) = ini.section("workflow") {
+            config.workflow = WorkflowProfile::from_section(section)?;
+        }
+
+        for (name, section) in ini.sections() {
+            if let Some(template_name) = name.strip_prefix("template:") {
+                config.add_template(TicketTemplate::from_section(template_name, section));
+            }
+            if let Some(reviewer_name) = name.strip_prefix("reviewer:") {
+                config.reviewers.push(TracReviewer {
+                    name: reviewer_name.to_string(),
+                    aliases: section
+                        .get("aliases")
+                        .map(|a| {
+                            a.split(',')
+                                .map(str::trim)
+                                .filter(|a| !a.is_empty())
+                                .map(str::to_string)
+                                .collect()
+                        })
+                        .unwrap_or_default(),
+                    email: section.get("email").cloned().unwrap_or_default(),
+                });
+            }
+            if let Some(policy_name) = name.strip_prefix("sla:") {
+                config
+                    .sla
+                    .push(SlaPolicy::from_section(policy_name, section)?);
+            }
+            if let Some(guard_name) = name.strip_prefix("guard:") {
+                config
+                    .guards
+                    .push(TransitionGuard::from_section(guard_name, section)?);
+            }
+            if let Some(webhook_name) = name.strip_prefix("webhook:") {
+                config
+                    .webhooks
+                    .push(Webhook::from_section(webhook_name, section)?);
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "\
+# Example
+[trac]
+username = alice
+password = s3cret=ok
+host = trac.example.com
+batch_size = 10
+
+[workflow]
+close = resolve
+
+; a template with a multi-line description
+[template:bug]
+description = Steps:
+  1. open
+  2. click
+";
+
+    #[test]
+    fn parses_sections_and_continuations() {
+        let ini = Ini::parse(CONFIG).unwrap();
+        assert_eq!(ini.get("trac", "password"), Some("s3cret=ok"));
+        assert_eq!(ini.get("workflow", "close"), Some("resolve"));
+        assert_eq!(
+            ini.get("template:bug", "description"),
+            Some("Steps:\n1. open\n2. click")
+        );
+        assert_eq!(ini.get("trac", "missing"), None);
+        assert_eq!(ini.get_parsed::<usize>("trac", "batch_size"), Ok(Some(10)));
+        assert!(ini.get_parsed::<usize>("trac", "host").is_err());
+    }
+
+    #[test]
+    fn rejects_lines_outside_sections_or_without_values() {
+        assert!(matches!(
+            Ini::parse("key = value"),
+            Err(TracError::Config(_))
+        ));
+        assert!(matches!(
+            Ini::parse("[trac]\njust words"),
+            Err(TracError::Config(_))
+        ));
+    }
+
+    #[test]
+    fn reads_a_config() {
+        let config = TracConfig::from_ini(CONFIG).unwrap();
+        assert_eq!(config.user.username, "alice");
+        assert_eq!(config.host, "trac.example.com");
+        assert_eq!(config.path, "/");
+        assert_eq!(config.batch_size, 10);
+        assert_eq!(config.workflow.close.as_deref(), Some("resolve"));
+    }
+
+    #[test]
+    fn requires_credentials_and_host() {
+        let result = TracConfig::from_ini("[trac]\nusername = alice\npassword = x");
+        assert!(
+            matches!(result, Err(TracError::Config(e)) if e == "config is missing [trac] host")
+        );
+    }
+}
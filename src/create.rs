@@ -0,0 +1,93 @@
+use std::collections::BTreeMap;
+
+use xmlrpc::{Request, Value};
+
+use crate::{Trac, TracError, TracTicket};
+
+// Fields the server fills in itself, or which would be wrong to default on
+// a new ticket (e.g. the default resolution).
+const NON_DEFAULTED_FIELDS: &[&str] = &["summary", "description", "status", "resolution"];
+
+/// Collects the fields of a new ticket and submits them with `ticket.create`.
+/// Unless disabled, fields left unset are pre-filled with the defaults the
+/// server reports through `ticket.getTicketFields`.
+#[derive(Debug)]
+pub struct TicketCreateBuilder {
+    summary: String,
+    description: String,
+    attributes: BTreeMap<String, String>,
+    server_defaults: bool,
+    create_missing: Option<bool>,
+}
+
+impl TicketCreateBuilder {
+    pub(crate) fn new(summary: &str) -> Self {
+        Self {
+            summary: summary.to_string(),
+            description: "".to_string(),
+            attributes: BTreeMap::new(),
+            server_defaults: true,
+            create_missing: None,
+        }
+    }
+
+    pub fn description(mut self, text: &str) -> Self {
+        self.description = text.to_string();
+        self
+    }
+
+    pub fn set(mut self, field: &str, value: &str) -> Self {
+        self.attributes.insert(field.to_string(), value.to_string());
+        self
+    }
+
+    pub fn server_defaults(mut self, enabled: bool) -> Self {
+        self.server_defaults = enabled;
+        self
+    }
+
+    /// Overrides `TracConfig::auto_create` for this ticket.
+    pub fn create_missing(mut self, enabled: bool) -> Self {
+        self.create_missing = Some(enabled);
+        self
+    }
+
+    pub fn submit(self, trac: &Trac) -> Result<TracTicket, TracError> {
+        let mut attributes = self.attributes;
+
+        if self.server_defaults {
+            let field_set = trac.ticket_fields()?;
+            for field in &field_set.fields {
+                if NON_DEFAULTED_FIELDS.contains(&field.name.as_str()) {
+                    continue;
+                }
+                if let Some(default) = &field.default {
+                    attributes
+                        .entry(field.name.to_owned())
+                        .or_insert_with(|| default.to_owned());
+                }
+            }
+        }
+
+        if self.create_missing.unwrap_or(trac.config.auto_create) {
+            trac.ensure_all_exist(&attributes)?;
+        }
+
+        let ticket_attributes = attributes
+            .into_iter()
+            .map(|(k, v)| (k, Value::String(v)))
+            .collect();
+        let xmlrpc_req = Request::new("ticket.create")
+            .arg(self.summary)
+            .arg(self.description)
+            .arg(Value::Struct(ticket_attributes));
+
+        match trac.call(&xmlrpc_req) {
+            Ok(r) => match r.as_i32() {
+                Some(id) => trac.get_ticket(id),
+                None => Err(TracError::invalid_response("ticket.create")),
+            },
+            Err(e) => Err(trac.note_error(e)),
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use std::time::{Duration, SystemTime};
+
+use crate::{Trac, TracError, TracTicket};
+
+/// How to recognise that a comment has already been posted, so retried
+/// automation does not post it twice.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DuplicateGuard {
+    /// Skip if the configured user posted the same text within the window.
+    Window(Duration),
+    /// Embed `key` in the comment as a hidden marker and skip if any earlier
+    /// comment carries it.
+    Marker(String),
+}
+
+impl DuplicateGuard {
+    fn marker(key: &str) -> String {
+        format!("{{{{{{#!comment\nidempotency-key: {}\n}}}}}}", key)
+    }
+
+    /// The comment text to post, including the marker if there is one.
+    pub(crate) fn decorate(&self, text: &str) -> String {
+        match self {
+            DuplicateGuard::Window(_) => text.to_string(),
+            DuplicateGuard::Marker(key) => format!("{}\n\n{}", text, Self::marker(key)),
+        }
+    }
+}
+
+impl TracTicket {
+    pub fn has_duplicate_comment(
+        &self,
+        text: &str,
+        guard: &DuplicateGuard,
+        trac: &Trac,
+    ) -> Result<bool, TracError> {
+        self.duplicate_comment_by(text, guard, &trac.config.user.username, trac)
+    }
+
+    // As `has_duplicate_comment`, with the window guard looking for comments
+    // by `author`.
+    pub(crate) fn duplicate_comment_by(
+        &self,
+        text: &str,
+        guard: &DuplicateGuard,
+        author: &str,
+        trac: &Trac,
+    ) -> Result<bool, TracError> {
+        let changes = self.changelog(trac)?;
+        let mut comments = changes.iter().filter(|c| c.field == "comment");
+
+        Ok(match guard {
+            DuplicateGuard::Window(window) => {
+                let since = SystemTime::now() - *window;
+                comments.any(|c| {
+                    c.time >= since && c.author == author && c.new_value.trim() == text.trim()
+                })
+            }
+            DuplicateGuard::Marker(key) => {
+                let marker = DuplicateGuard::marker(key);
+                comments.any(|c| c.new_value.contains(&marker))
+            }
+        })
+    }
+
+    pub fn comment(
+        &self,
+        text: &str,
+        guard: Option<DuplicateGuard>,
+        trac: &Trac,
+    ) -> Result<TracTicket, TracError> {
+        let mut update = self.update_builder().comment(text);
+        if let Some(g) = guard {
+            update = update.dedupe(g);
+        }
+        update.submit(trac)
+    }
+}
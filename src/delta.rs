@@ -0,0 +1,247 @@
+use std::cell::Cell;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::{time, TicketQuery, Trac, TracError, TracTicket};
+
+/// How far before the watermark each sync looks again by default.
+pub const DEFAULT_OVERLAP: Duration = Duration::from_secs(300);
+
+/// Where a `DeltaSync` keeps the time it last synced up to.
+pub trait WatermarkStore {
+    /// The stored watermark; `None` if nothing has been synced yet.
+    fn load(&self) -> Result<Option<SystemTime>, TracError>;
+
+    fn save(&self, watermark: SystemTime) -> Result<(), TracError>;
+}
+
+/// Keeps the watermark in a file, as Unix seconds.
+#[derive(Debug, Clone)]
+pub struct FileWatermark {
+    pub path: PathBuf,
+}
+
+impl FileWatermark {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl WatermarkStore for FileWatermark {
+    fn load(&self) -> Result<Option<SystemTime>, TracError> {
+        let text = match fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(TracError::Io(format!("{}: {}", self.path.display(), e))),
+        };
+        match text.trim().parse() {
+            Ok(seconds) => Ok(Some(time::from_unix(seconds))),
+            Err(_) => Err(TracError::Io(format!(
+                "{}: invalid watermark '{}'",
+                self.path.display(),
+                text.trim()
+            ))),
+        }
+    }
+
+    // Written to a temporary file first, so a crash never leaves a
+    // truncated watermark behind.
+    fn save(&self, watermark: SystemTime) -> Result<(), TracError> {
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        fs::write(&temporary, format!("{}\n", time::to_unix(watermark)))
+            .and_then(|_| fs::rename(&temporary, &self.path))
+            .map_err(|e| TracError::Io(format!("{}: {}", self.path.display(), e)))
+    }
+}
+
+/// Keeps the watermark in memory, for a sync that starts from scratch
+/// whenever the process does.
+#[derive(Debug, Default)]
+pub struct MemoryWatermark {
+    watermark: Cell<Option<SystemTime>>,
+}
+
+impl MemoryWatermark {
+    /// A store that already holds `watermark`, so the first sync fetches
+    /// only what changed after it.
+    pub fn at(watermark: SystemTime) -> Self {
+        Self {
+            watermark: Cell::new(Some(watermark)),
+        }
+    }
+}
+
+impl WatermarkStore for MemoryWatermark {
+    fn load(&self) -> Result<Option<SystemTime>, TracError> {
+        Ok(self.watermark.get())
+    }
+
+    fn save(&self, watermark: SystemTime) -> Result<(), TracError> {
+        self.watermark.set(Some(watermark));
+        Ok(())
+    }
+}
+
+/// Keeps watermarks in a SQLite database, one row per `name`, so several
+/// mirrors can share a database.
+#[cfg(feature = "sqlite")]
+pub struct SqliteWatermark {
+    connection: rusqlite::Connection,
+    name: String,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteWatermark {
+    pub fn open<P: AsRef<Path>>(path: P, name: &str) -> Result<Self, TracError> {
+        let connection =
+            rusqlite::Connection::open(path).map_err(|e| TracError::Io(e.to_string()))?;
+        Self::with_connection(connection, name)
+    }
+
+    /// Uses an existing connection, creating the `trac_sync_watermark`
+    /// table if needed.
+    pub fn with_connection(
+        connection: rusqlite::Connection,
+        name: &str,
+    ) -> Result<Self, TracError> {
+        connection
+            .execute(
+                "CREATE TABLE IF NOT EXISTS trac_sync_watermark (
+                    name TEXT PRIMARY KEY,
+                    synced_at INTEGER NOT NULL
+                )",
+                [],
+            )
+            .map_err(|e| TracError::Io(e.to_string()))?;
+        Ok(Self {
+            connection,
+            name: name.to_string(),
+        })
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl WatermarkStore for SqliteWatermark {
+    fn load(&self) -> Result<Option<SystemTime>, TracError> {
+        use rusqlite::OptionalExtension;
+
+        self.connection
+            .query_row(
+                "SELECT synced_at FROM trac_sync_watermark WHERE name = ?1",
+                [&self.name],
+                |row| row.get(0),
+            )
+            .optional()
+            .map(|seconds| seconds.map(time::from_unix))
+            .map_err(|e| TracError::Io(e.to_string()))
+    }
+
+    fn save(&self, watermark: SystemTime) -> Result<(), TracError> {
+        self.connection
+            .execute(
+                "INSERT INTO trac_sync_watermark (name, synced_at) VALUES (?1, ?2)
+                 ON CONFLICT (name) DO UPDATE SET synced_at = excluded.synced_at",
+                rusqlite::params![self.name, time::to_unix(watermark)],
+            )
+            .map(|_| ())
+            .map_err(|e| TracError::Io(e.to_string()))
+    }
+}
+
+/// What changed since the last sync.
+#[derive(Debug)]
+pub struct SyncDelta {
+    /// Tickets changed since the watermark that match the query.
+    pub changed: Vec<TracTicket>,
+    /// Tickets changed since the watermark that no longer match the query,
+    /// or no longer exist.
+    pub removed: Vec<i32>,
+    /// The watermark the delta starts from; `None` on the first sync, when
+    /// every matching ticket is fetched.
+    pub since: Option<SystemTime>,
+    /// The watermark to store once the delta has been applied.
+    pub watermark: SystemTime,
+}
+
+/// Fetches only the tickets changed since the last successful sync.
+///
+/// The watermark is taken from the server's own `changed` times, so the
+/// local clock only matters on the first sync. Each sync also looks
+/// `overlap` before the watermark, to catch changes committed late or a
+/// local clock running ahead of the server's on that first sync. Tickets
+/// can therefore appear in more than one delta, which is harmless for a
+/// mirror that applies them by id.
+#[derive(Debug)]
+pub struct DeltaSync<S: WatermarkStore> {
+    pub store: S,
+    pub overlap: Duration,
+}
+
+impl<S: WatermarkStore> DeltaSync<S> {
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            overlap: DEFAULT_OVERLAP,
+        }
+    }
+
+    pub fn overlap(mut self, overlap: Duration) -> Self {
+        self.overlap = overlap;
+        self
+    }
+
+    /// Fetches the delta without storing the new watermark, so that a
+    /// caller can `commit` it only once the delta has been applied.
+    pub fn fetch(&self, trac: &Trac, query: &TicketQuery) -> Result<SyncDelta, TracError> {
+        let started = SystemTime::now();
+        let since = self.store.load()?;
+
+        let (changed, removed, watermark) = match since {
+            None => {
+                let tickets = query.fetch(trac)?;
+                let latest = tickets.iter().map(|t| t.changed).max();
+                (tickets, vec![], latest.map_or(started, |l| l.max(started)))
+            }
+            Some(since) => {
+                let from = since.checked_sub(self.overlap).unwrap_or(since);
+                let ids = trac.tickets_changed_since(from)?;
+                let tickets = trac.get_tickets(&ids)?;
+                let latest = tickets.iter().map(|t| t.changed).max();
+
+                let changed: Vec<TracTicket> = tickets
+                    .into_iter()
+                    .filter(|t| query.conditions.iter().all(|c| c.matches(t)))
+                    .collect();
+                let removed = ids
+                    .into_iter()
+                    .filter(|id| !changed.iter().any(|t| t.id == *id))
+                    .collect();
+                (changed, removed, latest.map_or(since, |l| l.max(since)))
+            }
+        };
+
+        Ok(SyncDelta {
+            changed,
+            removed,
+            since,
+            watermark,
+        })
+    }
+
+    /// Stores the delta's watermark, so the next sync starts from it.
+    pub fn commit(&self, delta: &SyncDelta) -> Result<(), TracError> {
+        self.store.save(delta.watermark)
+    }
+
+    /// Fetches the delta and stores its watermark straight away.
+    pub fn run(&self, trac: &Trac, query: &TicketQuery) -> Result<SyncDelta, TracError> {
+        let delta = self.fetch(trac, query)?;
+        self.commit(&delta)?;
+        Ok(delta)
+    }
+}
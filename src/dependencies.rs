@@ -0,0 +1,393 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{parse_ticket_refs, Trac, TracError, TracTicket};
+
+const BLOCKED_BY_FIELD: &str = "blockedby";
+const BLOCKING_FIELD: &str = "blocking";
+// How many tickets `fetch` reads before it stops following references.
+const DEFAULT_MAX_TICKETS: usize = 1000;
+
+#[derive(Debug, Clone)]
+struct Node {
+    summary: String,
+    status: String,
+    milestone: String,
+}
+
+/// The blocking relationships between tickets, as recorded in the
+/// MasterTickets plugin's `blockedby`/`blocking` fields. An edge runs from a
+/// blocker to the ticket it blocks.
+#[derive(Debug)]
+pub struct DependencyGraph {
+    nodes: BTreeMap<i32, Node>,
+    edges: BTreeSet<(i32, i32)>,
+    missing: BTreeSet<i32>,
+    truncated: bool,
+    closed_statuses: Vec<String>,
+}
+
+impl Default for DependencyGraph {
+    fn default() -> Self {
+        Self {
+            nodes: BTreeMap::new(),
+            edges: BTreeSet::new(),
+            missing: BTreeSet::new(),
+            truncated: false,
+            closed_statuses: vec!["closed".to_string()],
+        }
+    }
+}
+
+impl DependencyGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the statuses that count as closed, which are no longer
+    /// blocking; just `closed` unless the graph was fetched, which uses
+    /// `TracConfig::closed_statuses`.
+    pub fn closed_statuses(&mut self, statuses: &[String]) -> &mut Self {
+        self.closed_statuses = statuses.to_vec();
+        self
+    }
+
+    pub fn add_ticket(&mut self, ticket: &TracTicket) -> &mut Self {
+        self.nodes.insert(
+            ticket.id,
+            Node {
+                summary: ticket.summary.to_owned(),
+                status: ticket.status.to_owned(),
+                milestone: ticket.milestone.to_owned(),
+            },
+        );
+        for blocker in parse_ticket_refs(ticket.field(BLOCKED_BY_FIELD)) {
+            self.edges.insert((blocker, ticket.id));
+        }
+        for blocked in parse_ticket_refs(ticket.field(BLOCKING_FIELD)) {
+            self.edges.insert((ticket.id, blocked));
+        }
+        self
+    }
+
+    pub fn from_tickets<'a, I>(tickets: I) -> Self
+    where
+        I: IntoIterator<Item = &'a TracTicket>,
+    {
+        let mut graph = Self::new();
+        for ticket in tickets {
+            graph.add_ticket(ticket);
+        }
+        graph
+    }
+
+    /// Builds the graph for the tickets matching `query`, following
+    /// references so that blockers outside the query are included too. At
+    /// most 1000 tickets are read; see `fetch_bounded`.
+    pub fn fetch(query: &str, trac: &Trac) -> Result<Self, TracError> {
+        Self::fetch_bounded(query, DEFAULT_MAX_TICKETS, trac)
+    }
+
+    /// Like `fetch`, but stops following references once `max_tickets`
+    /// tickets have been read, leaving the rest as bare references and
+    /// setting `truncated`. Referenced tickets that no longer exist are
+    /// skipped and listed by `missing`.
+    ///
+    /// Each round of references is read in one multicall.
+    pub fn fetch_bounded(query: &str, max_tickets: usize, trac: &Trac) -> Result<Self, TracError> {
+        let mut graph = Self::new();
+        graph.closed_statuses(&trac.config.closed_statuses);
+        let mut seen = BTreeSet::new();
+        let mut frontier: BTreeSet<i32> = trac.query(query)?.into_iter().collect();
+        while !frontier.is_empty() {
+            let room = max_tickets.saturating_sub(graph.nodes.len());
+            if room == 0 {
+                graph.truncated = true;
+                break;
+            }
+            let batch: Vec<i32> = frontier.iter().copied().take(room).collect();
+            for id in &batch {
+                frontier.remove(id);
+                seen.insert(*id);
+            }
+
+            // get_tickets leaves out the tickets that do not exist.
+            let tickets = trac.get_tickets(&batch)?;
+            let found: BTreeSet<i32> = tickets.iter().map(|t| t.id).collect();
+            graph
+                .missing
+                .extend(batch.iter().filter(|id| !found.contains(id)));
+            for ticket in &tickets {
+                graph.add_ticket(ticket);
+                frontier.extend(
+                    parse_ticket_refs(ticket.field(BLOCKED_BY_FIELD))
+                        .into_iter()
+                        .chain(parse_ticket_refs(ticket.field(BLOCKING_FIELD)))
+                        .filter(|i| !seen.contains(i)),
+                );
+            }
+        }
+        Ok(graph)
+    }
+
+    /// The referenced tickets that `fetch` found no longer exist.
+    pub fn missing(&self) -> Vec<i32> {
+        self.missing.iter().copied().collect()
+    }
+
+    /// Whether `fetch` stopped at its ticket limit before following every
+    /// reference.
+    pub fn truncated(&self) -> bool {
+        self.truncated
+    }
+
+    // Every id mentioned in the graph, including ones only seen as references.
+    fn ticket_ids(&self) -> impl Iterator<Item = i32> + '_ {
+        let referenced: BTreeSet<i32> = self.edges.iter().flat_map(|&(a, b)| vec![a, b]).collect();
+        self.nodes
+            .keys()
+            .copied()
+            .chain(referenced)
+            .collect::<BTreeSet<i32>>()
+            .into_iter()
+    }
+
+    pub fn blockers(&self, id: i32) -> Vec<i32> {
+        self.edges
+            .iter()
+            .filter(|&&(_, b)| b == id)
+            .map(|&(a, _)| a)
+            .collect()
+    }
+
+    pub fn blocked_by(&self, id: i32) -> Vec<i32> {
+        self.edges
+            .iter()
+            .filter(|&&(a, _)| a == id)
+            .map(|&(_, b)| b)
+            .collect()
+    }
+
+    fn is_open(&self, id: i32) -> bool {
+        match self.nodes.get(&id) {
+            Some(node) => !self.closed_statuses.contains(&node.status),
+            None => true,
+        }
+    }
+
+    /// Returns the open tickets that directly or transitively block an open
+    /// ticket in `milestone`.
+    pub fn blocking_milestone(&self, milestone: &str) -> Vec<i32> {
+        let mut found = BTreeSet::new();
+        let mut pending: Vec<i32> = self
+            .nodes
+            .iter()
+            .filter(|(id, node)| node.milestone == milestone && self.is_open(**id))
+            .map(|(id, _)| *id)
+            .collect();
+
+        while let Some(id) = pending.pop() {
+            for blocker in self.blockers(id) {
+                if self.is_open(blocker) && found.insert(blocker) {
+                    pending.push(blocker);
+                }
+            }
+        }
+
+        found.into_iter().collect()
+    }
+
+    /// Orders the tickets so that every blocker comes before the tickets it
+    /// blocks. If the graph has cycles, returns them instead.
+    pub fn topological_order(&self) -> Result<Vec<i32>, Vec<Vec<i32>>> {
+        let mut in_degree: BTreeMap<i32, usize> = self.ticket_ids().map(|id| (id, 0)).collect();
+        for &(_, b) in &self.edges {
+            *in_degree.entry(b).or_insert(0) += 1;
+        }
+
+        let mut ready: Vec<i32> = in_degree
+            .iter()
+            .filter(|(_, &d)| d == 0)
+            .map(|(id, _)| *id)
+            .rev()
+            .collect();
+        let mut order = Vec::new();
+
+        while let Some(id) = ready.pop() {
+            order.push(id);
+            for blocked in self.blocked_by(id) {
+                if let Some(d) = in_degree.get_mut(&blocked) {
+                    *d -= 1;
+                    if *d == 0 {
+                        ready.push(blocked);
+                    }
+                }
+            }
+        }
+
+        if order.len() == in_degree.len() {
+            Ok(order)
+        } else {
+            Err(self.cycles())
+        }
+    }
+
+    /// Returns each group of tickets that block one another in a cycle.
+    pub fn cycles(&self) -> Vec<Vec<i32>> {
+        let mut tarjan = Tarjan::default();
+        for id in self.ticket_ids() {
+            if !tarjan.index.contains_key(&id) {
+                tarjan.visit(id, self);
+            }
+        }
+
+        tarjan
+            .components
+            .into_iter()
+            .filter(|c| c.len() > 1 || self.edges.contains(&(c[0], c[0])))
+            .collect()
+    }
+
+    /// Renders the graph in Graphviz DOT format, with closed tickets greyed
+    /// out.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph dependencies {\n    rankdir=LR;\n");
+        for id in self.ticket_ids() {
+            let (label, style) = match self.nodes.get(&id) {
+                Some(node) => (
+                    format!("#{}: {}", id, node.summary),
+                    if !self.is_open(id) {
+                        ", style=filled, fillcolor=lightgrey"
+                    } else {
+                        ""
+                    },
+                ),
+                None => (format!("#{}", id), ", style=dashed"),
+            };
+            dot.push_str(&format!(
+                "    t{} [label=\"{}\"{}];\n",
+                id,
+                label.replace('\\', "\\\\").replace('"', "\\\""),
+                style
+            ));
+        }
+        for (a, b) in &self.edges {
+            dot.push_str(&format!("    t{} -> t{};\n", a, b));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[derive(Default)]
+struct Tarjan {
+    next: usize,
+    index: BTreeMap<i32, usize>,
+    low: BTreeMap<i32, usize>,
+    stack: Vec<i32>,
+    on_stack: BTreeSet<i32>,
+    components: Vec<Vec<i32>>,
+}
+
+impl Tarjan {
+    fn visit(&mut self, id: i32, graph: &DependencyGraph) {
+        self.index.insert(id, self.next);
+        self.low.insert(id, self.next);
+        self.next += 1;
+        self.stack.push(id);
+        self.on_stack.insert(id);
+
+        for next in graph.blocked_by(id) {
+            if !self.index.contains_key(&next) {
+                self.visit(next, graph);
+                let low = self.low[&id].min(self.low[&next]);
+                self.low.insert(id, low);
+            } else if self.on_stack.contains(&next) {
+                let low = self.low[&id].min(self.index[&next]);
+                self.low.insert(id, low);
+            }
+        }
+
+        if self.low[&id] == self.index[&id] {
+            let mut component = Vec::new();
+            while let Some(member) = self.stack.pop() {
+                self.on_stack.remove(&member);
+                component.push(member);
+                if member == id {
+                    break;
+                }
+            }
+            component.sort_unstable();
+            self.components.push(component);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn ticket(id: i32, status: &str, blocked_by: &str) -> TracTicket {
+        testing::ticket(
+            id,
+            &[
+                ("summary", "work"),
+                ("status", status),
+                ("milestone", "1.0"),
+                (BLOCKED_BY_FIELD, blocked_by),
+            ],
+        )
+    }
+
+    #[test]
+    fn orders_blockers_first() {
+        let tickets = [
+            ticket(1, "new", "2, 3"),
+            ticket(2, "new", "#3"),
+            ticket(3, "new", ""),
+        ];
+        let graph = DependencyGraph::from_tickets(&tickets);
+        assert_eq!(graph.topological_order(), Ok(vec![3, 2, 1]));
+        assert_eq!(graph.blockers(1), [2, 3]);
+        assert_eq!(graph.blocked_by(3), [1, 2]);
+        assert!(graph.cycles().is_empty());
+        assert!(!graph.truncated());
+    }
+
+    #[test]
+    fn reports_cycles() {
+        let tickets = [
+            ticket(1, "new", "2"),
+            ticket(2, "new", "1"),
+            ticket(3, "new", "3"),
+            ticket(4, "new", "1"),
+        ];
+        let graph = DependencyGraph::from_tickets(&tickets);
+        assert_eq!(graph.topological_order(), Err(vec![vec![1, 2], vec![3]]));
+    }
+
+    #[test]
+    fn closed_blockers_do_not_block() {
+        let tickets = [
+            ticket(1, "new", "2, 3"),
+            ticket(2, "rejected", "4"),
+            ticket(3, "assigned", ""),
+            ticket(4, "new", ""),
+        ];
+        let mut graph = DependencyGraph::from_tickets(&tickets);
+        assert_eq!(graph.blocking_milestone("1.0"), [2, 3, 4]);
+        graph.closed_statuses(&["closed".to_string(), "rejected".to_string()]);
+        assert_eq!(graph.blocking_milestone("1.0"), [3]);
+        assert!(graph
+            .to_dot()
+            .contains("t2 [label=\"#2: work\", style=filled"));
+    }
+
+    #[test]
+    fn renders_unfetched_references_dashed() {
+        let graph = DependencyGraph::from_tickets(&[ticket(1, "new", "9")]);
+        let dot = graph.to_dot();
+        assert!(dot.contains("t9 [label=\"#9\", style=dashed];"));
+        assert!(dot.contains("t9 -> t1;"));
+    }
+}
@@ -0,0 +1,205 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use xmlrpc::{Request, Value};
+
+use crate::{TicketQuery, Trac, TracError, TracTicket, WorkflowProfile};
+
+fn split_cc(cc: &str) -> impl Iterator<Item = &str> {
+    cc.split([',', ' '])
+        .map(str::trim)
+        .filter(|c| !c.is_empty())
+}
+
+impl TracTicket {
+    /// Closes this ticket as a duplicate of `other_id`, with a comment on
+    /// each ticket pointing at the other. With `copy_cc`, this ticket's
+    /// reporter and cc list are added to the other ticket's cc so they keep
+    /// getting notified. Returns this ticket and the other, as updated.
+    pub fn mark_duplicate_of(
+        &self,
+        other_id: i32,
+        copy_cc: bool,
+        trac: &Trac,
+    ) -> Result<(TracTicket, TracTicket), TracError> {
+        if other_id == self.id {
+            return Err(TracError::Unsupported(format!(
+                "ticket #{} cannot be a duplicate of itself",
+                self.id
+            )));
+        }
+        let other = trac.get_ticket(other_id)?;
+
+        let workflow = &trac.config.workflow;
+        let close = WorkflowProfile::action("close", &workflow.close)?;
+        let resolution_input = format!("action_{}_resolve_resolution", close.name);
+
+        let closed = self
+            .update_builder()
+            .with_action(close)
+            .action_input(&resolution_input, "duplicate")
+            .comment(&format!("Duplicate of #{}.", other_id))
+            .submit(trac)?;
+
+        let mut update = other.update_builder().comment(&format!(
+            "#{} was closed as a duplicate of this ticket.",
+            self.id
+        ));
+        if copy_cc {
+            let mut cc: Vec<&str> = split_cc(other.field("cc")).collect();
+            for person in split_cc(self.field("cc")).chain(std::iter::once(self.reporter.as_str()))
+            {
+                if !person.is_empty() && !cc.contains(&person) && person != other.reporter {
+                    cc.push(person);
+                }
+            }
+            update = update.set("cc", &cc.join(", "));
+        }
+        let survivor = update.submit(trac)?;
+
+        Ok((closed, survivor))
+    }
+}
+
+// Words too common to say anything about whether two summaries match.
+const STOP_WORDS: &[&str] = &[
+    "the", "and", "for", "with", "when", "not", "does", "from", "into", "that", "this", "are",
+    "can", "cannot", "should", "after", "before", "on", "in", "of", "to", "is", "a", "an",
+];
+// How many of the summary's words are sent to the server as search terms.
+const MAX_SEARCH_TERMS: usize = 8;
+// How many of the newest tickets are taken for each term, so that common
+// words do not return every ticket on the server.
+const MAX_MATCHES_PER_TERM: u32 = 100;
+// How many of the candidates matching the most terms are fetched and scored.
+const MAX_SCORED: usize = 20;
+
+#[derive(Debug)]
+pub struct SimilarTicket {
+    pub ticket: TracTicket,
+    /// Between 0 (nothing in common) and 1 (the same summary).
+    pub score: f64,
+}
+
+fn tokens(text: &str) -> BTreeSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .map(str::to_lowercase)
+        .filter(|t| t.len() > 1 && !STOP_WORDS.contains(&t.as_str()))
+        .collect()
+}
+
+fn trigrams(text: &str) -> BTreeSet<String> {
+    let normalized: Vec<char> = format!("  {} ", text.to_lowercase()).chars().collect();
+    normalized.windows(3).map(|w| w.iter().collect()).collect()
+}
+
+fn jaccard(a: &BTreeSet<String>, b: &BTreeSet<String>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
+
+// The ids matching the most terms, newest first among equals, given the
+// ids each term matched.
+fn top_candidates(matches: &[Vec<i32>], limit: usize) -> Vec<i32> {
+    let mut counts: BTreeMap<i32, usize> = BTreeMap::new();
+    for ids in matches {
+        for id in ids.iter().collect::<BTreeSet<_>>() {
+            *counts.entry(*id).or_default() += 1;
+        }
+    }
+    let mut ranked: Vec<(i32, usize)> = counts.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then(b.0.cmp(&a.0)));
+    ranked.into_iter().take(limit).map(|(id, _)| id).collect()
+}
+
+/// Averages word overlap, which rewards the same vocabulary, with trigram
+/// overlap, which tolerates typos and different word forms.
+fn similarity(a: &str, b: &str) -> f64 {
+    (jaccard(&tokens(a), &tokens(b)) + jaccard(&trigrams(a), &trigrams(b))) / 2.0
+}
+
+impl Trac {
+    /// Looks for existing tickets whose summary resembles `summary` and
+    /// returns those scoring at least `threshold`, best first. The server
+    /// finds the newest tickets whose summary contains each of its words;
+    /// only the few matching the most words are then fetched and scored.
+    pub fn find_similar(
+        &self,
+        summary: &str,
+        threshold: f64,
+    ) -> Result<Vec<SimilarTicket>, TracError> {
+        let mut terms: Vec<String> = tokens(summary).into_iter().collect();
+        terms.sort_by_key(|t| std::cmp::Reverse(t.len()));
+        terms.truncate(MAX_SEARCH_TERMS);
+        if terms.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let requests: Vec<Request> = terms
+            .iter()
+            .map(|term| {
+                let query = TicketQuery::new()
+                    .contains("summary", term)
+                    .order_by("id", true)
+                    .max(MAX_MATCHES_PER_TERM);
+                Ok(Request::new("ticket.query").arg(query.query_string()?))
+            })
+            .collect::<Result<_, TracError>>()?;
+        let mut matches = Vec::with_capacity(terms.len());
+        for result in self.multicall(&requests)? {
+            match result.map_err(|fault| TracError::from_fault(&fault))? {
+                Value::Array(ids) => matches.push(ids.iter().filter_map(|v| v.as_i32()).collect()),
+                _ => return Err(TracError::invalid_response("ticket.query")),
+            }
+        }
+
+        let mut similar: Vec<SimilarTicket> = self
+            .get_tickets(&top_candidates(&matches, MAX_SCORED))?
+            .into_iter()
+            .map(|ticket| SimilarTicket {
+                score: similarity(summary, &ticket.summary),
+                ticket,
+            })
+            .filter(|s| s.score >= threshold)
+            .collect();
+        similar.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        Ok(similar)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ignores_stop_words_and_case() {
+        let words: Vec<String> = tokens("The Login page crashes for a user")
+            .into_iter()
+            .collect();
+        assert_eq!(words, ["crashes", "login", "page", "user"]);
+    }
+
+    #[test]
+    fn scores_similar_summaries_higher() {
+        let summary = "Login page crashes on submit";
+        let close = similarity(summary, "login page crash on submit");
+        let far = similarity(summary, "Add dark mode to settings");
+        assert_eq!(similarity(summary, summary), 1.0);
+        assert!(close > 0.5 && close < 1.0, "{}", close);
+        assert!(far < 0.2, "{}", far);
+    }
+
+    #[test]
+    fn ranks_candidates_by_terms_matched() {
+        let matches = vec![vec![1, 2, 3, 3], vec![2, 3, 5], vec![3, 4]];
+        assert_eq!(top_candidates(&matches, 3), [3, 2, 5]);
+        assert_eq!(top_candidates(&matches, 10), [3, 2, 5, 4, 1]);
+        assert!(top_candidates(&[], 5).is_empty());
+    }
+}
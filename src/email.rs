@@ -0,0 +1,148 @@
+use std::collections::BTreeMap;
+
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+
+use crate::template::substitute;
+use crate::{Trac, TracError, TracReviewer, TracTicket};
+
+const DEFAULT_SUBJECT: &str = "Review requested: #{id} {summary}";
+const DEFAULT_BODY: &str = "{requester} asked you to review #{id}: {summary}\n\n{url}\n";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SmtpSecurity {
+    /// Plain SMTP, usually on port 25.
+    None,
+    /// Upgrades the connection with STARTTLS, usually on port 587.
+    StartTls,
+    /// TLS from the start, usually on port 465.
+    Tls,
+}
+
+/// How review request emails are sent, from the `[email]` section:
+/// `smtp_host`, `smtp_port`, `smtp_username`, `smtp_password`,
+/// `smtp_security` (`none`, `starttls` or `tls`; `starttls` by default),
+/// `from`, and the `subject` and `body` templates. These can use `{id}`,
+/// `{summary}`, `{url}`, `{reviewer}` and `{requester}`.
+#[derive(Debug, Clone)]
+pub struct EmailSettings {
+    pub smtp_host: String,
+    /// The security mode's usual port if `None`.
+    pub smtp_port: Option<u16>,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+    pub smtp_security: SmtpSecurity,
+    pub from: String,
+    pub subject: String,
+    pub body: String,
+}
+
+fn email_error(e: impl std::fmt::Display) -> TracError {
+    TracError::Transport(format!("email: {}", e))
+}
+
+impl EmailSettings {
+    pub(crate) fn from_section(section: &BTreeMap<String, String>) -> Result<Self, TracError> {
+        let get = |key: &str| section.get(key).map(|v| v.to_owned());
+        let require = |key: &str| {
+            get(key).ok_or_else(|| TracError::Config(format!("config is missing [email] {}", key)))
+        };
+        let smtp_security = match section.get("smtp_security").map(|s| s.as_str()) {
+            Some("none") => SmtpSecurity::None,
+            Some("starttls") | None => SmtpSecurity::StartTls,
+            Some("tls") => SmtpSecurity::Tls,
+            Some(other) => {
+                return Err(TracError::Config(format!(
+                    "unknown [email] smtp_security '{}'",
+                    other
+                )))
+            }
+        };
+        let smtp_port = match section.get("smtp_port") {
+            Some(port) => Some(port.parse().map_err(|_| {
+                TracError::Config(format!("invalid value for [email] smtp_port: {}", port))
+            })?),
+            None => None,
+        };
+
+        Ok(EmailSettings {
+            smtp_host: require("smtp_host")?,
+            smtp_port,
+            smtp_username: get("smtp_username"),
+            smtp_password: get("smtp_password"),
+            smtp_security,
+            from: require("from")?,
+            subject: get("subject").unwrap_or_else(|| DEFAULT_SUBJECT.to_string()),
+            body: get("body").unwrap_or_else(|| DEFAULT_BODY.to_string()),
+        })
+    }
+
+    fn transport(&self) -> Result<SmtpTransport, TracError> {
+        let mut builder = match self.smtp_security {
+            SmtpSecurity::None => SmtpTransport::builder_dangerous(&self.smtp_host),
+            SmtpSecurity::StartTls => {
+                SmtpTransport::starttls_relay(&self.smtp_host).map_err(email_error)?
+            }
+            SmtpSecurity::Tls => SmtpTransport::relay(&self.smtp_host).map_err(email_error)?,
+        };
+        if let Some(port) = self.smtp_port {
+            builder = builder.port(port);
+        }
+        if let (Some(username), Some(password)) = (&self.smtp_username, &self.smtp_password) {
+            builder =
+                builder.credentials(Credentials::new(username.to_owned(), password.to_owned()));
+        }
+        Ok(builder.build())
+    }
+}
+
+impl TracTicket {
+    /// Emails `reviewer` that this ticket is waiting for their review,
+    /// using the `[email]` settings.
+    pub fn notify_reviewer(&self, reviewer: &TracReviewer, trac: &Trac) -> Result<(), TracError> {
+        let settings = match &trac.config.email {
+            Some(settings) => settings,
+            None => return Err(TracError::Config("no [email] settings".to_string())),
+        };
+
+        let vars = [
+            ("id", self.id.to_string()),
+            ("summary", self.summary.to_owned()),
+            ("url", TracTicket::url(self.id, trac)),
+            ("reviewer", reviewer.name.to_owned()),
+            ("requester", trac.user().username.to_owned()),
+        ];
+        let lookup = |name: &str| {
+            vars.iter()
+                .find(|(k, _)| *k == name)
+                .map(|(_, v)| v.to_owned())
+        };
+        let expand = |text: &str| {
+            substitute(text, lookup).map_err(|missing| {
+                TracError::Config(format!(
+                    "[email] template uses unknown variables: {}",
+                    missing.join(", ")
+                ))
+            })
+        };
+
+        let from: Mailbox = settings.from.parse().map_err(email_error)?;
+        let to = Mailbox::new(
+            Some(reviewer.name.to_owned()),
+            reviewer.email.parse().map_err(email_error)?,
+        );
+        let message = Message::builder()
+            .from(from)
+            .to(to)
+            .subject(expand(&settings.subject)?)
+            .body(expand(&settings.body)?)
+            .map_err(email_error)?;
+
+        settings
+            .transport()?
+            .send(&message)
+            .map(|_| ())
+            .map_err(email_error)
+    }
+}
@@ -0,0 +1,75 @@
+use std::cell::Cell;
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use xmlrpc::Request;
+
+use crate::transport::StatusTransport;
+use crate::Trac;
+
+// Where the XML-RPC plugin is commonly mounted, most usual first.
+const CANDIDATES: &[&str] = &["login/xmlrpc", "xmlrpc", "login/rpc", "rpc"];
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where the server's XML-RPC handler lives, relative to the Trac path.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RpcEndpoint {
+    Path(String),
+    /// Probe the common locations in order on first use and keep the first
+    /// one that answers.
+    Auto,
+}
+
+impl RpcEndpoint {
+    pub(crate) fn from_setting(value: &str) -> Self {
+        match value {
+            "auto" => RpcEndpoint::Auto,
+            path => RpcEndpoint::Path(path.trim_start_matches('/').to_string()),
+        }
+    }
+}
+
+impl Default for RpcEndpoint {
+    fn default() -> Self {
+        RpcEndpoint::Path("login/xmlrpc".to_string())
+    }
+}
+
+impl Trac {
+    /// The endpoint path requests are sent to, probing for it first if the
+    /// config asks for auto-detection. If no candidate answers, the first one
+    /// is used so that the call reports its own error, and the next call
+    /// probes again.
+    pub fn rpc_path(&self) -> String {
+        match &self.config.endpoint {
+            RpcEndpoint::Path(path) => path.to_owned(),
+            RpcEndpoint::Auto => {
+                if let Some(path) = &*self.endpoint.borrow() {
+                    return path.to_owned();
+                }
+                match CANDIDATES.iter().find(|path| self.probe(path)) {
+                    Some(path) => {
+                        *self.endpoint.borrow_mut() = Some(path.to_string());
+                        path.to_string()
+                    }
+                    None => CANDIDATES[0].to_string(),
+                }
+            }
+        }
+    }
+
+    // Any XML-RPC answer, including a fault, shows the handler is there, as
+    // does an authentication challenge; anything else means keep looking.
+    fn probe(&self, path: &str) -> bool {
+        let status = Cell::new(None);
+        let transport = StatusTransport::new(self.post(path).timeout(PROBE_TIMEOUT), &status);
+        match Request::new("system.getAPIVersion").call(transport) {
+            Ok(_) => true,
+            Err(e) if e.fault().is_some() => true,
+            Err(_) => matches!(
+                status.get(),
+                Some(StatusCode::UNAUTHORIZED) | Some(StatusCode::FORBIDDEN)
+            ),
+        }
+    }
+}
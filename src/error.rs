@@ -0,0 +1,299 @@
+use std::error::Error;
+use std::fmt;
+
+use reqwest::StatusCode;
+use xmlrpc::Fault;
+
+// Fault codes used by the XML-RPC plugin for Trac's own exception types.
+const FAULT_PERMISSION_DENIED: i32 = 403;
+const FAULT_NOT_FOUND: i32 = 404;
+
+/// Errors fall into three groups. The server can refuse a request with a
+/// fault: `NoSuchTicket`, `PermissionDenied`, `InvalidAttribute`,
+/// `MidAirCollision`, `TransitionDenied` and `Fault`. The exchange can fail
+/// underneath that, with `Transport` or `Http`, or return something
+/// unexpected, `InvalidResponse`. The rest arise locally.
+///
+/// `is_retryable`, `is_auth_error` and `is_not_found` sort them by what a
+/// caller can do about them; anything else will fail the same way again
+/// until the request, data or configuration is changed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TracError {
+    /// The ticket with this id does not exist.
+    NoSuchTicket(i32),
+    /// The user lacks the named permission or may not perform the named
+    /// action.
+    PermissionDenied(String),
+    /// The server rejected the named field or its value.
+    InvalidAttribute(String),
+    /// The ticket was changed by someone else since it was read.
+    MidAirCollision,
+    /// Any other fault reported by the server.
+    Fault { code: i32, message: String },
+    /// The request did not reach the server, or the HTTP exchange failed.
+    Transport(String),
+    /// The server answered with an HTTP error status instead of a result.
+    Http { status: u16, message: String },
+    /// The server's response did not have the expected shape.
+    InvalidResponse(String),
+    /// The configuration or a config file is invalid.
+    Config(String),
+    /// A query string could not be parsed.
+    InvalidQuery(String),
+    /// A local file, index or database could not be read or written.
+    Io(String),
+    /// An attachment was refused before uploading it.
+    InvalidAttachment(String),
+    /// The caller cancelled the operation.
+    Cancelled,
+    /// The operation cannot be performed as requested, e.g. the workflow
+    /// profile has no action for it.
+    Unsupported(String),
+    /// A transition guard refused the action, for the reasons given.
+    TransitionDenied {
+        action: String,
+        violations: Vec<String>,
+    },
+}
+
+// The first `Ticket <n>` or `ticket #<n>` reference in a fault message.
+// ASCII lowercasing keeps byte offsets, so `lower` is sliced directly.
+fn ticket_id_in(message: &str) -> Option<i32> {
+    let lower = message.to_ascii_lowercase();
+    lower.match_indices("ticket").find_map(|(i, word)| {
+        let rest = lower[i + word.len()..].strip_prefix(' ')?;
+        let rest = rest.strip_prefix('#').unwrap_or(rest);
+        let digits: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+        digits.parse().ok()
+    })
+}
+
+// The field named in messages such as `field 'priority' ...`.
+fn field_name_in(message: &str) -> Option<String> {
+    let start = message.find(['\'', '"'])?;
+    let quote = message[start..].chars().next()?;
+    let rest = &message[start + 1..];
+    let end = rest.find(quote)?;
+    Some(rest[..end].to_string())
+}
+
+impl TracError {
+    /// Classifies a server fault by its code and the wording Trac uses for
+    /// its well-known errors.
+    pub fn from_fault(fault: &Fault) -> Self {
+        let message = &fault.fault_string;
+        let lower = message.to_lowercase();
+
+        if lower.contains("does not exist") || fault.fault_code == FAULT_NOT_FOUND {
+            if let Some(id) = ticket_id_in(message) {
+                return TracError::NoSuchTicket(id);
+            }
+        }
+
+        if fault.fault_code == FAULT_PERMISSION_DENIED
+            || lower.contains("privileges are required")
+            || lower.contains("permission")
+        {
+            let permission = message
+                .split_whitespace()
+                .find(|w| w.len() > 1 && w.chars().all(|c| c.is_ascii_uppercase() || c == '_'))
+                .unwrap_or(message);
+            return TracError::PermissionDenied(permission.to_string());
+        }
+
+        if lower.contains("updated since last get")
+            || lower.contains("modified by someone else")
+            || lower.contains("mid-air collision")
+        {
+            return TracError::MidAirCollision;
+        }
+
+        if lower.contains("field")
+            && (lower.contains("invalid")
+                || lower.contains("unknown")
+                || lower.contains("not a valid"))
+        {
+            if let Some(name) = field_name_in(message) {
+                return TracError::InvalidAttribute(name);
+            }
+        }
+
+        TracError::Fault {
+            code: fault.fault_code,
+            message: message.to_owned(),
+        }
+    }
+
+    pub(crate) fn invalid_response(method: &str) -> Self {
+        TracError::InvalidResponse(format!("unexpected response to {}", method))
+    }
+
+    pub(crate) fn from_status(status: StatusCode) -> Self {
+        TracError::Http {
+            status: status.as_u16(),
+            message: status.canonical_reason().unwrap_or("").to_string(),
+        }
+    }
+
+    // A failed call, told apart by the HTTP status it got, if any.
+    pub(crate) fn from_call(e: xmlrpc::Error, status: Option<StatusCode>) -> Self {
+        match status {
+            Some(status) if e.fault().is_none() && !status.is_success() => {
+                TracError::from_status(status)
+            }
+            _ => e.into(),
+        }
+    }
+
+    /// Whether the same request may succeed if sent again later: the
+    /// connection failed or timed out, or the server was overloaded or
+    /// failing (HTTP 408, 429 or 5xx). This is what `RetryPolicy` retries.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            TracError::Transport(_) => true,
+            TracError::Http { status, .. } => {
+                matches!(status, 408 | 429) || (500..600).contains(status)
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the credentials were rejected (HTTP 401 or 403), or the user
+    /// lacks a permission, so that other credentials may succeed.
+    pub fn is_auth_error(&self) -> bool {
+        match self {
+            TracError::Http { status, .. } => matches!(status, 401 | 403),
+            TracError::PermissionDenied(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the ticket or other resource asked for does not exist.
+    pub fn is_not_found(&self) -> bool {
+        match self {
+            TracError::NoSuchTicket(_) => true,
+            TracError::Http { status, .. } => *status == 404,
+            TracError::Fault { code, .. } => *code == FAULT_NOT_FOUND,
+            _ => false,
+        }
+    }
+}
+
+impl From<xmlrpc::Error> for TracError {
+    fn from(e: xmlrpc::Error) -> Self {
+        match e.fault() {
+            Some(fault) => TracError::from_fault(fault),
+            None => TracError::Transport(e.to_string()),
+        }
+    }
+}
+
+impl From<Fault> for TracError {
+    fn from(fault: Fault) -> Self {
+        TracError::from_fault(&fault)
+    }
+}
+
+impl fmt::Display for TracError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TracError::NoSuchTicket(id) => write!(f, "ticket #{} does not exist", id),
+            TracError::PermissionDenied(p) => write!(f, "permission denied: {}", p),
+            TracError::InvalidAttribute(name) => write!(f, "invalid ticket field '{}'", name),
+            TracError::MidAirCollision => {
+                write!(f, "the ticket was modified by someone else in the meantime")
+            }
+            TracError::Fault { code, message } => write!(f, "{} ({})", message, code),
+            TracError::Transport(e) => write!(f, "transport error: {}", e),
+            TracError::Http { status, message } => write!(f, "HTTP {}: {}", status, message),
+            TracError::InvalidResponse(e) => write!(f, "invalid response: {}", e),
+            TracError::Config(e) => write!(f, "configuration error: {}", e),
+            TracError::InvalidQuery(e) => write!(f, "invalid query: {}", e),
+            TracError::Io(e) => write!(f, "I/O error: {}", e),
+            TracError::InvalidAttachment(e) => write!(f, "invalid attachment: {}", e),
+            TracError::Cancelled => write!(f, "cancelled"),
+            TracError::Unsupported(e) => write!(f, "unsupported operation: {}", e),
+            TracError::TransitionDenied { action, violations } => {
+                write!(f, "cannot {}: {}", action, violations.join("; "))
+            }
+        }
+    }
+}
+
+impl Error for TracError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fault(code: i32, message: &str) -> TracError {
+        TracError::from_fault(&Fault {
+            fault_code: code,
+            fault_string: message.to_string(),
+        })
+    }
+
+    #[test]
+    fn classifies_missing_tickets() {
+        assert_eq!(
+            fault(404, "Ticket 42 does not exist."),
+            TracError::NoSuchTicket(42)
+        );
+        assert_eq!(
+            fault(1, "ticket #7 does not exist"),
+            TracError::NoSuchTicket(7)
+        );
+        // A non-ASCII prefix must not shift the offsets used to slice.
+        assert_eq!(
+            fault(404, "İİ Ticket 3 does not exist"),
+            TracError::NoSuchTicket(3)
+        );
+    }
+
+    #[test]
+    fn ignores_bare_numbers_in_not_found_messages() {
+        assert_eq!(
+            fault(404, "Wiki page #2 does not exist"),
+            TracError::Fault {
+                code: 404,
+                message: "Wiki page #2 does not exist".to_string()
+            }
+        );
+        assert!(matches!(
+            fault(404, "Ticket field 'x' does not exist"),
+            TracError::Fault { .. }
+        ));
+    }
+
+    #[test]
+    fn classifies_permission_faults() {
+        assert_eq!(
+            fault(
+                403,
+                "TICKET_MODIFY privileges are required to perform this operation"
+            ),
+            TracError::PermissionDenied("TICKET_MODIFY".to_string())
+        );
+    }
+
+    #[test]
+    fn classifies_collisions_and_fields() {
+        assert_eq!(
+            fault(1, "Sorry, can not save your changes. This ticket has been modified by someone else since you started"),
+            TracError::MidAirCollision
+        );
+        assert_eq!(
+            fault(1, "Invalid value for field 'priority'"),
+            TracError::InvalidAttribute("priority".to_string())
+        );
+    }
+
+    #[test]
+    fn sorts_errors_by_remedy() {
+        assert!(TracError::Transport("reset".to_string()).is_retryable());
+        assert!(TracError::from_status(StatusCode::SERVICE_UNAVAILABLE).is_retryable());
+        assert!(!TracError::from_status(StatusCode::NOT_FOUND).is_retryable());
+        assert!(TracError::from_status(StatusCode::UNAUTHORIZED).is_auth_error());
+        assert!(fault(404, "Page Foo does not exist").is_not_found());
+    }
+}
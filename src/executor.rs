@@ -0,0 +1,227 @@
+use xmlrpc::{Request, Value};
+
+use crate::{CancelToken, Trac, TracError};
+
+/// How far a bulk operation has got, reported after each round trip to the
+/// server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BulkProgress {
+    /// Tickets in the operation.
+    pub total: usize,
+    /// Tickets finished with, including those that failed.
+    pub done: usize,
+    pub failed: usize,
+}
+
+pub(crate) type ProgressReporter = Box<dyn FnMut(BulkProgress)>;
+
+// What a job wants to do next.
+pub(crate) enum Step<T> {
+    Call(Request<'static>),
+    Done(Result<T, TracError>),
+}
+
+/// One ticket's part of a bulk operation: a chain of calls, each chosen
+/// from the result of the one before.
+pub(crate) trait Job {
+    type Output;
+
+    /// The next call, given the result of the previous one (`None` to
+    /// start), or the job's outcome.
+    fn step(&mut self, previous: Option<Result<Value, TracError>>) -> Step<Self::Output>;
+}
+
+/// A job of a single call, whose result `map` turns into the outcome.
+pub(crate) struct Call<F> {
+    request: Option<Request<'static>>,
+    map: F,
+}
+
+impl<T, F: FnMut(Value) -> Result<T, TracError>> Job for Call<F> {
+    type Output = T;
+
+    fn step(&mut self, previous: Option<Result<Value, TracError>>) -> Step<T> {
+        match previous {
+            None => Step::Call(self.request.take().expect("job started twice")),
+            Some(result) => Step::Done(result.and_then(&mut self.map)),
+        }
+    }
+}
+
+pub(crate) fn call<T, F>(request: Request<'static>, map: F) -> Call<F>
+where
+    F: FnMut(Value) -> Result<T, TracError>,
+{
+    Call {
+        request: Some(request),
+        map,
+    }
+}
+
+fn finish<T>(
+    outcomes: &mut [Option<Result<T, TracError>>],
+    progress: &mut BulkProgress,
+    index: usize,
+    outcome: Result<T, TracError>,
+) {
+    progress.done += 1;
+    if outcome.is_err() {
+        progress.failed += 1;
+    }
+    outcomes[index] = Some(outcome);
+}
+
+impl Trac {
+    /// Runs `f` with `progress` called after each round trip of the bulk
+    /// operations it starts, e.g. to drive a progress bar.
+    pub fn with_bulk_progress<T, P, F>(&self, progress: P, f: F) -> T
+    where
+        P: FnMut(BulkProgress) + 'static,
+        F: FnOnce(&Trac) -> T,
+    {
+        let previous = self.bulk_progress.replace(Some(Box::new(progress)));
+        let result = f(self);
+        *self.bulk_progress.borrow_mut() = previous;
+        result
+    }
+
+    /// Runs one job per ticket, returning their outcomes in order.
+    ///
+    /// At most `TracConfig::concurrency` jobs are under way at once; each
+    /// round trip carries the next call of every one of them, so a ticket
+    /// that needs several calls holds up no other. Once `cancel` is set no
+    /// further round is sent, and the jobs left unfinished end in
+    /// `TracError::Cancelled`. If a round fails as a whole, e.g. because
+    /// the server cannot be reached, no further round is sent either: the
+    /// jobs in that round, whose last call may or may not have been
+    /// applied, and those not yet started end in its error. Either way the
+    /// outcomes of the jobs already finished are kept.
+    pub(crate) fn execute<J: Job>(
+        &self,
+        mut jobs: Vec<J>,
+        cancel: &CancelToken,
+    ) -> Vec<Result<J::Output, TracError>> {
+        let concurrency = self.config.concurrency.max(1);
+        let mut outcomes: Vec<Option<Result<J::Output, TracError>>> =
+            jobs.iter().map(|_| None).collect();
+        let mut progress = BulkProgress {
+            total: jobs.len(),
+            done: 0,
+            failed: 0,
+        };
+
+        let mut waiting = 0..jobs.len();
+        let mut active: Vec<(usize, Request<'static>)> = Vec::new();
+        let mut failure: Option<TracError> = None;
+        loop {
+            while active.len() < concurrency {
+                let index = match waiting.next() {
+                    Some(index) => index,
+                    None => break,
+                };
+                match jobs[index].step(None) {
+                    Step::Call(request) => active.push((index, request)),
+                    Step::Done(outcome) => finish(&mut outcomes, &mut progress, index, outcome),
+                }
+            }
+            if active.is_empty() || cancel.is_cancelled() {
+                break;
+            }
+
+            // Sent a batch at a time, so that a failed batch loses none of
+            // the results of those before it.
+            let (indices, requests): (Vec<usize>, Vec<Request>) = active.drain(..).unzip();
+            let batch_size = self.config.batch_size.max(1);
+            for (indices, requests) in indices.chunks(batch_size).zip(requests.chunks(batch_size)) {
+                let results = match &failure {
+                    None => self.multicall(requests),
+                    Some(e) => Err(e.clone()),
+                };
+                match results {
+                    Ok(results) => {
+                        for (&index, result) in indices.iter().zip(results) {
+                            match jobs[index].step(Some(result.map_err(TracError::from))) {
+                                Step::Call(request) => active.push((index, request)),
+                                Step::Done(outcome) => {
+                                    finish(&mut outcomes, &mut progress, index, outcome)
+                                }
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        for &index in indices {
+                            finish(&mut outcomes, &mut progress, index, Err(e.clone()));
+                        }
+                        failure = Some(e);
+                    }
+                }
+            }
+
+            if let Some(report) = &mut *self.bulk_progress.borrow_mut() {
+                report(progress);
+            }
+            if failure.is_some() {
+                break;
+            }
+        }
+
+        let unfinished = failure.unwrap_or(TracError::Cancelled);
+        outcomes
+            .into_iter()
+            .map(|o| o.unwrap_or_else(|| Err(unfinished.clone())))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    // Finishes at once for even numbers, and otherwise makes one call.
+    struct Parity(i32);
+
+    impl Job for Parity {
+        type Output = i32;
+
+        fn step(&mut self, previous: Option<Result<Value, TracError>>) -> Step<i32> {
+            match previous {
+                None if self.0 % 2 == 0 => Step::Done(Ok(self.0)),
+                None => Step::Call(Request::new("ticket.get").arg(self.0)),
+                Some(result) => Step::Done(result.map(|_| self.0)),
+            }
+        }
+    }
+
+    fn unreachable_trac() -> Trac {
+        let mut config = testing::config();
+        config.concurrency = 1;
+        testing::offline(config)
+    }
+
+    #[test]
+    fn failed_round_keeps_finished_outcomes() {
+        let trac = unreachable_trac();
+        let jobs = (0..5).map(Parity).collect();
+        let outcomes = trac.execute(jobs, &CancelToken::new());
+
+        assert_eq!(outcomes.len(), 5);
+        assert_eq!(outcomes[0], Ok(0));
+        assert!(matches!(outcomes[1], Err(TracError::Transport(_))));
+        // Left untouched once the server could not be reached.
+        assert!(matches!(outcomes[3], Err(TracError::Transport(_))));
+        assert!(matches!(outcomes[4], Err(TracError::Transport(_))));
+    }
+
+    #[test]
+    fn cancelled_jobs_are_not_started() {
+        let trac = unreachable_trac();
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let outcomes = trac.execute(vec![Parity(1), Parity(2)], &cancel);
+        assert_eq!(
+            outcomes,
+            vec![Err(TracError::Cancelled), Err(TracError::Cancelled)]
+        );
+    }
+}
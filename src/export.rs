@@ -0,0 +1,322 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::time::SystemTime;
+
+use reqwest::Url;
+
+use crate::json::Object;
+use crate::{time, CancelToken, TicketQuery, Trac, TracChange, TracError, TracTicket};
+
+/// What `to_ics` puts in the calendar.
+#[derive(Debug, Clone, Default)]
+pub struct IcsOptions {
+    /// The calendar's display name; none is set by default.
+    pub calendar_name: Option<String>,
+    /// Also list milestones that are already completed.
+    pub include_completed: bool,
+    /// A custom ticket field holding a `YYYY-MM-DD` due date. If set, the
+    /// open tickets of `owner` with a date in it are listed as well.
+    pub due_field: Option<String>,
+    /// Whose tickets to list; the configured user by default.
+    pub owner: Option<String>,
+}
+
+fn write_line<W: Write>(writer: &mut W, line: String) -> Result<(), TracError> {
+    writeln!(writer, "{}", line).map_err(|e| TracError::Io(e.to_string()))
+}
+
+// The field values a ticket was created with: the old value of the first
+// change to each field, or the current value if it never changed.
+fn initial_fields(ticket: &TracTicket, changes: &[TracChange]) -> BTreeMap<String, String> {
+    let mut fields = ticket.fields.clone();
+    for change in changes.iter().rev() {
+        if change.field != "comment" && !change.field.starts_with('_') {
+            fields.insert(change.field.to_owned(), change.old_value.to_owned());
+        }
+    }
+    fields
+}
+
+impl TracTicket {
+    /// Writes the ticket's full history as JSON Lines: a `ticket` record
+    /// with the values it was created with, then one `change` record per
+    /// changelog entry and one `attachment` record per attachment.
+    pub fn export_history<W: Write>(&self, writer: &mut W, trac: &Trac) -> Result<(), TracError> {
+        let changes = self.changelog(trac)?;
+
+        let mut fields = Object::new();
+        for (name, value) in initial_fields(self, &changes) {
+            fields = fields.str(&name, &value);
+        }
+        write_line(
+            writer,
+            Object::new()
+                .str("type", "ticket")
+                .num("id", self.id)
+                .str("created", &time::format_rfc3339(self.created))
+                .raw("fields", fields.build())
+                .build(),
+        )?;
+
+        for change in &changes {
+            write_line(
+                writer,
+                Object::new()
+                    .str("type", "change")
+                    .num("ticket", self.id)
+                    .str("time", &time::format_rfc3339(change.time))
+                    .str("author", &change.author)
+                    .str("field", &change.field)
+                    .str("old", &change.old_value)
+                    .str("new", &change.new_value)
+                    .bool("permanent", change.permanent)
+                    .build(),
+            )?;
+        }
+
+        for attachment in self.attachments(trac)? {
+            write_line(
+                writer,
+                Object::new()
+                    .str("type", "attachment")
+                    .num("ticket", self.id)
+                    .str("filename", &attachment.filename)
+                    .str("description", &attachment.description)
+                    .num("size", attachment.size)
+                    .str("time", &time::format_rfc3339(attachment.time))
+                    .str("author", &attachment.author)
+                    .build(),
+            )?;
+        }
+
+        writer.flush().map_err(|e| TracError::Io(e.to_string()))
+    }
+}
+
+impl Trac {
+    /// Exports the history of every ticket matching `query`, one after the
+    /// other, and returns how many were written.
+    pub fn export_all_history<W: Write>(
+        &self,
+        query: &TicketQuery,
+        writer: &mut W,
+    ) -> Result<usize, TracError> {
+        self.export_all_history_cancellable(query, writer, &CancelToken::new())
+    }
+
+    /// Like `export_all_history`, but stops with `TracError::Cancelled` once
+    /// `cancel` is set. Only whole tickets are written, so the output up to
+    /// that point is a valid export of the tickets in it.
+    pub fn export_all_history_cancellable<W: Write>(
+        &self,
+        query: &TicketQuery,
+        writer: &mut W,
+        cancel: &CancelToken,
+    ) -> Result<usize, TracError> {
+        let ids = query.ids(self)?;
+        let mut exported = 0;
+        for chunk in ids.chunks(self.config.batch_size.max(1)) {
+            for ticket in self.get_tickets_cancellable(chunk, cancel)? {
+                cancel.check()?;
+                ticket.export_history(writer, self)?;
+                exported += 1;
+            }
+        }
+        Ok(exported)
+    }
+}
+
+// Escapes a TEXT value as RFC 5545 requires.
+fn ics_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            ';' => out.push_str("\\;"),
+            ',' => out.push_str("\\,"),
+            '\n' => out.push_str("\\n"),
+            '\r' => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+// Adds a content line, folded so that no line is longer than 75 octets.
+fn push_line(ics: &mut String, line: &str) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            ics.push_str("\r\n ");
+            width = 1;
+        }
+        ics.push(c);
+        width += c.len_utf8();
+    }
+    ics.push_str("\r\n");
+}
+
+fn ics_datetime(t: SystemTime) -> String {
+    time::format_rfc3339(t).replace(['-', ':'], "")
+}
+
+fn ics_date(t: SystemTime) -> String {
+    time::format_date(t).replace('-', "")
+}
+
+fn milestone_url(name: &str, trac: &Trac) -> Option<String> {
+    let mut url = Url::parse(&trac.url()).ok()?;
+    url.path_segments_mut()
+        .ok()?
+        .pop_if_empty()
+        .push("milestone")
+        .push(name);
+    Some(url.to_string())
+}
+
+/// Builds an iCalendar feed of milestone due dates and, if
+/// `options.due_field` is set, of the due dates of a user's open tickets,
+/// for subscribing to from a team calendar. Milestones are timed events at
+/// their due time; ticket due dates are all-day events.
+pub fn to_ics(options: &IcsOptions, trac: &Trac) -> Result<String, TracError> {
+    let host = &trac.config.host;
+    let stamp = ics_datetime(SystemTime::now());
+    let mut events: Vec<Vec<String>> = Vec::new();
+
+    for milestone in trac.roadmap()? {
+        let due = match milestone.due {
+            Some(due) if options.include_completed || !milestone.is_completed() => due,
+            _ => continue,
+        };
+        let mut event = vec![
+            format!("UID:milestone-{}@{}", ics_text(&milestone.name), host),
+            format!("DTSTAMP:{}", stamp),
+            format!("DTSTART:{}", ics_datetime(due)),
+            format!(
+                "SUMMARY:{}",
+                ics_text(&format!("Milestone {}", milestone.name))
+            ),
+        ];
+        if !milestone.description.is_empty() {
+            event.push(format!("DESCRIPTION:{}", ics_text(&milestone.description)));
+        }
+        if let Some(url) = milestone_url(&milestone.name, trac) {
+            event.push(format!("URL:{}", url));
+        }
+        events.push(event);
+    }
+
+    if let Some(due_field) = &options.due_field {
+        let owner = options
+            .owner
+            .as_deref()
+            .unwrap_or(&trac.config.user.username);
+        let tickets = TicketQuery::new()
+            .is("owner", owner)
+            .open(trac)
+            .is_not(due_field, "")
+            .order_by(due_field, false)
+            .fetch(trac)?;
+        for ticket in tickets {
+            let due = match ticket
+                .fields
+                .get(due_field)
+                .and_then(|d| time::parse_date(d))
+            {
+                Some(due) => due,
+                None => continue,
+            };
+            events.push(vec![
+                format!("UID:ticket-{}@{}", ticket.id, host),
+                format!("DTSTAMP:{}", stamp),
+                format!("DTSTART;VALUE=DATE:{}", ics_date(due)),
+                format!(
+                    "SUMMARY:{}",
+                    ics_text(&format!("#{}: {}", ticket.id, ticket.summary))
+                ),
+                format!("URL:{}", TracTicket::url(ticket.id, trac)),
+            ]);
+        }
+    }
+
+    let mut ics = String::new();
+    push_line(&mut ics, "BEGIN:VCALENDAR");
+    push_line(&mut ics, "VERSION:2.0");
+    push_line(
+        &mut ics,
+        &format!(
+            "PRODID:-//{}//{}//EN",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION")
+        ),
+    );
+    push_line(&mut ics, "CALSCALE:GREGORIAN");
+    if let Some(name) = &options.calendar_name {
+        push_line(&mut ics, &format!("X-WR-CALNAME:{}", ics_text(name)));
+    }
+    for event in events {
+        push_line(&mut ics, "BEGIN:VEVENT");
+        for line in event {
+            push_line(&mut ics, &line);
+        }
+        push_line(&mut ics, "END:VEVENT");
+    }
+    push_line(&mut ics, "END:VCALENDAR");
+    Ok(ics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn escapes_text_values() {
+        assert_eq!(ics_text("a\\b; c, d\r\ne"), r"a\\b\; c\, d\ne");
+    }
+
+    #[test]
+    fn folds_long_lines() {
+        let mut ics = String::new();
+        push_line(&mut ics, "SHORT:ok");
+        assert_eq!(ics, "SHORT:ok\r\n");
+
+        let mut ics = String::new();
+        let line = format!("SUMMARY:{}", "x".repeat(150));
+        push_line(&mut ics, &line);
+        let lines: Vec<&str> = ics.trim_end_matches("\r\n").split("\r\n").collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines.iter().all(|l| l.len() <= 75));
+        assert!(lines[1..].iter().all(|l| l.starts_with(' ')));
+        assert_eq!(ics.replace("\r\n ", ""), format!("{}\r\n", line));
+    }
+
+    #[test]
+    fn folds_between_characters() {
+        let mut ics = String::new();
+        push_line(&mut ics, &format!("SUMMARY:{}", "é".repeat(60)));
+        for line in ics.split("\r\n") {
+            assert!(line.len() <= 75, "{} octets", line.len());
+        }
+        assert_eq!(
+            ics.replace("\r\n ", ""),
+            format!("SUMMARY:{}\r\n", "é".repeat(60))
+        );
+    }
+
+    #[test]
+    fn formats_dates() {
+        let t = time::parse_date("2026-10-14").unwrap();
+        assert_eq!(ics_datetime(t), "20261014T000000Z");
+        assert_eq!(ics_date(t), "20261014");
+    }
+
+    #[test]
+    fn links_milestones() {
+        let trac = testing::offline(testing::config());
+        assert_eq!(
+            milestone_url("1.0 beta", &trac).unwrap(),
+            "https://127.0.0.1:1/trac/milestone/1.0%20beta"
+        );
+    }
+}
@@ -0,0 +1,284 @@
+use crate::chat::escape_markdown;
+use crate::{Trac, TracTicket};
+
+/// A rendering style for tickets, so output can be switched as a whole
+/// rather than by picking among `fmt_*` methods.
+pub trait TicketFormatter {
+    /// One line per ticket.
+    fn format_terse(&self, ticket: &TracTicket) -> String;
+
+    /// The ticket with its description.
+    fn format_detail(&self, ticket: &TracTicket) -> String;
+
+    fn format_list(&self, tickets: &[TracTicket]) -> String {
+        tickets
+            .iter()
+            .map(|t| self.format_terse(t))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// The `fmt_terse` and `fmt_detail` output.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PlainFormatter;
+
+impl TicketFormatter for PlainFormatter {
+    fn format_terse(&self, ticket: &TracTicket) -> String {
+        ticket.fmt_terse()
+    }
+
+    fn format_detail(&self, ticket: &TracTicket) -> String {
+        ticket.fmt_detail()
+    }
+}
+
+/// Markdown, with ticket ids linked if built with `linked`. Lists are
+/// rendered as a table.
+#[derive(Debug, Clone, Default)]
+pub struct MarkdownFormatter {
+    // Ticket URLs are this followed by the id.
+    ticket_url: Option<String>,
+}
+
+impl MarkdownFormatter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn linked(trac: &Trac) -> Self {
+        Self {
+            ticket_url: Some(format!("{}ticket/", trac.url())),
+        }
+    }
+
+    fn id(&self, ticket: &TracTicket) -> String {
+        match &self.ticket_url {
+            Some(url) => format!("[#{}]({}{})", ticket.id, url, ticket.id),
+            None => format!("#{}", ticket.id),
+        }
+    }
+}
+
+impl TicketFormatter for MarkdownFormatter {
+    fn format_terse(&self, ticket: &TracTicket) -> String {
+        format!(
+            "{} {} ({}, {})",
+            self.id(ticket),
+            escape_markdown(&ticket.summary),
+            escape_markdown(&ticket.status),
+            escape_markdown(&ticket.owner)
+        )
+    }
+
+    fn format_detail(&self, ticket: &TracTicket) -> String {
+        let mut out = format!(
+            "## {} {}\n\n",
+            self.id(ticket),
+            escape_markdown(&ticket.summary)
+        );
+        for (label, value) in [
+            ("Status", &ticket.status),
+            ("Owner", &ticket.owner),
+            ("Reviewer", &ticket.reviewer),
+            ("Milestone", &ticket.milestone),
+        ] {
+            if !value.is_empty() {
+                out.push_str(&format!("- **{}:** {}\n", label, escape_markdown(value)));
+            }
+        }
+        // The description is Trac wiki text, close enough to Markdown to
+        // pass through as it is.
+        out.push_str(&format!("\n{}", ticket.description.trim_end()));
+        out
+    }
+
+    fn format_list(&self, tickets: &[TracTicket]) -> String {
+        let mut out = String::from("| Ticket | Summary | Status | Owner | Milestone |\n");
+        out.push_str("|---|---|---|---|---|");
+        for ticket in tickets {
+            out.push_str(&format!(
+                "\n| {} | {} | {} | {} | {} |",
+                self.id(ticket),
+                escape_markdown(&ticket.summary),
+                escape_markdown(&ticket.status),
+                escape_markdown(&ticket.owner),
+                escape_markdown(&ticket.milestone)
+            ));
+        }
+        out
+    }
+}
+
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+/// Plain text with ANSI colours for terminals, the status coloured by
+/// how far along the ticket is. Closed statuses are just `closed` unless
+/// built with `for_trac`.
+#[derive(Debug, Clone)]
+pub struct ColorFormatter {
+    closed_statuses: Vec<String>,
+}
+
+impl Default for ColorFormatter {
+    fn default() -> Self {
+        Self {
+            closed_statuses: vec!["closed".to_string()],
+        }
+    }
+}
+
+impl ColorFormatter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Colours the statuses in `TracConfig::closed_statuses` as closed.
+    pub fn for_trac(trac: &Trac) -> Self {
+        Self {
+            closed_statuses: trac.config.closed_statuses.clone(),
+        }
+    }
+
+    fn status_color(&self, status: &str) -> &'static str {
+        match status {
+            _ if self.closed_statuses.iter().any(|s| s == status) => "\x1b[32m",
+            "new" | "reopened" => "\x1b[36m",
+            "assigned" | "accepted" => "\x1b[33m",
+            _ => "\x1b[35m",
+        }
+    }
+}
+
+impl TicketFormatter for ColorFormatter {
+    fn format_terse(&self, ticket: &TracTicket) -> String {
+        format!(
+            "{}#{}{} {} {}{}{} {}o: {}, r: {}, m: {}{}",
+            BOLD,
+            ticket.id,
+            RESET,
+            ticket.summary,
+            self.status_color(&ticket.status),
+            ticket.status,
+            RESET,
+            DIM,
+            ticket.owner,
+            ticket.reviewer,
+            ticket.milestone,
+            RESET
+        )
+    }
+
+    fn format_detail(&self, ticket: &TracTicket) -> String {
+        format!("{}\n\n{}", self.format_terse(ticket), ticket.description)
+    }
+}
+
+/// Output from `fmt_template` templates, one for single lines and lists
+/// and optionally another for the detailed view.
+#[derive(Debug, Clone)]
+pub struct TemplateFormatter {
+    pub terse: String,
+    pub detail: Option<String>,
+}
+
+impl TemplateFormatter {
+    pub fn new(terse: &str) -> Self {
+        Self {
+            terse: terse.to_string(),
+            detail: None,
+        }
+    }
+
+    pub fn detail(mut self, template: &str) -> Self {
+        self.detail = Some(template.to_string());
+        self
+    }
+}
+
+impl TicketFormatter for TemplateFormatter {
+    fn format_terse(&self, ticket: &TracTicket) -> String {
+        ticket.fmt_template(&self.terse)
+    }
+
+    fn format_detail(&self, ticket: &TracTicket) -> String {
+        ticket.fmt_template(self.detail.as_ref().unwrap_or(&self.terse))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn ticket() -> TracTicket {
+        testing::ticket(
+            42,
+            &[
+                ("summary", "Crash on a|b *input*"),
+                ("status", "accepted"),
+                ("owner", "alice"),
+                ("reviewer", ""),
+                ("milestone", "1.0"),
+                ("priority", "major"),
+                ("description", "It crashes.\n"),
+            ],
+        )
+    }
+
+    #[test]
+    fn formats_markdown() {
+        let plain = MarkdownFormatter::new();
+        assert_eq!(
+            plain.format_terse(&ticket()),
+            r"#42 Crash on a\|b \*input\* (accepted, alice)"
+        );
+        assert_eq!(
+            plain.format_detail(&ticket()),
+            "## #42 Crash on a\\|b \\*input\\*\n\n\
+             - **Status:** accepted\n- **Owner:** alice\n- **Milestone:** 1.0\n\n\
+             It crashes."
+        );
+
+        let linked = MarkdownFormatter::linked(&testing::offline(testing::config()));
+        assert_eq!(
+            linked.format_list(&[ticket()]),
+            "| Ticket | Summary | Status | Owner | Milestone |\n\
+             |---|---|---|---|---|\n\
+             | [#42](https://127.0.0.1:1/trac/ticket/42) | Crash on a\\|b \\*input\\* \
+             | accepted | alice | 1.0 |"
+        );
+    }
+
+    #[test]
+    fn formats_colors() {
+        assert_eq!(
+            ColorFormatter::new().format_terse(&ticket()),
+            "\x1b[1m#42\x1b[0m Crash on a|b *input* \x1b[33maccepted\x1b[0m \
+             \x1b[2mo: alice, r: , m: 1.0\x1b[0m"
+        );
+        let mut config = testing::config();
+        config.closed_statuses = vec!["accepted".to_string()];
+        let formatter = ColorFormatter::for_trac(&testing::offline(config));
+        assert!(formatter
+            .format_detail(&ticket())
+            .contains("\x1b[32maccepted\x1b[0m"));
+    }
+
+    #[test]
+    fn formats_templates() {
+        let formatter =
+            TemplateFormatter::new("{id}\t{status}\t{priority}").detail("{{{id}}} {summary}");
+        assert_eq!(formatter.format_terse(&ticket()), "42\taccepted\tmajor");
+        assert_eq!(
+            formatter.format_detail(&ticket()),
+            "{42} Crash on a|b *input*"
+        );
+        assert_eq!(
+            formatter.format_list(&[ticket(), testing::ticket(7, &[("status", "new")])]),
+            "42\taccepted\tmajor\n7\tnew\t"
+        );
+    }
+}
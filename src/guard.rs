@@ -0,0 +1,270 @@
+use std::collections::BTreeMap;
+
+use regex::Regex;
+
+use crate::{FieldValue, QueryCondition, TicketQuery, Trac, TracAction, TracError, TracTicket};
+
+/// One requirement a ticket must meet before a guarded action is applied.
+#[derive(Debug, Clone)]
+pub enum GuardRule {
+    /// The field must not be empty.
+    FieldSet(String),
+    /// The ticket must match the condition.
+    Matches(QueryCondition),
+    /// At least one comment must match the pattern.
+    Comment(Regex),
+}
+
+impl GuardRule {
+    fn describe(&self) -> String {
+        match self {
+            GuardRule::FieldSet(field) => format!("{} must be set", field),
+            GuardRule::Matches(condition) => format!(
+                "the ticket must match {}",
+                TicketQuery {
+                    conditions: vec![condition.clone()],
+                    ..TicketQuery::default()
+                }
+            ),
+            GuardRule::Comment(pattern) => {
+                format!("a comment matching '{}' is required", pattern.as_str())
+            }
+        }
+    }
+}
+
+/// Rules checked before an action is sent, read from a `[guard:<name>]`
+/// section:
+///
+/// ```ini
+/// [guard:review-before-close]
+/// action = close
+/// require = reviewer
+/// comment = (?i)review passed
+///
+/// [guard:milestone-for-review]
+/// action = request_review
+/// match = milestone=!
+/// ```
+///
+/// `action` is a workflow action name or a `WorkflowProfile` operation.
+/// `require` lists fields that must be set, `match` is a query string the
+/// ticket must match, and `comment` a pattern one of its comments must
+/// match. Rules see the fields as they will be after the update.
+#[derive(Debug, Clone)]
+pub struct TransitionGuard {
+    pub name: String,
+    pub action: String,
+    pub rules: Vec<GuardRule>,
+}
+
+impl TransitionGuard {
+    pub fn new(name: &str, action: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            action: action.to_string(),
+            rules: vec![],
+        }
+    }
+
+    pub fn require(mut self, field: &str) -> Self {
+        self.rules.push(GuardRule::FieldSet(field.to_string()));
+        self
+    }
+
+    pub fn matching(mut self, condition: QueryCondition) -> Self {
+        self.rules.push(GuardRule::Matches(condition));
+        self
+    }
+
+    pub fn comment(mut self, pattern: Regex) -> Self {
+        self.rules.push(GuardRule::Comment(pattern));
+        self
+    }
+
+    pub(crate) fn from_section(
+        name: &str,
+        section: &BTreeMap<String, String>,
+    ) -> Result<Self, TracError> {
+        let action = section.get("action").ok_or_else(|| {
+            TracError::Config(format!("config is missing [guard:{}] action", name))
+        })?;
+        let mut guard = Self::new(name, action);
+        for (key, value) in section {
+            match key.as_str() {
+                "action" => continue,
+                "require" => {
+                    for field in value.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+                        guard = guard.require(field);
+                    }
+                }
+                "match" => {
+                    for condition in TicketQuery::parse(value)?.conditions {
+                        guard = guard.matching(condition);
+                    }
+                }
+                "comment" => {
+                    let pattern = Regex::new(value).map_err(|e| {
+                        TracError::Config(format!("invalid [guard:{}] comment: {}", name, e))
+                    })?;
+                    guard = guard.comment(pattern);
+                }
+                _ => {
+                    return Err(TracError::Config(format!(
+                        "unknown key '{}' in [guard:{}]",
+                        key, name
+                    )))
+                }
+            }
+        }
+        Ok(guard)
+    }
+
+    fn applies_to(&self, action: &str, trac: &Trac) -> bool {
+        self.action == action || trac.config.workflow.operation(&self.action) == Some(action)
+    }
+}
+
+impl Trac {
+    pub(crate) fn guards_for<'a>(
+        &'a self,
+        action: &'a str,
+    ) -> impl Iterator<Item = &'a TransitionGuard> + 'a {
+        self.config
+            .guards
+            .iter()
+            .filter(move |g| g.applies_to(action, self))
+    }
+}
+
+impl TracTicket {
+    /// Checks the guards for `action` against the ticket with `attributes`
+    /// applied, and refuses the action with every unmet rule if any fail.
+    pub(crate) fn check_guards(
+        &self,
+        action: &TracAction,
+        attributes: &[(String, FieldValue)],
+        trac: &Trac,
+    ) -> Result<(), TracError> {
+        let guards: Vec<&TransitionGuard> = trac.guards_for(&action.name).collect();
+        if guards.is_empty() {
+            return Ok(());
+        }
+
+        let mut fields = self.fields.clone();
+        for (name, value) in attributes {
+            fields.insert(name.to_owned(), value.to_string());
+        }
+        let field = |name: &str| match name {
+            "id" => self.id.to_string(),
+            _ => fields.get(name).cloned().unwrap_or_default(),
+        };
+
+        let mut comments = None;
+        let mut violations = vec![];
+        for rule in guards.iter().flat_map(|g| g.rules.iter()) {
+            let met = match rule {
+                GuardRule::FieldSet(name) => !field(name).trim().is_empty(),
+                GuardRule::Matches(condition) => condition.matches_value(&field(&condition.field)),
+                GuardRule::Comment(pattern) => {
+                    if comments.is_none() {
+                        comments = Some(self.comments(trac)?);
+                    }
+                    comments.iter().flatten().any(|c| pattern.is_match(&c.text))
+                }
+            };
+            if !met {
+                violations.push(rule.describe());
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(TracError::TransitionDenied {
+                action: action.name.to_owned(),
+                violations,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn section(entries: &[(&str, &str)]) -> BTreeMap<String, String> {
+        entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn guarded(guard: TransitionGuard) -> Trac {
+        let mut config = testing::config();
+        config.guards.push(guard);
+        testing::offline(config)
+    }
+
+    #[test]
+    fn parses_section() {
+        let guard = TransitionGuard::from_section(
+            "g",
+            &section(&[
+                ("action", "close"),
+                ("require", "reviewer, tester"),
+                ("match", "milestone=!"),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(guard.action, "close");
+        assert_eq!(guard.rules.len(), 3);
+    }
+
+    #[test]
+    fn rejects_unknown_keys_and_missing_action() {
+        assert!(TransitionGuard::from_section("g", &section(&[("require", "x")])).is_err());
+        assert!(TransitionGuard::from_section(
+            "g",
+            &section(&[("action", "close"), ("colour", "red")])
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn reports_every_unmet_rule() {
+        let trac = guarded(
+            TransitionGuard::new("g", "resolve")
+                .require("reviewer")
+                .require("tester"),
+        );
+        let ticket = testing::ticket(1, &[("reviewer", "bob")]);
+        match ticket.check_guards(&TracAction::new("resolve"), &[], &trac) {
+            Err(TracError::TransitionDenied { action, violations }) => {
+                assert_eq!(action, "resolve");
+                assert_eq!(violations, vec!["tester must be set"]);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn sees_fields_as_updated() {
+        let trac = guarded(TransitionGuard::new("g", "resolve").require("reviewer"));
+        let ticket = testing::ticket(1, &[]);
+        let attributes = vec![("reviewer".to_string(), FieldValue::from("bob"))];
+        assert!(ticket
+            .check_guards(&TracAction::new("resolve"), &attributes, &trac)
+            .is_ok());
+    }
+
+    #[test]
+    fn ignores_other_actions() {
+        let trac = guarded(TransitionGuard::new("g", "resolve").require("reviewer"));
+        let ticket = testing::ticket(1, &[]);
+        assert!(ticket
+            .check_guards(&TracAction::new("leave"), &[], &trac)
+            .is_ok());
+    }
+}
@@ -0,0 +1,103 @@
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+use reqwest::StatusCode;
+use xmlrpc::Request;
+
+use crate::transport::StatusTransport;
+use crate::Trac;
+
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TracHealth {
+    /// The server answered over HTTP at all.
+    pub reachable: bool,
+    /// Whether the server accepted the configured credentials: true if it
+    /// answered successfully, false if it refused them with 401 or 403, and
+    /// `None` if the response does not tell, e.g. no response or a 500.
+    pub authenticated: Option<bool>,
+    /// The XML-RPC plugin's `[epoch, major, minor]` API version.
+    pub api_version: Option<(i32, i32, i32)>,
+    pub latency: Duration,
+    pub error: Option<String>,
+}
+
+fn authenticated(status: StatusCode) -> Option<bool> {
+    match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Some(false),
+        _ if status.is_success() => Some(true),
+        _ => None,
+    }
+}
+
+impl TracHealth {
+    pub fn is_healthy(&self) -> bool {
+        self.reachable && self.authenticated == Some(true) && self.api_version.is_some()
+    }
+}
+
+impl Trac {
+    /// Checks that the server can be reached and accepts our credentials by
+    /// calling `system.getAPIVersion` with a short timeout, unless
+    /// `with_call_options` gives it another.
+    pub fn ping(&self) -> TracHealth {
+        let status = Cell::new(None);
+        let timeout = match &*self.call_options.borrow() {
+            Some(options) => options.timeout.unwrap_or(PING_TIMEOUT),
+            None => PING_TIMEOUT,
+        };
+        let transport = StatusTransport::new(self.get_transport().timeout(timeout), &status);
+        let started = Instant::now();
+        let result = Request::new("system.getAPIVersion").call(transport);
+        let latency = started.elapsed();
+
+        let status = status.get();
+        let reachable = status.is_some();
+        let authenticated = status.and_then(authenticated);
+
+        match result {
+            Ok(r) => {
+                let part = |i: usize| r.get(i).and_then(|v| v.as_i32()).unwrap_or(0);
+                TracHealth {
+                    reachable,
+                    authenticated,
+                    api_version: Some((part(0), part(1), part(2))),
+                    latency,
+                    error: None,
+                }
+            }
+            Err(e) => TracHealth {
+                reachable,
+                authenticated,
+                api_version: None,
+                latency,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn reads_authentication_from_the_status() {
+        assert_eq!(authenticated(StatusCode::OK), Some(true));
+        assert_eq!(authenticated(StatusCode::UNAUTHORIZED), Some(false));
+        assert_eq!(authenticated(StatusCode::FORBIDDEN), Some(false));
+        assert_eq!(authenticated(StatusCode::NOT_FOUND), None);
+        assert_eq!(authenticated(StatusCode::INTERNAL_SERVER_ERROR), None);
+    }
+
+    #[test]
+    fn unreachable_servers_are_unhealthy() {
+        let health = testing::offline(testing::config()).ping();
+        assert!(!health.reachable);
+        assert_eq!(health.authenticated, None);
+        assert!(health.error.is_some());
+        assert!(!health.is_healthy());
+    }
+}
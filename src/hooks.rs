@@ -0,0 +1,135 @@
+use std::fmt;
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+use crate::{Trac, TracError};
+
+/// What a commit message must satisfy.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommitPolicy {
+    /// The message has to mention at least one ticket.
+    pub require_reference: bool,
+    /// Every ticket mentioned has to be open.
+    pub require_open: bool,
+    /// If set, every ticket mentioned has to be owned by this user.
+    pub committer: Option<String>,
+}
+
+impl Default for CommitPolicy {
+    fn default() -> Self {
+        Self {
+            require_reference: true,
+            require_open: true,
+            committer: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommitViolation {
+    NoTicketReference,
+    NoSuchTicket(i32),
+    TicketClosed(i32),
+    NotOwner { ticket: i32, owner: String },
+}
+
+impl fmt::Display for CommitViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommitViolation::NoTicketReference => write!(f, "the message refers to no ticket"),
+            CommitViolation::NoSuchTicket(id) => write!(f, "ticket #{} does not exist", id),
+            CommitViolation::TicketClosed(id) => write!(f, "ticket #{} is closed", id),
+            CommitViolation::NotOwner { ticket, owner } if owner.is_empty() => {
+                write!(f, "ticket #{} has no owner", ticket)
+            }
+            CommitViolation::NotOwner { ticket, owner } => {
+                write!(f, "ticket #{} is owned by {}", ticket, owner)
+            }
+        }
+    }
+}
+
+/// The tickets a commit message refers to, as `#123` or `ticket:123`, in
+/// order of first mention.
+pub fn commit_message_refs(text: &str) -> Vec<i32> {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    let pattern = PATTERN.get_or_init(|| Regex::new(r"(?:^|[^\w&/])(?:#|ticket:)(\d+)\b").unwrap());
+    let mut refs = vec![];
+    for captures in pattern.captures_iter(text) {
+        if let Ok(id) = captures[1].parse() {
+            if !refs.contains(&id) {
+                refs.push(id);
+            }
+        }
+    }
+    refs
+}
+
+/// Checks a commit message against `policy`, returning every violation
+/// found; an empty list means the commit may go ahead.
+pub fn validate_commit_message(
+    text: &str,
+    policy: &CommitPolicy,
+    trac: &Trac,
+) -> Result<Vec<CommitViolation>, TracError> {
+    let refs = commit_message_refs(text);
+    if refs.is_empty() {
+        return Ok(if policy.require_reference {
+            vec![CommitViolation::NoTicketReference]
+        } else {
+            vec![]
+        });
+    }
+
+    let tickets = trac.get_tickets(&refs)?;
+    let mut violations = vec![];
+    for id in refs {
+        let ticket = match tickets.iter().find(|t| t.id == id) {
+            Some(ticket) => ticket,
+            None => {
+                violations.push(CommitViolation::NoSuchTicket(id));
+                continue;
+            }
+        };
+        if policy.require_open && ticket.is_closed(trac) {
+            violations.push(CommitViolation::TicketClosed(id));
+        }
+        if let Some(committer) = &policy.committer {
+            if &ticket.owner != committer {
+                violations.push(CommitViolation::NotOwner {
+                    ticket: id,
+                    owner: ticket.owner.to_owned(),
+                });
+            }
+        }
+    }
+    Ok(violations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_references_in_order() {
+        assert_eq!(commit_message_refs("Fix #12"), [12]);
+        assert_eq!(commit_message_refs("See ticket:12."), [12]);
+        assert_eq!(
+            commit_message_refs("Fix #3 and #12, see ticket:3 and (#7)\n#12 again"),
+            [3, 12, 7]
+        );
+    }
+
+    #[test]
+    fn ignores_lookalikes() {
+        for text in [
+            "Escape &#123; properly",
+            "Merge branch a/#1",
+            "Rename foo#1",
+            "Nothing here",
+        ] {
+            assert_eq!(commit_message_refs(text), Vec::<i32>::new(), "{}", text);
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use crate::{check_query_value, escape_query_value, Trac, TracError, TracTicket};
+
+const ESTIMATED_HOURS_FIELD: &str = "estimatedhours";
+const TOTAL_HOURS_FIELD: &str = "totalhours";
+const HOURS_FIELD: &str = "hours";
+
+/// Who is responsible for adding newly logged `hours` into `totalhours`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HoursTracking {
+    /// A server plugin (e.g. timingandestimation) accumulates `hours` into
+    /// `totalhours` when the ticket is saved.
+    Plugin,
+    /// Nothing on the server does, so the client updates `totalhours` too.
+    Client,
+}
+
+impl HoursTracking {
+    pub(crate) fn from_name(name: &str) -> Result<Self, TracError> {
+        match name {
+            "plugin" => Ok(HoursTracking::Plugin),
+            "client" => Ok(HoursTracking::Client),
+            _ => Err(TracError::Config(format!(
+                "unknown hours_tracking mode '{}'",
+                name
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct HoursSummary {
+    pub tickets: usize,
+    pub estimated: f64,
+    pub total: f64,
+    /// Estimated hours not yet spent, counting overruns as zero.
+    pub remaining: f64,
+}
+
+fn parse_hours(value: &str) -> f64 {
+    value.trim().parse().unwrap_or(0.0)
+}
+
+impl TracTicket {
+    pub fn estimated_hours(&self) -> f64 {
+        parse_hours(self.field(ESTIMATED_HOURS_FIELD))
+    }
+
+    pub fn total_hours(&self) -> f64 {
+        parse_hours(self.field(TOTAL_HOURS_FIELD))
+    }
+
+    /// Hours entered in the current change, before the plugin folds them
+    /// into the total.
+    pub fn hours(&self) -> f64 {
+        parse_hours(self.field(HOURS_FIELD))
+    }
+
+    pub fn set_estimated_hours(&self, hours: f64, trac: &Trac) -> Result<TracTicket, TracError> {
+        self.update_builder()
+            .set(ESTIMATED_HOURS_FIELD, &hours.to_string())
+            .submit(trac)
+    }
+
+    /// Records `hours` of work on the ticket.
+    pub fn add_hours(
+        &self,
+        hours: f64,
+        comment: Option<String>,
+        trac: &Trac,
+    ) -> Result<TracTicket, TracError> {
+        let mut update = self.update_builder().set(HOURS_FIELD, &hours.to_string());
+
+        if trac.config.hours_tracking == HoursTracking::Client {
+            // Re-read the total so concurrent additions are not lost.
+            let current = trac.get_ticket(self.id)?;
+            update = update.set(
+                TOTAL_HOURS_FIELD,
+                &(current.total_hours() + hours).to_string(),
+            );
+        }
+        if let Some(c) = comment {
+            update = update.comment(&c);
+        }
+
+        update.submit(trac)
+    }
+}
+
+impl HoursSummary {
+    pub fn add(&mut self, ticket: &TracTicket) {
+        let estimated = ticket.estimated_hours();
+        let total = ticket.total_hours();
+
+        self.tickets += 1;
+        self.estimated += estimated;
+        self.total += total;
+        self.remaining += (estimated - total).max(0.0);
+    }
+}
+
+impl Trac {
+    pub fn milestone_hours(&self, milestone: &str) -> Result<HoursSummary, TracError> {
+        let mut summary = HoursSummary::default();
+        check_query_value(milestone)?;
+        let ids = self.query(&format!("milestone={}", escape_query_value(milestone)))?;
+        for ticket in self.get_tickets(&ids)? {
+            summary.add(&ticket);
+        }
+        Ok(summary)
+    }
+}
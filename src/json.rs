@@ -0,0 +1,52 @@
+// Just enough JSON output for exports and webhook payloads.
+
+pub(crate) fn string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Builds a JSON object one member at a time, in the order given.
+#[derive(Debug, Default)]
+pub(crate) struct Object {
+    members: Vec<String>,
+}
+
+impl Object {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn raw(mut self, key: &str, json: String) -> Self {
+        self.members.push(format!("{}:{}", string(key), json));
+        self
+    }
+
+    pub(crate) fn str(self, key: &str, value: &str) -> Self {
+        self.raw(key, string(value))
+    }
+
+    pub(crate) fn num<N: ToString>(self, key: &str, value: N) -> Self {
+        self.raw(key, value.to_string())
+    }
+
+    pub(crate) fn bool(self, key: &str, value: bool) -> Self {
+        self.raw(key, value.to_string())
+    }
+
+    pub(crate) fn build(self) -> String {
+        format!("{{{}}}", self.members.join(","))
+    }
+}
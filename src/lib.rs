@@ -1,8 +1,123 @@
+use std::cell::RefCell;
 use std::collections::BTreeMap;
 use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use reqwest::blocking::{Client, RequestBuilder};
-use xmlrpc::{Request, Value};
+use xmlrpc::{Fault, Request, Value};
+
+use transport::{is_read_only, method_name};
+
+mod aging;
+mod attachments;
+mod auth;
+mod autocreate;
+mod builder;
+mod bulk;
+mod cancel;
+mod changelog;
+mod chat;
+mod comments;
+mod config;
+mod create;
+mod dedupe;
+mod delta;
+mod dependencies;
+mod duplicates;
+#[cfg(feature = "email")]
+mod email;
+mod endpoint;
+mod error;
+mod executor;
+mod export;
+mod formatter;
+mod guard;
+mod health;
+mod hooks;
+mod hours;
+mod json;
+mod mentions;
+mod qa;
+mod query;
+mod release;
+mod roadmap;
+#[cfg(feature = "search")]
+mod search;
+mod server_search;
+mod sla;
+mod snapshot;
+mod status;
+mod subtickets;
+mod sync;
+mod template;
+#[cfg(test)]
+mod testing;
+mod time;
+mod tls;
+mod transport;
+mod undo;
+mod update;
+mod value;
+mod watchlist;
+mod webhook;
+mod wiki;
+mod workflow;
+mod workload;
+mod worklog;
+
+pub use aging::{AgingReport, Interval, TicketAging};
+pub use attachments::{detect_mime_type, AttachmentSource, TracAttachment};
+pub use auth::CredentialProvider;
+pub use builder::TracBuilder;
+pub use bulk::{BulkOutcome, BulkStatus};
+pub use cancel::CancelToken;
+pub use changelog::{ChangelogStream, ChangelogWindows, TracChange};
+pub use comments::{CommentFilters, TracComment};
+pub use create::TicketCreateBuilder;
+pub use dedupe::DuplicateGuard;
+#[cfg(feature = "sqlite")]
+pub use delta::SqliteWatermark;
+pub use delta::{
+    DeltaSync, FileWatermark, MemoryWatermark, SyncDelta, WatermarkStore, DEFAULT_OVERLAP,
+};
+pub use dependencies::DependencyGraph;
+pub use duplicates::SimilarTicket;
+#[cfg(feature = "email")]
+pub use email::{EmailSettings, SmtpSecurity};
+pub use endpoint::RpcEndpoint;
+pub use error::TracError;
+pub use executor::BulkProgress;
+pub use export::{to_ics, IcsOptions};
+pub use formatter::{
+    ColorFormatter, MarkdownFormatter, PlainFormatter, TemplateFormatter, TicketFormatter,
+};
+pub use guard::{GuardRule, TransitionGuard};
+pub use health::TracHealth;
+pub use hooks::{commit_message_refs, validate_commit_message, CommitPolicy, CommitViolation};
+pub use hours::{HoursSummary, HoursTracking};
+pub use mentions::{Mention, MentionScanner, MentionSource};
+pub use query::{QueryCondition, QueryOp, TicketQuery};
+pub use release::{NotesFormat, ReleaseNotesOptions};
+pub use roadmap::RoadmapEntry;
+#[cfg(feature = "search")]
+pub use search::{LocalIndex, SearchHit};
+pub use server_search::{SearchFilter, SearchResult, SearchTarget};
+pub use sla::{SlaCheck, SlaPolicy, SlaStatus, SlaSummary, SlaTarget};
+pub use snapshot::{ChangedTicket, FieldChange, QueryDelta, QuerySnapshot};
+pub use status::Status;
+pub use subtickets::TicketTree;
+pub use sync::{SyncService, SyncSnapshot};
+pub use template::TicketTemplate;
+pub use tls::{TlsOptions, TlsVersion};
+pub use transport::{CallOptions, CallPriority, RetryPolicy};
+pub use update::{TicketUpdateBuilder, UpdateOptions};
+pub use value::FieldValue;
+pub use watchlist::{WatchList, WatchUpdate};
+pub use webhook::{TicketEvent, TicketEventKind, Webhook, WebhookDelivery, WebhookDispatcher};
+pub use wiki::WikiChange;
+pub use workflow::WorkflowProfile;
+pub use workload::ReviewerLoad;
+pub use worklog::{format_worklog, parse_worklog, WorkLogEntry, WORKLOG_PREFIX};
 
 pub struct TracUser {
     pub username: String,
@@ -13,6 +128,64 @@ pub struct TracConfig {
     pub user: Rc<TracUser>,
     pub host: String,
     pub path: String,
+    pub workflow: WorkflowProfile,
+    pub templates: BTreeMap<String, TicketTemplate>,
+    pub parent_field: String,
+    pub hours_tracking: HoursTracking,
+    pub fields_ttl: Duration,
+    pub compression: bool,
+    pub pool: PoolOptions,
+    pub tls: TlsOptions,
+    /// The timeout and retries for calls not given their own.
+    pub call: CallOptions,
+    pub endpoint: RpcEndpoint,
+    /// How many calls go into each `system.multicall` request.
+    pub batch_size: usize,
+    /// How many tickets a bulk operation works on at once.
+    pub concurrency: usize,
+    /// Create milestones, components and versions that a new or updated
+    /// ticket refers to but the server does not have yet.
+    pub auto_create: bool,
+    /// A size limit in bytes that attachments are checked against before
+    /// uploading; `None`, the default, for none. The XML-RPC plugin neither
+    /// reports nor applies `trac.ini`'s `[attachment] max_size`, so set
+    /// this to match it if uploads should respect it.
+    pub attachment_max_size: Option<u64>,
+    /// Refuse attachments whose content, as `detect_mime_type` sees it,
+    /// disagrees with their extension, such as a `.png` that is not a PNG or
+    /// a `.txt` that is. Off by default, since some valid files, like an empty zip, fail it.
+    pub attachment_check_content: bool,
+    pub sla: Vec<SlaPolicy>,
+    /// Emoji shown before tickets in each status in chat messages.
+    pub status_emoji: BTreeMap<String, String>,
+    /// The statuses a ticket is closed in; Trac itself only has `closed`.
+    pub closed_statuses: Vec<String>,
+    pub reviewers: Vec<TracReviewer>,
+    /// Checked before any workflow action is sent.
+    pub guards: Vec<TransitionGuard>,
+    pub webhooks: Vec<Webhook>,
+    #[cfg(feature = "email")]
+    pub email: Option<EmailSettings>,
+}
+
+/// Connection reuse settings for the shared HTTP client.
+#[derive(Debug, Clone)]
+pub struct PoolOptions {
+    pub max_idle_per_host: usize,
+    /// How long an unused connection is kept; `None` keeps it indefinitely.
+    pub idle_timeout: Option<Duration>,
+    /// Interval for TCP keepalive probes; `None` disables them.
+    pub tcp_keepalive: Option<Duration>,
+}
+
+impl Default for PoolOptions {
+    fn default() -> Self {
+        Self {
+            max_idle_per_host: usize::MAX,
+            idle_timeout: Some(Duration::from_secs(90)),
+            tcp_keepalive: None,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -22,19 +195,77 @@ pub struct TracReviewer {
     pub email: String,
 }
 
-#[derive(Debug)]
+/// Whether `request_review` emailed the reviewer. A failed email does not
+/// fail the request, since the ticket has already been sent for review.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReviewNotification {
+    /// No email was due: the `email` feature or `[email]` settings are
+    /// missing, or the reviewer is not configured.
+    NotSent,
+    /// Emailed to this address.
+    Sent(String),
+    Failed(TracError),
+}
+
+#[derive(Debug, Clone)]
+pub struct TracActionInput {
+    pub name: String,
+    pub value: String,
+    pub options: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
 pub struct TracAction {
     pub name: String,
     pub description: String,
+    pub inputs: Vec<TracActionInput>,
 }
 
 impl TracAction {
-    fn new(name: &str) -> Self {
+    pub fn new(name: &str) -> Self {
         Self {
             name: name.to_string(),
             description: "".to_string(),
+            inputs: vec![],
+        }
+    }
+
+    pub(crate) fn from_value(item: &Value) -> Self {
+        let mut inputs: Vec<TracActionInput> = Vec::new();
+        if let Some(input_fields) = item[3].as_array() {
+            for input in input_fields {
+                let options = match input[2].as_array() {
+                    Some(o) => o.iter().map(val_to_string).collect(),
+                    None => vec![],
+                };
+                inputs.push(TracActionInput {
+                    name: val_to_string(&input[0]),
+                    value: val_to_string(&input[1]),
+                    options,
+                })
+            }
+        }
+
+        TracAction {
+            name: val_to_string(&item[0]),
+            description: val_to_string(&item[1]),
+            inputs,
         }
     }
+
+    /// Sets the value sent for one of the action's input fields, such as
+    /// `action_resolve_resolve_resolution` for stock Trac's `resolve`.
+    pub fn with_input(mut self, name: &str, value: &str) -> Self {
+        match self.inputs.iter_mut().find(|i| i.name == name) {
+            Some(input) => input.value = value.to_string(),
+            None => self.inputs.push(TracActionInput {
+                name: name.to_string(),
+                value: value.to_string(),
+                options: vec![],
+            }),
+        }
+        self
+    }
 }
 
 #[derive(Debug)]
@@ -44,6 +275,7 @@ pub struct TracUpdateAttributes {
 }
 
 impl TracUpdateAttributes {
+    #[allow(dead_code)]
     fn new(action: TracAction) -> Self {
         Self {
             action: action.name,
@@ -52,8 +284,8 @@ impl TracUpdateAttributes {
     }
 }
 
-#[derive(Debug)]
-enum TracTicketFieldType {
+#[derive(Debug, Clone, PartialEq)]
+pub enum TracTicketFieldType {
     DropDown,
     String,
     Integer,
@@ -62,28 +294,45 @@ enum TracTicketFieldType {
     Boolean,
 }
 
-#[derive(Debug)]
-struct TracTicketField {
-    name: String,
-    field_type: TracTicketFieldType,
-    options: Option<Vec<String>>,
-    default: Option<String>,
+#[derive(Debug, Clone)]
+pub struct TracTicketField {
+    pub name: String,
+    /// The name shown in the web UI, such as `Component`.
+    pub label: String,
+    pub field_type: TracTicketFieldType,
+    pub options: Option<Vec<String>>,
+    pub default: Option<String>,
+    /// The `order` setting of a custom field; `None` for built-in fields.
+    pub order: Option<i32>,
 }
 
-#[derive(Debug)]
-struct TracTicketFieldSet {
-    fields: Vec<TracTicketField>,
+#[derive(Debug, Clone)]
+pub struct TracTicketFieldSet {
+    pub fields: Vec<TracTicketField>,
 }
 
 impl TracTicketFieldSet {
-    fn get(trac: &Trac) -> Result<Self, ()> {
-        let transport = trac.get_transport();
+    pub fn field(&self, name: &str) -> Option<&TracTicketField> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+
+    /// The fields in the order the web UI shows them: built-in fields as
+    /// the server lists them, then custom fields by their `order`.
+    pub fn in_display_order(&self) -> Vec<&TracTicketField> {
+        let mut fields: Vec<&TracTicketField> = self.fields.iter().collect();
+        fields.sort_by_key(|f| f.order.map_or((false, 0), |order| (true, order)));
+        fields
+    }
+
+    fn get(trac: &Trac) -> Result<Self, TracError> {
         let xmlrpc_req = Request::new("ticket.getTicketFields");
 
-        match xmlrpc_req.call(transport) {
+        match trac.call(&xmlrpc_req) {
             Ok(r) => {
                 let mut fields: Vec<TracTicketField> = Vec::new();
-                let result = r.as_array().expect("XMLRPC result was not an array.");
+                let result = r
+                    .as_array()
+                    .ok_or_else(|| TracError::invalid_response("ticket.getTicketFields"))?;
                 for field_val in result {
                     if let Some(field_meta) = field_val.as_struct() {
                         if let Value::String(field_name) = &field_meta["name"] {
@@ -96,47 +345,57 @@ impl TracTicketFieldSet {
                                     _ => continue,
                                 };
 
-                                let field_default =
-                                    if let Some((k, val)) = field_meta.get_key_value("default") {
-                                        match val {
-                                            Value::String(ref d) => {
-                                                if d != "" {
-                                                    Some(d.to_owned())
-                                                } else {
-                                                    None
-                                                }
+                                let field_default = if let Some(val) = field_meta.get("default") {
+                                    match val {
+                                        Value::String(ref d) => {
+                                            if !d.is_empty() {
+                                                Some(d.to_owned())
+                                            } else {
+                                                None
                                             }
-                                            _ => None,
                                         }
-                                    } else {
-                                        None
-                                    };
-
-                                let field_options =
-                                    if let Some((k, val)) = field_meta.get_key_value("options") {
-                                        match val {
-                                            Value::Array(o) => {
-                                                let options = o
-                                                    .iter()
-                                                    .map(|v| {
-                                                        v.as_str()
-                                                            .expect("value not a string")
-                                                            .to_string()
-                                                    })
-                                                    .collect();
-                                                Some(options)
-                                            }
-                                            _ => None,
+                                        _ => None,
+                                    }
+                                } else {
+                                    None
+                                };
+
+                                let field_options = if let Some(val) = field_meta.get("options") {
+                                    match val {
+                                        Value::Array(o) => {
+                                            let options = o
+                                                .iter()
+                                                .map(|v| {
+                                                    v.as_str()
+                                                        .expect("value not a string")
+                                                        .to_string()
+                                                })
+                                                .collect();
+                                            Some(options)
                                         }
-                                    } else {
-                                        None
-                                    };
+                                        _ => None,
+                                    }
+                                } else {
+                                    None
+                                };
+
+                                let label = match field_meta.get("label") {
+                                    Some(Value::String(label)) => label.to_owned(),
+                                    _ => field_name.to_owned(),
+                                };
+                                let order = match field_meta.get("order") {
+                                    Some(Value::Int(order)) => Some(*order),
+                                    Some(Value::String(order)) => order.trim().parse().ok(),
+                                    _ => None,
+                                };
 
                                 fields.push(TracTicketField {
                                     name: field_name.to_owned(),
-                                    field_type: field_type,
+                                    label,
+                                    field_type,
                                     options: field_options,
                                     default: field_default,
+                                    order,
                                 });
                             }
                         }
@@ -145,10 +404,7 @@ impl TracTicketFieldSet {
 
                 Ok(TracTicketFieldSet { fields })
             }
-            Err(e) => {
-                eprintln!("\nError: {}\n", e);
-                Err(())
-            }
+            Err(e) => Err(e),
         }
     }
 }
@@ -167,12 +423,61 @@ pub struct TracTicket {
     pub status: String,
     pub reviewer: String,
     pub resolution: String,
+    pub created: SystemTime,
+    pub changed: SystemTime,
+    pub fields: BTreeMap<String, String>,
 }
 
 fn val_to_string(val: &Value) -> String {
     val.as_str().unwrap().to_string()
 }
 
+// Plugins store ticket references as "#12", "12" or a list such as "12, 14".
+pub(crate) fn parse_ticket_refs(value: &str) -> Vec<i32> {
+    value
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter_map(|r| r.trim_start_matches('#').parse().ok())
+        .collect()
+}
+
+// Values in a Trac query string must not contain unescaped separators.
+// Trac only treats a backslash before `&` or `|` as an escape, so other
+// backslashes are left alone.
+pub(crate) fn escape_query_value(value: &str) -> String {
+    value.replace('&', "\\&").replace('|', "\\|")
+}
+
+// Trac splits a query string on every `&` or `|` not after a backslash, so
+// a value ending in one would swallow the separator that follows it, and
+// there is no escape for that.
+pub(crate) fn check_query_value(value: &str) -> Result<(), TracError> {
+    if value.ends_with('\\') {
+        return Err(TracError::InvalidQuery(format!(
+            "'{}' ends in a backslash, which Trac queries cannot express",
+            value
+        )));
+    }
+    Ok(())
+}
+
+pub(crate) fn scalar_to_string(val: &Value) -> Option<String> {
+    match val {
+        Value::String(s) => Some(s.to_owned()),
+        Value::Int(i) => Some(i.to_string()),
+        Value::Int64(i) => Some(i.to_string()),
+        Value::Bool(b) => Some(if *b { "1" } else { "0" }.to_string()),
+        Value::Double(d) => Some(d.to_string()),
+        _ => None,
+    }
+}
+
+fn datetime_at(r: &Value, index: usize) -> SystemTime {
+    match r.get(index).and_then(|v| v.as_datetime()) {
+        Some(dt) => time::from_datetime(&dt),
+        None => UNIX_EPOCH,
+    }
+}
+
 fn get_val(valmap: &BTreeMap<String, Value>, field: &str) -> String {
     match valmap.get(field) {
         Some(val) => val_to_string(val),
@@ -180,51 +485,93 @@ fn get_val(valmap: &BTreeMap<String, Value>, field: &str) -> String {
     }
 }
 
+pub(crate) fn update_request<'a>(
+    id: i32,
+    attributes: Vec<(String, Value)>,
+    action: Option<TracAction>,
+    comment: Option<String>,
+    options: &UpdateOptions,
+) -> Request<'a> {
+    let modify_comment = match comment {
+        Some(c) => c,
+        None => "".to_string(),
+    };
+
+    let mut ticket_attributes: BTreeMap<String, Value> = BTreeMap::new();
+    for (key, value) in attributes {
+        ticket_attributes.insert(key, value);
+    }
+    if let Some(action) = action {
+        ticket_attributes.insert("action".to_string(), Value::String(action.name));
+        for input in action.inputs {
+            ticket_attributes.insert(input.name, Value::String(input.value));
+        }
+    }
+
+    let mut request = Request::new("ticket.update")
+        .arg(id)
+        .arg(modify_comment)
+        .arg(Value::Struct(ticket_attributes));
+    // Left off unless needed, for servers that predate them.
+    if options.notify || options.author.is_some() {
+        request = request.arg(options.notify);
+    }
+    if let Some(author) = &options.author {
+        request = request.arg(author.as_str());
+    }
+    request
+}
+
 impl TracTicket {
-    fn get(id: i32, trac: &Trac) -> Result<Self, ()> {
-        let transport = trac.get_transport();
+    fn from_value(r: &Value) -> Self {
+        let fields = r[3].as_struct().unwrap();
+        TracTicket {
+            id: r[0].as_i32().unwrap(),
+            summary: get_val(fields, "summary"),
+            description: get_val(fields, "description"),
+            component: get_val(fields, "component"),
+            reporter: get_val(fields, "reporter"),
+            owner: get_val(fields, "owner"),
+            reviewer: get_val(fields, "reviewer"),
+            tester: get_val(fields, "tester"),
+            priority: get_val(fields, "priority"),
+            milestone: get_val(fields, "milestone"),
+            status: get_val(fields, "status"),
+            resolution: get_val(fields, "resolution"),
+            created: datetime_at(r, 1),
+            changed: datetime_at(r, 2),
+            fields: fields
+                .iter()
+                .filter_map(|(k, v)| scalar_to_string(v).map(|s| (k.to_owned(), s)))
+                .collect(),
+        }
+    }
+
+    /// Returns the value of any field, including custom fields, or an empty
+    /// string if the ticket does not have it.
+    pub fn field(&self, name: &str) -> &str {
+        self.fields.get(name).map(|v| v.as_str()).unwrap_or("")
+    }
+
+    fn get(id: i32, trac: &Trac) -> Result<Self, TracError> {
         let xmlrpc_req = Request::new("ticket.get").arg(id);
 
-        match xmlrpc_req.call(transport) {
-            Ok(r) => {
-                let fields = r[3].as_struct().unwrap();
-                let t = TracTicket {
-                    id: r[0].as_i32().unwrap(),
-                    summary: get_val(&fields, "summary"),
-                    description: get_val(&fields, "description"),
-                    component: get_val(&fields, "component"),
-                    reporter: get_val(&fields, "reporter"),
-                    owner: get_val(&fields, "owner"),
-                    reviewer: get_val(&fields, "reviewer"),
-                    tester: get_val(&fields, "tester"),
-                    priority: get_val(&fields, "priority"),
-                    milestone: get_val(&fields, "milestone"),
-                    status: get_val(&fields, "status"),
-                    resolution: get_val(&fields, "resolution"),
-                };
-                Ok(t)
-            }
-            Err(e) => {
-                eprintln!("\nError: {}\n", e);
-                Err(())
-            }
+        match trac.call(&xmlrpc_req) {
+            Ok(r) => Ok(TracTicket::from_value(&r)),
+            Err(e) => Err(e),
         }
     }
 
     pub fn actions(&self, trac: &Trac) -> Vec<TracAction> {
-        let transport = trac.get_transport();
         let xmlrpc_req = Request::new("ticket.getActions").arg(self.id);
 
-        match xmlrpc_req.call(transport) {
+        match trac.call(&xmlrpc_req) {
             Ok(r) => {
                 let mut actions: Vec<TracAction> = Vec::new();
 
                 if let Value::Array(v) = r {
                     for item in v.iter() {
-                        actions.push(TracAction {
-                            name: val_to_string(&item[0]),
-                            description: val_to_string(&item[1]),
-                        })
+                        actions.push(TracAction::from_value(item))
                     }
                 }
 
@@ -244,88 +591,154 @@ impl TracTicket {
         format!("{}://{}{}ticket/{}", scheme, &conf.host, &conf.path, id)
     }
 
-    fn modify_attributes(
+    /// Sends a single `ticket.update` changing the given attributes and, if
+    /// present, applying a workflow action along with its input values.
+    /// Returns the ticket as stored by the server after the change.
+    /// Each value is encoded for its field's type, as described by
+    /// `ticket.getTicketFields`.
+    pub fn update(
         &self,
-        attributes: Vec<(String, String)>,
+        attributes: Vec<(String, FieldValue)>,
+        action: Option<TracAction>,
         comment: Option<String>,
         trac: &Trac,
-    ) -> Result<(), ()> {
-        let transport = trac.get_transport();
-        let modify_comment = match comment {
-            Some(c) => c,
-            None => "".to_string(),
-        };
+    ) -> Result<TracTicket, TracError> {
+        self.update_with(attributes, action, comment, &UpdateOptions::new(), trac)
+    }
 
-        let mut ticket_attributes: BTreeMap<String, Value> = BTreeMap::new();
-        for (key, value) in attributes {
-            ticket_attributes.insert(key, Value::String(value));
+    /// Like `update`, with control over notification and the recorded
+    /// author.
+    pub fn update_with(
+        &self,
+        attributes: Vec<(String, FieldValue)>,
+        action: Option<TracAction>,
+        comment: Option<String>,
+        options: &UpdateOptions,
+        trac: &Trac,
+    ) -> Result<TracTicket, TracError> {
+        if let Some(action) = &action {
+            self.check_guards(action, &attributes, trac)?;
         }
-        let xmlrpc_req = Request::new("ticket.update")
-            .arg(self.id)
-            .arg(modify_comment)
-            .arg(Value::Struct(ticket_attributes));
+        let encoded = if attributes.is_empty() {
+            vec![]
+        } else {
+            let fields = trac.ticket_fields()?;
+            attributes
+                .iter()
+                .map(|(name, value)| (name.to_owned(), value.encode(fields.field(name))))
+                .collect()
+        };
+        let xmlrpc_req = update_request(self.id, encoded, action, comment, options);
 
-        match xmlrpc_req.call(transport) {
-            Ok(r) => Ok(()),
-            Err(e) => {
-                eprintln!("\nError: {}\n", &e);
-                Err(())
-            }
+        match trac.call(&xmlrpc_req) {
+            Ok(r) => Ok(TracTicket::from_value(&r)),
+            Err(e) => Err(trac.note_error(e)),
         }
     }
 
+    pub fn update_builder(&self) -> TicketUpdateBuilder<'_> {
+        TicketUpdateBuilder::new(self)
+    }
+
+    fn modify_attributes(
+        &self,
+        attributes: Vec<(String, FieldValue)>,
+        comment: Option<String>,
+        trac: &Trac,
+    ) -> Result<(), TracError> {
+        self.update(attributes, None, comment, trac).map(|_| ())
+    }
+
     fn apply_action(
         &self,
         action: TracAction,
         comment: Option<String>,
         trac: &Trac,
-    ) -> Result<(), ()> {
-        let transport = trac.get_transport();
-        self.modify_attributes(vec![("action".to_string(), action.name)], comment, trac)
+    ) -> Result<(), TracError> {
+        self.update(vec![], Some(action), comment, trac).map(|_| ())
     }
 
-    pub fn set_reviewer(&self, reviewer: String, trac: &Trac) -> Result<(), ()> {
-        let transport = trac.get_transport();
-        self.modify_attributes(vec![("reviewer".to_string(), reviewer)], None, trac)
+    pub fn set_reviewer(&self, reviewer: String, trac: &Trac) -> Result<(), TracError> {
+        self.modify_attributes(vec![("reviewer".to_string(), reviewer.into())], None, trac)
     }
 
-    pub fn request_review(&self, reviewer: String, trac: &Trac) -> Result<(), ()> {
-        self.set_reviewer(reviewer.clone(), trac);
-        self.apply_action(
-            TracAction::new("peer_review"),
-            Some(format!("Sent to {} for review", reviewer)),
-            trac,
-        )
+    /// Sends the ticket to `reviewer` for review and, with the `email`
+    /// feature, emails them if they are a configured reviewer.
+    pub fn request_review(
+        &self,
+        reviewer: String,
+        trac: &Trac,
+    ) -> Result<ReviewNotification, TracError> {
+        let workflow = &trac.config.workflow;
+        let action = WorkflowProfile::action("request_review", &workflow.request_review)?;
+
+        #[cfg_attr(not(feature = "email"), allow(unused_variables))]
+        let updated = self
+            .update_builder()
+            .set("reviewer", &reviewer)
+            .with_action(action)
+            .comment(&format!("Sent to {} for review", reviewer))
+            .submit(trac)?;
+
+        // The review has been requested even if the email then fails.
+        #[cfg(feature = "email")]
+        {
+            if trac.config.email.is_some() {
+                if let Some(person) = trac.config.find_reviewer(&reviewer) {
+                    return Ok(match updated.notify_reviewer(person, trac) {
+                        Ok(()) => ReviewNotification::Sent(person.email.to_owned()),
+                        Err(e) => ReviewNotification::Failed(e),
+                    });
+                }
+            }
+        }
+        Ok(ReviewNotification::NotSent)
     }
 
-    pub fn review_fail(&self, reason: String, trac: &Trac) -> Result<(), ()> {
-        self.apply_action(TracAction::new("reject"), Some(reason), trac)
+    pub fn review_fail(&self, reason: String, trac: &Trac) -> Result<(), TracError> {
+        let workflow = &trac.config.workflow;
+        let action = WorkflowProfile::action("reject", &workflow.reject)?;
+        self.apply_action(action, Some(reason), trac)
     }
 
-    pub fn review_pass(&self, comment: Option<String>, trac: &Trac) -> Result<(), ()> {
-        self.apply_action(TracAction::new("pass_peer_review"), comment, trac)
+    pub fn review_pass(&self, comment: Option<String>, trac: &Trac) -> Result<(), TracError> {
+        let workflow = &trac.config.workflow;
+        let action = WorkflowProfile::action("approve", &workflow.approve)?;
+        self.apply_action(action, comment, trac)
     }
 
-    pub fn release(&self, comment: Option<String>, trac: &Trac) -> Result<(), ()> {
-        self.apply_action(TracAction::new("leave"), comment, trac)
+    pub fn release(&self, comment: Option<String>, trac: &Trac) -> Result<(), TracError> {
+        let workflow = &trac.config.workflow;
+        let action = WorkflowProfile::action("release", &workflow.release)?;
+        self.apply_action(action, comment, trac)
     }
 
-    pub fn accept(&self, estimate: bool, comment: Option<String>, trac: &Trac) -> Result<(), ()> {
-        let action_name = if estimate {
-            "accept"
+    pub fn accept(
+        &self,
+        estimate: bool,
+        comment: Option<String>,
+        trac: &Trac,
+    ) -> Result<(), TracError> {
+        let workflow = &trac.config.workflow;
+        let action = if estimate {
+            WorkflowProfile::action("accept", &workflow.accept)?
         } else {
-            "no_estimate_needed"
+            WorkflowProfile::action("accept_no_estimate", &workflow.accept_no_estimate)?
         };
 
-        self.apply_action(TracAction::new(action_name), comment, trac)
+        self.apply_action(action, comment, trac)
     }
 
-    pub fn reopen(&self, comment: Option<String>, trac: &Trac) -> Result<(), ()> {
-        self.apply_action(TracAction::new("reopen"), comment, trac)
+    pub fn reopen(&self, comment: Option<String>, trac: &Trac) -> Result<(), TracError> {
+        let workflow = &trac.config.workflow;
+        let action = WorkflowProfile::action("reopen", &workflow.reopen)?;
+        self.apply_action(action, comment, trac)
     }
 
-    pub fn close(&self, comment: Option<String>, trac: &Trac) -> Result<(), ()> {
-        self.apply_action(TracAction::new("resolve"), comment, trac)
+    pub fn close(&self, comment: Option<String>, trac: &Trac) -> Result<(), TracError> {
+        let workflow = &trac.config.workflow;
+        let action = WorkflowProfile::action("close", &workflow.close)?;
+        self.apply_action(action, comment, trac)
     }
 
     pub fn fmt_terse(&self) -> String {
@@ -335,6 +748,17 @@ impl TracTicket {
         )
     }
 
+    /// Formats the ticket with a template such as `"{id}\t{status}\t{summary}"`,
+    /// where any field, including custom fields, can be named. Fields the
+    /// ticket does not have come out empty; `{{` and `}}` give literal braces.
+    pub fn fmt_template(&self, template: &str) -> String {
+        let lookup = |name: &str| match name {
+            "id" => Some(self.id.to_string()),
+            _ => Some(self.field(name).to_string()),
+        };
+        template::substitute(template, lookup).unwrap_or_default()
+    }
+
     pub fn fmt_detail(&self) -> String {
         format!(
             "{}\n========================================================\n\n{}",
@@ -346,29 +770,219 @@ impl TracTicket {
 
 pub struct Trac {
     pub config: Rc<TracConfig>,
+    client: Client,
+    fields: RefCell<Option<(Instant, Rc<TracTicketFieldSet>)>>,
+    user: RefCell<Rc<TracUser>>,
+    endpoint: RefCell<Option<String>>,
+    call_options: RefCell<Option<CallOptions>>,
+    bulk_progress: RefCell<Option<executor::ProgressReporter>>,
+    credentials: Option<Box<dyn CredentialProvider>>,
+    #[cfg(feature = "search")]
+    local_index: Option<LocalIndex>,
 }
 
 impl Trac {
+    /// Creates a client for the configured server. Like `Client::new`, this
+    /// panics if the HTTP client (e.g. its TLS backend) cannot be set up;
+    /// `try_new` returns the error instead.
+    pub fn new(config: Rc<TracConfig>) -> Self {
+        match Self::try_new(config) {
+            Ok(trac) => trac,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Creates a client for the configured server, or fails with
+    /// `TracError::Config` if the TLS options are not supported here or the
+    /// HTTP client cannot be set up.
+    pub fn try_new(config: Rc<TracConfig>) -> Result<Self, TracError> {
+        let pool = &config.pool;
+        let builder = Client::builder()
+            .gzip(config.compression)
+            .pool_max_idle_per_host(pool.max_idle_per_host)
+            .pool_idle_timeout(pool.idle_timeout)
+            .tcp_keepalive(pool.tcp_keepalive);
+        let client = tls::configure(builder, &config.tls)
+            .map_err(|e| TracError::Config(format!("failed to set up TLS: {}", e)))?
+            .build()
+            .map_err(|e| TracError::Config(format!("failed to initialise HTTP client: {}", e)))?;
+
+        Ok(Self {
+            user: RefCell::new(Rc::clone(&config.user)),
+            config,
+            client,
+            fields: RefCell::new(None),
+            endpoint: RefCell::new(None),
+            call_options: RefCell::new(None),
+            bulk_progress: RefCell::new(None),
+            credentials: None,
+            #[cfg(feature = "search")]
+            local_index: None,
+        })
+    }
+
+    /// Returns the server's ticket field metadata, fetching it only when the
+    /// cached copy is older than `TracConfig::fields_ttl`.
+    pub fn ticket_fields(&self) -> Result<Rc<TracTicketFieldSet>, TracError> {
+        if let Some((fetched, fields)) = &*self.fields.borrow() {
+            if fetched.elapsed() < self.config.fields_ttl {
+                return Ok(Rc::clone(fields));
+            }
+        }
+        self.refresh_fields()
+    }
+
+    pub fn refresh_fields(&self) -> Result<Rc<TracTicketFieldSet>, TracError> {
+        let fields = Rc::new(TracTicketFieldSet::get(self)?);
+        *self.fields.borrow_mut() = Some((Instant::now(), Rc::clone(&fields)));
+        Ok(fields)
+    }
+
+    pub fn invalidate_fields(&self) {
+        *self.fields.borrow_mut() = None;
+    }
+
+    // A ticket referring to a field the server no longer knows means our
+    // cached field metadata is stale.
+    pub(crate) fn note_error(&self, e: TracError) -> TracError {
+        if let TracError::InvalidAttribute(_) = e {
+            self.invalidate_fields();
+        }
+        e
+    }
+
     pub fn url(&self) -> String {
         let conf = &self.config;
-        let user = &conf.user;
         let scheme = "https";
 
         format!("{}://{}{}", scheme, &conf.host, &conf.path)
     }
 
     fn get_transport(&self) -> RequestBuilder {
-        let conf = &self.config;
-        let user = &conf.user;
+        self.post(&self.rpc_path())
+    }
+
+    fn post(&self, path: &str) -> RequestBuilder {
+        let user = self.user();
 
-        let url_base = format!("{}login/xmlrpc", self.url());
+        let url_base = format!("{}{}", self.url(), path);
 
-        Client::new()
+        let builder = self
+            .client
             .post(&url_base)
-            .basic_auth(&user.username, Some(&user.password))
+            .basic_auth(&user.username, Some(&user.password));
+        match self.call_options().timeout {
+            Some(timeout) => builder.timeout(timeout),
+            None => builder,
+        }
+    }
+
+    /// Sends `requests` through `system.multicall`, in batches, and returns
+    /// one result per request in the same order.
+    pub(crate) fn multicall(
+        &self,
+        requests: &[Request],
+    ) -> Result<Vec<Result<Value, Fault>>, TracError> {
+        let mut results = Vec::with_capacity(requests.len());
+
+        for batch in requests.chunks(self.config.batch_size.max(1)) {
+            let xmlrpc_req = Request::new_multicall(batch);
+            let repeatable = batch.iter().all(|r| is_read_only(&method_name(r)));
+
+            match self.call_repeatable(&xmlrpc_req, repeatable) {
+                Ok(Value::Array(responses)) => {
+                    for response in responses {
+                        results.push(match response {
+                            Value::Array(mut v) if v.len() == 1 => Ok(v.remove(0)),
+                            other => Err(Fault::from_value(&other).unwrap_or(Fault {
+                                fault_code: 0,
+                                fault_string: "malformed multicall response".to_string(),
+                            })),
+                        });
+                    }
+                }
+                Ok(_) => return Err(TracError::invalid_response("system.multicall")),
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(results)
     }
 
-    pub fn get_ticket(&self, id: i32) -> Result<TracTicket, ()> {
-        TracTicket::get(id, &self)
+    pub fn get_ticket(&self, id: i32) -> Result<TracTicket, TracError> {
+        TracTicket::get(id, self)
+    }
+
+    /// Fetches several tickets through multicall, in the order given.
+    /// Tickets that no longer exist are left out.
+    pub fn get_tickets(&self, ids: &[i32]) -> Result<Vec<TracTicket>, TracError> {
+        self.get_tickets_cancellable(ids, &CancelToken::new())
+    }
+
+    /// Like `get_tickets`, but gives up with `TracError::Cancelled` once
+    /// `cancel` is set, discarding the tickets fetched so far.
+    pub fn get_tickets_cancellable(
+        &self,
+        ids: &[i32],
+        cancel: &CancelToken,
+    ) -> Result<Vec<TracTicket>, TracError> {
+        let jobs = ids
+            .iter()
+            .map(|id| {
+                executor::call(Request::new("ticket.get").arg(*id), |r| {
+                    Ok(TracTicket::from_value(&r))
+                })
+            })
+            .collect();
+
+        let mut tickets = Vec::with_capacity(ids.len());
+        for outcome in self.execute(jobs, cancel) {
+            match outcome {
+                Ok(ticket) => tickets.push(ticket),
+                Err(TracError::NoSuchTicket(_)) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(tickets)
+    }
+
+    /// Runs a `ticket.query` and returns the matching ticket ids. Unless the
+    /// query sets `max` itself, every match is returned rather than the
+    /// server's first page.
+    pub fn query(&self, query: &str) -> Result<Vec<i32>, TracError> {
+        let query = if query.split('&').any(|c| c.starts_with("max=")) {
+            query.to_string()
+        } else if query.is_empty() {
+            "max=0".to_string()
+        } else {
+            format!("{}&max=0", query)
+        };
+        let xmlrpc_req = Request::new("ticket.query").arg(query);
+
+        match self.call(&xmlrpc_req) {
+            Ok(r) => match r.as_array() {
+                Some(ids) => Ok(ids.iter().filter_map(|v| v.as_i32()).collect()),
+                None => Ok(vec![]),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn new_ticket(&self, summary: &str) -> TicketCreateBuilder {
+        TicketCreateBuilder::new(summary)
+    }
+
+    pub fn create_from_template(
+        &self,
+        name: &str,
+        vars: &[(&str, &str)],
+    ) -> Result<TracTicket, TracError> {
+        match self.config.templates.get(name) {
+            Some(template) => template.render(vars)?.submit(self),
+            None => Err(TracError::Config(format!(
+                "no ticket template named '{}'",
+                name
+            ))),
+        }
     }
 }
@@ -0,0 +1,182 @@
+use std::collections::BTreeMap;
+
+use regex::Regex;
+
+use crate::{Trac, TracError, TracReviewer, TracTicket};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MentionSource {
+    Description,
+    /// The comment with this number.
+    Comment(u32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mention {
+    pub username: String,
+    pub source: MentionSource,
+    /// Byte offset of the mention within its text.
+    pub offset: usize,
+}
+
+/// Finds people referred to in ticket text. By default it looks for
+/// `@name`; further patterns can be added, and names from a roster are also
+/// recognised when written bare.
+#[derive(Debug, Clone)]
+pub struct MentionScanner {
+    patterns: Vec<Regex>,
+    // Bare name or alias, to the username it stands for.
+    roster: BTreeMap<String, String>,
+}
+
+impl Default for MentionScanner {
+    fn default() -> Self {
+        Self {
+            patterns: vec![Regex::new(r"(?:^|[^\w.])@([\w.-]*\w)").unwrap()],
+            roster: BTreeMap::new(),
+        }
+    }
+}
+
+impl MentionScanner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a pattern whose first capture group is the username.
+    pub fn pattern(mut self, pattern: Regex) -> Self {
+        self.patterns.push(pattern);
+        self
+    }
+
+    /// Recognises these usernames when they appear as whole words.
+    pub fn roster(mut self, usernames: &[&str]) -> Self {
+        for name in usernames {
+            self.roster.insert(name.to_string(), name.to_string());
+        }
+        self
+    }
+
+    /// Adds reviewers to the roster, with their aliases standing for them.
+    pub fn reviewers(mut self, reviewers: &[TracReviewer]) -> Self {
+        for reviewer in reviewers {
+            self.roster
+                .insert(reviewer.name.to_owned(), reviewer.name.to_owned());
+            for alias in &reviewer.aliases {
+                self.roster
+                    .insert(alias.to_owned(), reviewer.name.to_owned());
+            }
+        }
+        self
+    }
+
+    /// Returns each username mentioned in `text` with its offset, in order.
+    pub fn scan(&self, text: &str) -> Vec<(String, usize)> {
+        let mut found: Vec<(String, usize)> = Vec::new();
+
+        for pattern in &self.patterns {
+            for captures in pattern.captures_iter(text) {
+                if let Some(name) = captures.get(1) {
+                    let username = self
+                        .roster
+                        .get(name.as_str())
+                        .cloned()
+                        .unwrap_or_else(|| name.as_str().to_string());
+                    found.push((username, name.start()));
+                }
+            }
+        }
+
+        if !self.roster.is_empty() {
+            let names: Vec<String> = self.roster.keys().map(|n| regex::escape(n)).collect();
+            let bare = Regex::new(&format!(r"\b(?:{})\b", names.join("|"))).unwrap();
+            for m in bare.find_iter(text) {
+                if !found.iter().any(|(_, offset)| *offset == m.start()) {
+                    found.push((self.roster[m.as_str()].to_owned(), m.start()));
+                }
+            }
+        }
+
+        found.sort_by_key(|(_, offset)| *offset);
+        found
+    }
+
+    /// Scans a ticket's description and all of its comments.
+    pub fn scan_ticket(&self, ticket: &TracTicket, trac: &Trac) -> Result<Vec<Mention>, TracError> {
+        let mention = |source: MentionSource| {
+            move |(username, offset): (String, usize)| Mention {
+                username,
+                source,
+                offset,
+            }
+        };
+
+        let mut mentions: Vec<Mention> = self
+            .scan(&ticket.description)
+            .into_iter()
+            .map(mention(MentionSource::Description))
+            .collect();
+        for comment in ticket.comments(trac)? {
+            mentions.extend(
+                self.scan(&comment.text)
+                    .into_iter()
+                    .map(mention(MentionSource::Comment(comment.number))),
+            );
+        }
+        Ok(mentions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_at_mentions() {
+        let scanner = MentionScanner::new();
+        assert_eq!(
+            scanner.scan("@alice, can you and @bob.smith look? (cc @carol)"),
+            [
+                ("alice".to_string(), 1),
+                ("bob.smith".to_string(), 21),
+                ("carol".to_string(), 42),
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_email_addresses_and_trailing_punctuation() {
+        let scanner = MentionScanner::new();
+        assert_eq!(
+            scanner.scan("mail dave@example.com or ask @erin."),
+            [("erin".to_string(), 30)]
+        );
+    }
+
+    #[test]
+    fn recognises_roster_names_and_aliases() {
+        let reviewers = [TracReviewer {
+            name: "alice".to_string(),
+            aliases: vec!["ali".to_string()],
+            email: String::new(),
+        }];
+        let scanner = MentionScanner::new().roster(&["bob"]).reviewers(&reviewers);
+        assert_eq!(
+            scanner.scan("bob and ali agreed; @ali too, but not bobby or alison"),
+            [
+                ("bob".to_string(), 0),
+                ("alice".to_string(), 8),
+                ("alice".to_string(), 21),
+            ]
+        );
+    }
+
+    #[test]
+    fn uses_extra_patterns() {
+        let scanner = MentionScanner::new().pattern(Regex::new(r"\[\[user:(\w+)\]\]").unwrap());
+        assert_eq!(
+            scanner.scan("see [[user:frank]]"),
+            [("frank".to_string(), 11)]
+        );
+    }
+}
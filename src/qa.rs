@@ -0,0 +1,57 @@
+use crate::{AttachmentSource, Trac, TracAction, TracError, TracTicket, WorkflowProfile};
+
+// A wiki link to one of the ticket's attachments.
+fn attachment_link(filename: &str) -> String {
+    format!("[attachment:\"{}\"]", filename.replace('"', ""))
+}
+
+impl TracTicket {
+    /// Uploads the evidence, if any, then applies the profile's `qa_pass`
+    /// action with a comment recording the result and linking the evidence.
+    pub fn qa_pass(
+        &self,
+        evidence: Option<AttachmentSource>,
+        comment: Option<String>,
+        trac: &Trac,
+    ) -> Result<(), TracError> {
+        let action = WorkflowProfile::action("qa_pass", &trac.config.workflow.qa_pass)?;
+        self.qa_result("QA passed", action, comment, evidence, trac)
+    }
+
+    /// Like `qa_pass`, applying the `qa_fail` action with `reason`.
+    pub fn qa_fail(
+        &self,
+        reason: String,
+        evidence: Option<AttachmentSource>,
+        trac: &Trac,
+    ) -> Result<(), TracError> {
+        let action = WorkflowProfile::action("qa_fail", &trac.config.workflow.qa_fail)?;
+        self.qa_result("QA failed", action, Some(reason), evidence, trac)
+    }
+
+    fn qa_result(
+        &self,
+        outcome: &str,
+        action: TracAction,
+        text: Option<String>,
+        evidence: Option<AttachmentSource>,
+        trac: &Trac,
+    ) -> Result<(), TracError> {
+        // Guards run again on submit, but checking first avoids leaving an
+        // orphaned upload behind when the action is refused.
+        self.check_guards(&action, &[], trac)?;
+
+        let mut comment = format!("'''{}'''", outcome);
+        if let Some(text) = text.filter(|t| !t.trim().is_empty()) {
+            comment.push_str(&format!("\n\n{}", text.trim()));
+        }
+        if let Some(source) = &evidence {
+            let description = format!("{} evidence", outcome);
+            let stored = self.put_attachment_from(source, &description, trac)?;
+            comment.push_str(&format!("\n\nEvidence: {}", attachment_link(&stored)));
+        }
+
+        self.update(vec![], Some(action), Some(comment), trac)
+            .map(|_| ())
+    }
+}
@@ -0,0 +1,470 @@
+use std::fmt;
+use std::time::SystemTime;
+
+use crate::{
+    check_query_value, escape_query_value, time, CancelToken, Trac, TracError, TracTicket,
+};
+
+/// How a condition compares a field against its values, written as a
+/// prefix on the value in Trac's query language (`status=!closed`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QueryOp {
+    Is,
+    IsNot,
+    Contains,
+    NotContains,
+    StartsWith,
+    NotStartsWith,
+    EndsWith,
+    NotEndsWith,
+}
+
+impl QueryOp {
+    fn prefix(self) -> &'static str {
+        match self {
+            QueryOp::Is => "",
+            QueryOp::IsNot => "!",
+            QueryOp::Contains => "~",
+            QueryOp::NotContains => "!~",
+            QueryOp::StartsWith => "^",
+            QueryOp::NotStartsWith => "!^",
+            QueryOp::EndsWith => "$",
+            QueryOp::NotEndsWith => "!$",
+        }
+    }
+
+    // Splits the operator off the front of a value.
+    fn split(value: &str) -> (Self, &str) {
+        let (negated, rest) = match value.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, value),
+        };
+        let (op, rest) = match rest.chars().next() {
+            Some('~') => (QueryOp::Contains, &rest[1..]),
+            Some('^') => (QueryOp::StartsWith, &rest[1..]),
+            Some('$') => (QueryOp::EndsWith, &rest[1..]),
+            _ => (QueryOp::Is, rest),
+        };
+        if negated {
+            (op.negate(), rest)
+        } else {
+            (op, rest)
+        }
+    }
+
+    fn is_negated(self) -> bool {
+        matches!(
+            self,
+            QueryOp::IsNot | QueryOp::NotContains | QueryOp::NotStartsWith | QueryOp::NotEndsWith
+        )
+    }
+
+    fn negate(self) -> Self {
+        match self {
+            QueryOp::Is => QueryOp::IsNot,
+            QueryOp::IsNot => QueryOp::Is,
+            QueryOp::Contains => QueryOp::NotContains,
+            QueryOp::NotContains => QueryOp::Contains,
+            QueryOp::StartsWith => QueryOp::NotStartsWith,
+            QueryOp::NotStartsWith => QueryOp::StartsWith,
+            QueryOp::EndsWith => QueryOp::NotEndsWith,
+            QueryOp::NotEndsWith => QueryOp::EndsWith,
+        }
+    }
+}
+
+/// One `field=value` clause. Several values are alternatives, written
+/// `owner=alice|bob`; Trac expects the operator on each of them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryCondition {
+    pub field: String,
+    pub op: QueryOp,
+    pub values: Vec<String>,
+}
+
+impl QueryCondition {
+    /// Evaluates the condition locally against a ticket's fields, the way
+    /// the server would: a ticket matches if any value does, or, for the
+    /// negated operators, if none does.
+    pub fn matches(&self, ticket: &TracTicket) -> bool {
+        let actual = match self.field.as_str() {
+            "id" => ticket.id.to_string(),
+            field => ticket.field(field).to_string(),
+        };
+        self.matches_value(&actual)
+    }
+
+    pub(crate) fn matches_value(&self, actual: &str) -> bool {
+        let positive = if self.op.is_negated() {
+            self.op.negate()
+        } else {
+            self.op
+        };
+        let any = self.values.iter().any(|value| match positive {
+            QueryOp::Contains => actual.contains(value.as_str()),
+            QueryOp::StartsWith => actual.starts_with(value.as_str()),
+            QueryOp::EndsWith => actual.ends_with(value.as_str()),
+            _ => actual == *value,
+        });
+        any != self.op.is_negated()
+    }
+}
+
+/// A Trac ticket query, as used by `ticket.query` and the web UI's query
+/// page. `parse` reads an existing query string and `to_string` writes one
+/// back.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TicketQuery {
+    pub conditions: Vec<QueryCondition>,
+    pub order: Option<String>,
+    pub desc: bool,
+    pub group: Option<String>,
+    pub group_desc: bool,
+    /// The columns to show. `ticket.query` itself only returns ids, so this
+    /// is a hint for exports and reports built from the results.
+    pub columns: Vec<String>,
+    pub max: Option<u32>,
+    pub page: Option<u32>,
+    /// Any other parameters (e.g. `format`), kept as given.
+    pub extra: Vec<(String, String)>,
+}
+
+// Splits on `sep` where it does not follow a backslash, leaving the
+// escapes in place, as Trac does.
+fn split_unescaped(text: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut previous = None;
+    for (i, c) in text.char_indices() {
+        if c == sep && previous != Some('\\') {
+            parts.push(&text[start..i]);
+            start = i + c.len_utf8();
+        }
+        previous = Some(c);
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+// Undoes `escape_query_value`; any other backslash is literal.
+fn unescape(text: &str) -> String {
+    text.replace("\\&", "&").replace("\\|", "|")
+}
+
+fn parse_number(key: &str, value: &str) -> Result<u32, TracError> {
+    value
+        .parse()
+        .map_err(|_| TracError::InvalidQuery(format!("{}={}", key, value)))
+}
+
+impl TicketQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads a query string such as `status=!closed&owner=alice&order=priority`.
+    /// The operator may also be written before the `=` (`status!=closed`),
+    /// as the TracQuery macro does.
+    pub fn parse(text: &str) -> Result<Self, TracError> {
+        let mut query = Self::new();
+
+        for clause in split_unescaped(text, '&') {
+            if clause.is_empty() {
+                continue;
+            }
+            let (key, value) = match clause.find('=') {
+                Some(i) => (&clause[..i], &clause[i + 1..]),
+                None => return Err(TracError::InvalidQuery(clause.to_string())),
+            };
+
+            match key {
+                "order" => query.order = Some(unescape(value)),
+                "desc" => query.desc = value == "1",
+                "max" => query.max = Some(parse_number(key, value)?),
+                "page" => query.page = Some(parse_number(key, value)?),
+                "group" => query.group = Some(unescape(value)),
+                "groupdesc" => query.group_desc = value == "1",
+                "col" => query.columns.push(unescape(value)),
+                "format" | "report" | "verbose" | "row" => {
+                    query.extra.push((key.to_string(), unescape(value)))
+                }
+                _ => {
+                    let (field, suffix) = match key.find(|c| "!~^$".contains(c)) {
+                        Some(i) => (&key[..i], &key[i..]),
+                        None => (key, ""),
+                    };
+                    let mut values = split_unescaped(value, '|').into_iter();
+                    let (first_op, first) = QueryOp::split(values.next().unwrap_or(""));
+                    let (suffix_op, _) = QueryOp::split(suffix);
+                    let op = match (suffix_op, first_op) {
+                        (QueryOp::Is, op) => op,
+                        (op, QueryOp::Is) => op,
+                        (QueryOp::IsNot, op) => op.negate(),
+                        (op, _) => op,
+                    };
+
+                    let mut parsed = vec![unescape(first)];
+                    for v in values {
+                        let v = v.strip_prefix(op.prefix()).unwrap_or(v);
+                        parsed.push(unescape(v));
+                    }
+                    query.conditions.push(QueryCondition {
+                        field: field.to_string(),
+                        op,
+                        values: parsed,
+                    });
+                }
+            }
+        }
+
+        query.check()?;
+        Ok(query)
+    }
+
+    // Refuses values ending in a backslash; see `check_query_value`.
+    fn check(&self) -> Result<(), TracError> {
+        let values = self.conditions.iter().flat_map(|c| &c.values);
+        values
+            .chain(&self.order)
+            .chain(&self.group)
+            .chain(&self.columns)
+            .chain(self.extra.iter().map(|(_, value)| value))
+            .try_for_each(|value| check_query_value(value))
+    }
+
+    /// The query string to send to Trac, as `to_string` gives, or
+    /// `TracError::InvalidQuery` if a value ends in a backslash, which Trac
+    /// would read as escaping the separator after it.
+    pub fn query_string(&self) -> Result<String, TracError> {
+        self.check()?;
+        Ok(self.to_string())
+    }
+
+    pub fn filter(mut self, field: &str, op: QueryOp, values: &[&str]) -> Self {
+        self.conditions.push(QueryCondition {
+            field: field.to_string(),
+            op,
+            values: values.iter().map(|v| v.to_string()).collect(),
+        });
+        self
+    }
+
+    pub fn is(self, field: &str, value: &str) -> Self {
+        self.filter(field, QueryOp::Is, &[value])
+    }
+
+    pub fn is_not(self, field: &str, value: &str) -> Self {
+        self.filter(field, QueryOp::IsNot, &[value])
+    }
+
+    pub fn contains(self, field: &str, value: &str) -> Self {
+        self.filter(field, QueryOp::Contains, &[value])
+    }
+
+    /// Limits the query to tickets last changed between two dates, as a
+    /// `changetime=from..to` range.
+    pub fn changed_between(self, from: SystemTime, to: SystemTime) -> Self {
+        let range = format!("{}..{}", time::format_date(from), time::format_date(to));
+        self.is("changetime", &range)
+    }
+
+    pub fn order_by(mut self, field: &str, desc: bool) -> Self {
+        self.order = Some(field.to_string());
+        self.desc = desc;
+        self
+    }
+
+    pub fn group_by(mut self, field: &str) -> Self {
+        self.group = Some(field.to_string());
+        self
+    }
+
+    pub fn columns(mut self, fields: &[&str]) -> Self {
+        self.columns = fields.iter().map(|f| f.to_string()).collect();
+        self
+    }
+
+    pub fn max(mut self, max: u32) -> Self {
+        self.max = Some(max);
+        self
+    }
+
+    /// Runs the query and returns the matching ticket ids.
+    pub fn ids(&self, trac: &Trac) -> Result<Vec<i32>, TracError> {
+        trac.query(&self.query_string()?)
+    }
+
+    /// Runs the query and fetches every matching ticket, in batches of
+    /// `TracConfig::batch_size`.
+    pub fn fetch(&self, trac: &Trac) -> Result<Vec<TracTicket>, TracError> {
+        trac.get_tickets(&self.ids(trac)?)
+    }
+
+    /// Like `fetch`, but gives up with `TracError::Cancelled` once `cancel`
+    /// is set.
+    pub fn fetch_cancellable(
+        &self,
+        trac: &Trac,
+        cancel: &CancelToken,
+    ) -> Result<Vec<TracTicket>, TracError> {
+        let ids = self.ids(trac)?;
+        cancel.check()?;
+        trac.get_tickets_cancellable(&ids, cancel)
+    }
+}
+
+impl fmt::Display for TicketQuery {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut clauses: Vec<String> = Vec::new();
+
+        for condition in &self.conditions {
+            let values: Vec<String> = condition
+                .values
+                .iter()
+                .map(|v| format!("{}{}", condition.op.prefix(), escape_query_value(v)))
+                .collect();
+            clauses.push(format!("{}={}", condition.field, values.join("|")));
+        }
+        if let Some(order) = &self.order {
+            clauses.push(format!("order={}", escape_query_value(order)));
+        }
+        if self.desc {
+            clauses.push("desc=1".to_string());
+        }
+        if let Some(group) = &self.group {
+            clauses.push(format!("group={}", escape_query_value(group)));
+        }
+        if self.group_desc {
+            clauses.push("groupdesc=1".to_string());
+        }
+        for column in &self.columns {
+            clauses.push(format!("col={}", escape_query_value(column)));
+        }
+        if let Some(max) = self.max {
+            clauses.push(format!("max={}", max));
+        }
+        if let Some(page) = self.page {
+            clauses.push(format!("page={}", page));
+        }
+        for (key, value) in &self.extra {
+            clauses.push(format!("{}={}", key, escape_query_value(value)));
+        }
+
+        write!(f, "{}", clauses.join("&"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn round_trip(query: &TicketQuery) -> TicketQuery {
+        TicketQuery::parse(&query.to_string()).unwrap()
+    }
+
+    #[test]
+    fn parses_conditions_and_options() {
+        let query = TicketQuery::parse(
+            "status=!closed|!rejected&owner=~ali&order=priority&desc=1&max=5&col=id&col=summary",
+        )
+        .unwrap();
+        assert_eq!(
+            query.conditions,
+            vec![
+                QueryCondition {
+                    field: "status".to_string(),
+                    op: QueryOp::IsNot,
+                    values: vec!["closed".to_string(), "rejected".to_string()],
+                },
+                QueryCondition {
+                    field: "owner".to_string(),
+                    op: QueryOp::Contains,
+                    values: vec!["ali".to_string()],
+                },
+            ]
+        );
+        assert_eq!(query.order.as_deref(), Some("priority"));
+        assert!(query.desc);
+        assert_eq!(query.max, Some(5));
+        assert_eq!(query.columns, ["id", "summary"]);
+    }
+
+    #[test]
+    fn parses_operators_before_the_equals_sign() {
+        let query = TicketQuery::parse("status!=closed&summary!~=draft&keywords^=ui").unwrap();
+        let ops: Vec<QueryOp> = query.conditions.iter().map(|c| c.op).collect();
+        assert_eq!(
+            ops,
+            [QueryOp::IsNot, QueryOp::NotContains, QueryOp::StartsWith]
+        );
+        assert_eq!(query.conditions[1].values, ["draft"]);
+    }
+
+    #[test]
+    fn rejects_malformed_clauses() {
+        assert!(TicketQuery::parse("status").is_err());
+        assert!(TicketQuery::parse("max=lots").is_err());
+    }
+
+    #[test]
+    fn round_trips_every_operator() {
+        for op in [
+            QueryOp::Is,
+            QueryOp::IsNot,
+            QueryOp::Contains,
+            QueryOp::NotContains,
+            QueryOp::StartsWith,
+            QueryOp::NotStartsWith,
+            QueryOp::EndsWith,
+            QueryOp::NotEndsWith,
+        ] {
+            let query = TicketQuery::new().filter("summary", op, &["a", "b"]);
+            assert_eq!(round_trip(&query), query, "{}", query);
+        }
+    }
+
+    #[test]
+    fn round_trips_special_characters() {
+        let query = TicketQuery::new()
+            .is("summary", "a|b & c")
+            .contains("keywords", "x!~^$y")
+            .is("component", r"C:\path\x")
+            .order_by("a&b", false);
+        assert_eq!(
+            query.to_string(),
+            r"summary=a\|b \& c&keywords=~x!~^$y&component=C:\path\x&order=a\&b"
+        );
+        assert_eq!(round_trip(&query), query);
+
+        // A trailing backslash would escape the `&` after it.
+        let query = TicketQuery::new()
+            .is("component", r"C:\path\")
+            .order_by("id", false);
+        assert_eq!(query.to_string(), r"component=C:\path\&order=id");
+        assert!(matches!(
+            query.query_string(),
+            Err(TracError::InvalidQuery(_))
+        ));
+        let misread = TicketQuery::parse(&query.to_string()).unwrap();
+        assert_eq!(misread.conditions[0].values, [r"C:\path&order=id"]);
+        assert_eq!(misread.order, None);
+        assert!(TicketQuery::parse(r"component=C:\path\").is_err());
+        assert!(TicketQuery::parse(r"component=a\|b").is_ok());
+    }
+
+    #[test]
+    fn keeps_other_backslashes_literal() {
+        let query = TicketQuery::parse(r"summary=a\b\\c").unwrap();
+        assert_eq!(query.conditions[0].values, [r"a\b\\c"]);
+    }
+
+    #[test]
+    fn matches_tickets_locally() {
+        let ticket = testing::ticket(3, &[("status", "new"), ("owner", "alice")]);
+        let query = TicketQuery::parse("status=!closed|!rejected&owner=^al&id=3").unwrap();
+        assert!(query.conditions.iter().all(|c| c.matches(&ticket)));
+        let query = TicketQuery::parse("status=closed|rejected").unwrap();
+        assert!(!query.conditions[0].matches(&ticket));
+    }
+}
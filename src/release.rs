@@ -0,0 +1,411 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use regex::{Captures, Regex};
+
+use crate::chat::escape_markdown;
+use crate::{QueryOp, TicketQuery, Trac, TracError, TracTicket};
+
+// Types listed first, in this order; any others follow alphabetically.
+const TYPE_ORDER: &[&str] = &["defect", "enhancement", "task"];
+const NO_COMPONENT: &str = "General";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NotesFormat {
+    Markdown,
+    TracWiki,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReleaseNotesOptions {
+    pub format: NotesFormat,
+    /// The document's title; `Release notes for <milestone>` by default.
+    pub title: Option<String>,
+    /// Only closed tickets with one of these resolutions are included; all
+    /// closed tickets if empty.
+    pub resolutions: Vec<String>,
+}
+
+impl Default for ReleaseNotesOptions {
+    fn default() -> Self {
+        Self {
+            format: NotesFormat::Markdown,
+            title: None,
+            resolutions: vec!["fixed".to_string()],
+        }
+    }
+}
+
+fn type_heading(ticket_type: &str) -> String {
+    let mut chars = ticket_type.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => "Other".to_string(),
+    }
+}
+
+fn type_rank(ticket_type: &str) -> (usize, String) {
+    let rank = TYPE_ORDER
+        .iter()
+        .position(|t| *t == ticket_type)
+        .unwrap_or(TYPE_ORDER.len());
+    (rank, ticket_type.to_string())
+}
+
+// Puts `!` before the wiki markup in `text` so that Trac shows it as typed.
+// Trac only drops a `!` that starts a rule, so just those are escaped:
+// inline formatting, `#1`, `{1}`, `[1]` and `[realm:...]` links, `r1`,
+// CamelCase page names and `realm:target` links. Like Trac, `&#1` is left
+// alone.
+fn escape_wiki(text: &str) -> String {
+    static MARKUP: OnceLock<Regex> = OnceLock::new();
+    MARKUP
+        .get_or_init(|| {
+            Regex::new(concat!(
+                r"'''|''|__|~~|,,|\^|`|\{\{\{|&?#\d|\{\w*\d+\}|\[(?:\d|[a-zA-Z][\w.+-]*:)",
+                r"|\b(?:[A-Z][a-z]+(?:[A-Z][a-z]*[a-z/])+\b|r\d+\b|[a-zA-Z][\w.+-]*:[^\s:])",
+            ))
+            .unwrap()
+        })
+        .replace_all(text, |c: &Captures| match &c[0] {
+            entity if entity.starts_with('&') => entity.to_string(),
+            markup => format!("!{}", markup),
+        })
+        .into_owned()
+}
+
+impl NotesFormat {
+    pub(crate) fn heading(self, level: usize, text: &str) -> String {
+        match self {
+            NotesFormat::Markdown => format!("{} {}", "#".repeat(level), escape_markdown(text)),
+            NotesFormat::TracWiki => {
+                let marks = "=".repeat(level);
+                format!("{} {} {}", marks, text, marks)
+            }
+        }
+    }
+
+    /// A list item linking to the ticket.
+    pub(crate) fn item(self, ticket: &TracTicket, trac: &Trac) -> String {
+        match self {
+            NotesFormat::Markdown => format!(
+                "- [#{}]({}) {}",
+                ticket.id,
+                TracTicket::url(ticket.id, trac),
+                escape_markdown(&ticket.summary)
+            ),
+            // Trac links `#123` by itself.
+            NotesFormat::TracWiki => {
+                format!(" * #{} {}", ticket.id, escape_wiki(&ticket.summary))
+            }
+        }
+    }
+}
+
+/// Renders `tickets` grouped by component, then by type, with headings
+/// starting at `level`.
+pub(crate) fn render_groups(
+    tickets: &[TracTicket],
+    level: usize,
+    format: NotesFormat,
+    trac: &Trac,
+) -> String {
+    let mut groups: BTreeMap<&str, BTreeMap<(usize, String), Vec<&TracTicket>>> = BTreeMap::new();
+    for ticket in tickets {
+        let component = match ticket.component.as_str() {
+            "" => NO_COMPONENT,
+            component => component,
+        };
+        groups
+            .entry(component)
+            .or_default()
+            .entry(type_rank(ticket.field("type")))
+            .or_default()
+            .push(ticket);
+    }
+
+    let mut out = String::new();
+    for (component, types) in groups {
+        out.push_str(&format!("\n{}\n", format.heading(level, component)));
+        for ((_, ticket_type), tickets) in types {
+            out.push_str(&format!(
+                "\n{}\n\n",
+                format.heading(level + 1, &type_heading(&ticket_type))
+            ));
+            for ticket in tickets {
+                out.push_str(&format.item(ticket, trac));
+                out.push('\n');
+            }
+        }
+    }
+    out
+}
+
+impl Trac {
+    /// The closed tickets of `milestone` that release notes would cover.
+    pub(crate) fn release_tickets(
+        &self,
+        milestone: &str,
+        resolutions: &[String],
+    ) -> Result<Vec<TracTicket>, TracError> {
+        let mut query = TicketQuery::new()
+            .is("milestone", milestone)
+            .closed(self)
+            .order_by("id", false);
+        if !resolutions.is_empty() {
+            let resolutions: Vec<&str> = resolutions.iter().map(|r| r.as_str()).collect();
+            query = query.filter("resolution", QueryOp::Is, &resolutions);
+        }
+        query.fetch(self)
+    }
+
+    /// Adds a section headed `heading` to a Markdown changelog, listing the
+    /// tickets matching `query` that it does not mention yet. Only closed
+    /// tickets are listed unless the query says otherwise; `changed_between`
+    /// or a `milestone` condition select the range. The section goes above
+    /// the newest existing one, and the file is created if needed. Returns
+    /// how many tickets were added, writing nothing if there are none.
+    pub fn append_changelog<P: AsRef<Path>>(
+        &self,
+        path: P,
+        heading: &str,
+        query: &TicketQuery,
+    ) -> Result<usize, TracError> {
+        let mut query = query.clone();
+        if !query.conditions.iter().any(|c| c.field == "status") {
+            query = query.closed(self);
+        }
+        self.add_changelog_section(path.as_ref(), heading, query.fetch(self)?)
+    }
+
+    // The file handling of `append_changelog`, given the tickets to list.
+    fn add_changelog_section(
+        &self,
+        path: &Path,
+        heading: &str,
+        tickets: Vec<TracTicket>,
+    ) -> Result<usize, TracError> {
+        let existing = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => "# Changelog\n".to_string(),
+            Err(e) => return Err(TracError::Io(format!("{}: {}", path.display(), e))),
+        };
+
+        static MENTION: OnceLock<Regex> = OnceLock::new();
+        let mentioned: BTreeSet<i32> = MENTION
+            .get_or_init(|| Regex::new(r"\[#(\d+)\]\(").unwrap())
+            .captures_iter(&existing)
+            .filter_map(|c| c[1].parse().ok())
+            .collect();
+
+        let tickets: Vec<TracTicket> = tickets
+            .into_iter()
+            .filter(|t| !mentioned.contains(&t.id))
+            .collect();
+        if tickets.is_empty() {
+            return Ok(0);
+        }
+
+        let format = NotesFormat::Markdown;
+        let section = format!(
+            "{}\n{}",
+            format.heading(2, heading),
+            render_groups(&tickets, 3, format, self)
+        );
+        let newest = if existing.starts_with("## ") {
+            Some(0)
+        } else {
+            existing.find("\n## ").map(|i| i + 1)
+        };
+        let updated = match newest {
+            Some(i) => format!("{}{}\n{}", &existing[..i], section, &existing[i..]),
+            None => format!("{}\n\n{}", existing.trim_end(), section),
+        };
+        let mut temporary = path.to_path_buf().into_os_string();
+        temporary.push(".tmp");
+        fs::write(&temporary, updated)
+            .and_then(|_| fs::rename(&temporary, path))
+            .map_err(|e| TracError::Io(format!("{}: {}", path.display(), e)))?;
+        Ok(tickets.len())
+    }
+
+    /// Writes release notes for `milestone`, listing its closed tickets by
+    /// component and type.
+    pub fn release_notes(
+        &self,
+        milestone: &str,
+        options: &ReleaseNotesOptions,
+    ) -> Result<String, TracError> {
+        let tickets = self.release_tickets(milestone, &options.resolutions)?;
+        let title = match &options.title {
+            Some(title) => title.to_owned(),
+            None => format!("Release notes for {}", milestone),
+        };
+        Ok(format!(
+            "{}\n{}",
+            options.format.heading(1, &title),
+            render_groups(&tickets, 2, options.format, self)
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn ticket(id: i32, summary: &str) -> TracTicket {
+        testing::ticket(
+            id,
+            &[
+                ("summary", summary),
+                ("component", "ui"),
+                ("type", "defect"),
+            ],
+        )
+    }
+
+    #[test]
+    fn escapes_wiki_markup() {
+        for plain in [
+            "Plain summary, ok!",
+            "Note: C# at 10:30 in [draft] {x} McD",
+            "fooBar and &#123;",
+        ] {
+            assert_eq!(escape_wiki(plain), plain);
+        }
+        assert_eq!(
+            escape_wiki("'''Bold''' and ''it'' __u__ {{{code}}} `x`"),
+            "!'''Bold!''' and !''it!'' !__u!__ !{{{code}}} !`x!`"
+        );
+        assert_eq!(
+            escape_wiki("See #12, [1], {3} and r45"),
+            "See !#12, ![1], !{3} and !r45"
+        );
+        assert_eq!(
+            escape_wiki("(WikiStart) ticket:4 [wiki:Foo bar] x^2"),
+            "(!WikiStart) !ticket:4 ![wiki:Foo bar] x!^2"
+        );
+    }
+
+    #[test]
+    fn groups_by_component_then_type() {
+        let trac = testing::offline(testing::config());
+        let tickets: Vec<TracTicket> = [
+            (1, "ui", "task"),
+            (2, "", "zebra"),
+            (3, "ui", "apple"),
+            (4, "ui", "defect"),
+            (5, "ui", ""),
+            (6, "core", "enhancement"),
+            (7, "ui", "defect"),
+        ]
+        .iter()
+        .map(|&(id, component, ticket_type)| {
+            testing::ticket(
+                id,
+                &[
+                    ("summary", "work"),
+                    ("component", component),
+                    ("type", ticket_type),
+                ],
+            )
+        })
+        .collect();
+        let notes = render_groups(&tickets, 1, NotesFormat::TracWiki, &trac);
+        let headings: Vec<&str> = notes.lines().filter(|l| l.starts_with('=')).collect();
+        assert_eq!(
+            headings,
+            [
+                "= General =",
+                "== Zebra ==",
+                "= core =",
+                "== Enhancement ==",
+                "= ui =",
+                "== Defect ==",
+                "== Task ==",
+                "== Other ==",
+                "== Apple ==",
+            ]
+        );
+        assert!(notes.contains(" * #4 work\n * #7 work\n"));
+    }
+
+    #[test]
+    fn renders_both_formats() {
+        let trac = testing::offline(testing::config());
+        let tickets = [ticket(3, "Fix `x` in #2")];
+        assert_eq!(
+            render_groups(&tickets, 2, NotesFormat::Markdown, &trac),
+            "\n## ui\n\n### Defect\n\n\
+             - [#3](https://127.0.0.1:1/trac/ticket/3) Fix \\`x\\` in \\#2\n"
+        );
+        assert_eq!(
+            render_groups(&tickets, 2, NotesFormat::TracWiki, &trac),
+            "\n== ui ==\n\n=== Defect ===\n\n * #3 Fix !`x!` in !#2\n"
+        );
+    }
+
+    fn changelog_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("trac-changelog-{}-{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn creates_a_missing_changelog() {
+        let trac = testing::offline(testing::config());
+        let path = changelog_path("new");
+        let _ = fs::remove_file(&path);
+        let added = trac.add_changelog_section(&path, "1.0", vec![ticket(1, "Crash")]);
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(added, Ok(1));
+        assert_eq!(
+            text,
+            "# Changelog\n\n## 1.0\n\n### ui\n\n#### Defect\n\n\
+             - [#1](https://127.0.0.1:1/trac/ticket/1) Crash\n"
+        );
+    }
+
+    #[test]
+    fn adds_sections_above_the_newest() {
+        let trac = testing::offline(testing::config());
+        let path = changelog_path("existing");
+        for (existing, start) in [
+            (
+                "# Changelog\n\nIntro\n\n## 0.9\n\n- old\n",
+                "# Changelog\n\nIntro\n\n## 1.0\n",
+            ),
+            ("## 0.9\n\n- old\n", "## 1.0\n"),
+        ] {
+            fs::write(&path, existing).unwrap();
+            trac.add_changelog_section(&path, "1.0", vec![ticket(2, "Hang")])
+                .unwrap();
+            let text = fs::read_to_string(&path).unwrap();
+            assert!(text.starts_with(start), "{}", text);
+            assert!(text.ends_with("Hang\n\n## 0.9\n\n- old\n"), "{}", text);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn skips_tickets_already_listed() {
+        let trac = testing::offline(testing::config());
+        let path = changelog_path("listed");
+        let existing = "## 0.9\n\n- [#1](https://127.0.0.1:1/trac/ticket/1) Crash\n";
+        fs::write(&path, existing).unwrap();
+        let none = trac.add_changelog_section(&path, "1.0", vec![ticket(1, "Crash")]);
+        let unchanged = fs::read_to_string(&path).unwrap();
+        let some =
+            trac.add_changelog_section(&path, "1.0", vec![ticket(1, "Crash"), ticket(11, "Leak")]);
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(none, Ok(0));
+        assert_eq!(unchanged, existing);
+        assert_eq!(some, Ok(1));
+        assert_eq!(text.matches("[#1](").count(), 1);
+        assert!(text.contains("[#11]("));
+    }
+}
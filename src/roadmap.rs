@@ -0,0 +1,94 @@
+use std::time::SystemTime;
+
+use xmlrpc::{Request, Value};
+
+use crate::{scalar_to_string, time, TicketQuery, Trac, TracError};
+
+/// A milestone with its ticket counts, as shown on Trac's roadmap page.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoadmapEntry {
+    pub name: String,
+    pub description: String,
+    pub due: Option<SystemTime>,
+    pub completed: Option<SystemTime>,
+    pub open_tickets: usize,
+    pub closed_tickets: usize,
+}
+
+impl RoadmapEntry {
+    pub fn is_completed(&self) -> bool {
+        self.completed.is_some()
+    }
+
+    pub fn total_tickets(&self) -> usize {
+        self.open_tickets + self.closed_tickets
+    }
+
+    /// The share of tickets closed, between 0 and 1; 0 if there are none.
+    pub fn progress(&self) -> f64 {
+        match self.total_tickets() {
+            0 => 0.0,
+            total => self.closed_tickets as f64 / total as f64,
+        }
+    }
+}
+
+// Unset dates come back as the integer 0.
+fn optional_time(value: Option<&Value>) -> Option<SystemTime> {
+    value
+        .and_then(|v| v.as_datetime())
+        .map(|dt| time::from_datetime(&dt))
+}
+
+fn count_request<'a>(query: TicketQuery) -> Result<Request<'a>, TracError> {
+    Ok(Request::new("ticket.query").arg(query.max(0).query_string()?))
+}
+
+impl Trac {
+    /// Returns every milestone with its open and closed ticket counts,
+    /// ordered by due date with undated milestones last.
+    pub fn roadmap(&self) -> Result<Vec<RoadmapEntry>, TracError> {
+        let names: Vec<String> = match self.call(&Request::new("ticket.milestone.getAll"))? {
+            Value::Array(names) => names.iter().filter_map(scalar_to_string).collect(),
+            _ => return Err(TracError::invalid_response("ticket.milestone.getAll")),
+        };
+
+        let mut requests = Vec::with_capacity(names.len() * 3);
+        for name in &names {
+            let milestone = TicketQuery::new().is("milestone", name);
+            requests.push(Request::new("ticket.milestone.get").arg(name.as_str()));
+            requests.push(count_request(milestone.clone().open(self))?);
+            requests.push(count_request(milestone.closed(self))?);
+        }
+        let results = self.multicall(&requests)?;
+
+        let mut roadmap = Vec::with_capacity(names.len());
+        for (name, chunk) in names.into_iter().zip(results.chunks(3)) {
+            let (milestone, open, closed) = match chunk {
+                [Ok(m), Ok(Value::Array(open)), Ok(Value::Array(closed))] => (m, open, closed),
+                [Err(fault), ..] | [_, Err(fault), _] | [_, _, Err(fault)] => {
+                    return Err(TracError::from_fault(fault))
+                }
+                _ => return Err(TracError::invalid_response("ticket.milestone.get")),
+            };
+            let text = |key: &str| milestone.get(key).and_then(scalar_to_string);
+
+            roadmap.push(RoadmapEntry {
+                description: text("description").unwrap_or_default(),
+                due: optional_time(milestone.get("due")),
+                completed: optional_time(milestone.get("completed")),
+                open_tickets: open.len(),
+                closed_tickets: closed.len(),
+                name,
+            });
+        }
+
+        roadmap.sort_by(|a, b| match (a.due, b.due) {
+            (Some(x), Some(y)) => x.cmp(&y).then_with(|| a.name.cmp(&b.name)),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => a.name.cmp(&b.name),
+        });
+        Ok(roadmap)
+    }
+}
@@ -0,0 +1,241 @@
+use std::path::Path;
+
+use tantivy::collector::TopDocs;
+use tantivy::directory::MmapDirectory;
+use tantivy::query::QueryParser;
+use tantivy::schema::{Field, Schema, Value, FAST, INDEXED, STORED, STRING, TEXT};
+use tantivy::{doc, Index, IndexReader, IndexWriter, ReloadPolicy, TantivyDocument, Term};
+
+use crate::comments::comments_from_changelog;
+use crate::{TicketQuery, Trac, TracError, TracTicket};
+
+const WRITER_MEMORY: usize = 50_000_000;
+
+// Exact-match fields that can be used as filters, e.g. `status:new`.
+const FILTER_FIELDS: &[&str] = &["status", "owner", "component", "milestone", "priority"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    pub id: i32,
+    pub score: f32,
+    pub summary: String,
+}
+
+/// A tantivy index over ticket summaries, descriptions and comments, so
+/// that searching does not need a round trip to `search.performSearch`.
+pub struct LocalIndex {
+    index: Index,
+    reader: IndexReader,
+    id: Field,
+    summary: Field,
+    description: Field,
+    comments: Field,
+    filters: Vec<Field>,
+}
+
+fn index_error(e: tantivy::TantivyError) -> TracError {
+    TracError::Io(e.to_string())
+}
+
+fn schema() -> Schema {
+    let mut builder = Schema::builder();
+    builder.add_u64_field("id", INDEXED | STORED | FAST);
+    builder.add_text_field("summary", TEXT | STORED);
+    builder.add_text_field("description", TEXT);
+    builder.add_text_field("comments", TEXT);
+    for name in FILTER_FIELDS {
+        builder.add_text_field(name, STRING);
+    }
+    builder.build()
+}
+
+impl LocalIndex {
+    pub fn in_memory() -> Result<Self, TracError> {
+        Self::from_index(Index::create_in_ram(schema()))
+    }
+
+    /// Opens the index kept in `dir`, creating it if the directory is empty.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, TracError> {
+        let directory = MmapDirectory::open(dir).map_err(|e| TracError::Io(e.to_string()))?;
+        Self::from_index(Index::open_or_create(directory, schema()).map_err(index_error)?)
+    }
+
+    fn from_index(index: Index) -> Result<Self, TracError> {
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()
+            .map_err(index_error)?;
+        let schema = index.schema();
+        let field = |name: &str| schema.get_field(name).map_err(index_error);
+
+        Ok(Self {
+            id: field("id")?,
+            summary: field("summary")?,
+            description: field("description")?,
+            comments: field("comments")?,
+            filters: FILTER_FIELDS
+                .iter()
+                .map(|name| field(name))
+                .collect::<Result<_, _>>()?,
+            index,
+            reader,
+        })
+    }
+
+    /// Adds tickets with their comment texts, replacing any earlier copy.
+    pub fn add(&self, tickets: &[(TracTicket, Vec<String>)]) -> Result<(), TracError> {
+        let mut writer: IndexWriter = self.index.writer(WRITER_MEMORY).map_err(index_error)?;
+
+        for (ticket, comments) in tickets {
+            let id = ticket.id as u64;
+            writer.delete_term(Term::from_field_u64(self.id, id));
+
+            let mut document = doc!(
+                self.id => id,
+                self.summary => ticket.summary.as_str(),
+                self.description => ticket.description.as_str(),
+            );
+            for comment in comments {
+                document.add_text(self.comments, comment);
+            }
+            for (name, field) in FILTER_FIELDS.iter().zip(&self.filters) {
+                document.add_text(*field, ticket.field(name));
+            }
+            writer.add_document(document).map_err(index_error)?;
+        }
+
+        writer.commit().map_err(index_error)?;
+        self.reader.reload().map_err(index_error)
+    }
+
+    /// Returns the best `limit` matches for `query`, best first. The query
+    /// uses tantivy's syntax: words are looked up in the summary (ranked
+    /// higher), description and comments, and `status:new` style terms
+    /// filter on status, owner, component, milestone or priority.
+    pub fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchHit>, TracError> {
+        let mut parser = QueryParser::for_index(
+            &self.index,
+            vec![self.summary, self.description, self.comments],
+        );
+        parser.set_field_boost(self.summary, 2.0);
+        let query = parser
+            .parse_query(query)
+            .map_err(|e| TracError::InvalidQuery(e.to_string()))?;
+
+        let searcher = self.reader.searcher();
+        let top = searcher
+            .search(&query, &TopDocs::with_limit(limit))
+            .map_err(index_error)?;
+
+        let mut hits = Vec::with_capacity(top.len());
+        for (score, address) in top {
+            let document: TantivyDocument = searcher.doc(address).map_err(index_error)?;
+            let id = document.get_first(self.id).and_then(|v| v.as_u64());
+            let summary = document.get_first(self.summary).and_then(|v| v.as_str());
+            if let Some(id) = id {
+                hits.push(SearchHit {
+                    id: id as i32,
+                    score,
+                    summary: summary.unwrap_or("").to_string(),
+                });
+            }
+        }
+        Ok(hits)
+    }
+}
+
+impl Trac {
+    pub fn set_local_index(&mut self, index: LocalIndex) {
+        self.local_index = Some(index);
+    }
+
+    /// Fetches the tickets matching `query`, with their comments, and adds
+    /// them to the local index. Returns how many were indexed.
+    pub fn index_tickets(&self, query: &TicketQuery) -> Result<usize, TracError> {
+        let index = self.require_local_index()?;
+        let tickets = query.fetch(self)?;
+
+        let changelogs = self.changelogs(&tickets)?;
+        let mut entries = Vec::with_capacity(tickets.len());
+        for (ticket, changes) in tickets.into_iter().zip(changelogs) {
+            let comments = comments_from_changelog(&changes)
+                .into_iter()
+                .map(|c| c.text)
+                .collect();
+            entries.push((ticket, comments));
+        }
+
+        index.add(&entries)?;
+        Ok(entries.len())
+    }
+
+    pub fn local_search(&self, query: &str, limit: usize) -> Result<Vec<SearchHit>, TracError> {
+        self.require_local_index()?.search(query, limit)
+    }
+
+    fn require_local_index(&self) -> Result<&LocalIndex, TracError> {
+        self.local_index
+            .as_ref()
+            .ok_or_else(|| TracError::Unsupported("no local index has been set".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn ticket(id: i32, summary: &str, status: &str) -> TracTicket {
+        testing::ticket(id, &[("summary", summary), ("status", status)])
+    }
+
+    fn ids(hits: &[SearchHit]) -> Vec<i32> {
+        hits.iter().map(|h| h.id).collect()
+    }
+
+    #[test]
+    fn ranks_and_filters_matches() {
+        let index = LocalIndex::in_memory().unwrap();
+        index
+            .add(&[
+                (
+                    ticket(1, "Printing is slow", "new"),
+                    vec!["Seen with the crash reporter on".to_string()],
+                ),
+                (
+                    ticket(2, "Crash when saving", "assigned"),
+                    vec!["Happens every time".to_string()],
+                ),
+            ])
+            .unwrap();
+
+        let hits = index.search("crash", 10).unwrap();
+        assert_eq!(ids(&hits), [2, 1]);
+        assert_eq!(hits[0].summary, "Crash when saving");
+        assert!(hits[0].score > hits[1].score);
+        assert_eq!(ids(&index.search("crash AND status:new", 10).unwrap()), [1]);
+        assert_eq!(ids(&index.search("time", 10).unwrap()), [2]);
+        assert!(matches!(
+            index.search("status:(", 10),
+            Err(TracError::InvalidQuery(_))
+        ));
+    }
+
+    #[test]
+    fn replaces_readded_tickets() {
+        let index = LocalIndex::in_memory().unwrap();
+        index
+            .add(&[(ticket(1, "Crash on start", "new"), vec![])])
+            .unwrap();
+        index
+            .add(&[(ticket(1, "Crash on exit", "closed"), vec![])])
+            .unwrap();
+
+        let hits = index.search("crash", 10).unwrap();
+        assert_eq!(ids(&hits), [1]);
+        assert_eq!(hits[0].summary, "Crash on exit");
+        assert!(index.search("start", 10).unwrap().is_empty());
+        assert!(index.search("status:new", 10).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,237 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use reqwest::Url;
+use xmlrpc::{Request, Value};
+
+use crate::{scalar_to_string, time, Trac, TracError};
+
+/// The kinds of object `search.performSearch` can be limited to. Plugins
+/// may offer more; `Trac::search_filters` lists them all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchFilter {
+    Ticket,
+    Wiki,
+    Changeset,
+    Milestone,
+}
+
+impl SearchFilter {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SearchFilter::Ticket => "ticket",
+            SearchFilter::Wiki => "wiki",
+            SearchFilter::Changeset => "changeset",
+            SearchFilter::Milestone => "milestone",
+        }
+    }
+}
+
+/// What a search result links to, worked out from its href.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SearchTarget {
+    Ticket(i32),
+    Wiki(String),
+    /// `repository` is `None` for the default repository.
+    Changeset {
+        revision: String,
+        repository: Option<String>,
+    },
+    Milestone(String),
+    /// Anything else, e.g. from a plugin, with the full URL.
+    Other(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    pub target: SearchTarget,
+    pub href: String,
+    pub title: String,
+    pub date: SystemTime,
+    pub author: String,
+    pub excerpt: String,
+}
+
+pub(crate) fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok());
+        match (bytes[i], hex.and_then(|h| u8::from_str_radix(h, 16).ok())) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+impl Trac {
+    /// The search filters the server offers, as name and description.
+    pub fn search_filters(&self) -> Result<Vec<(String, String)>, TracError> {
+        match self.call(&Request::new("search.getSearchFilters"))? {
+            Value::Array(filters) => Ok(filters
+                .iter()
+                .filter_map(|f| {
+                    let text = |i: usize| f.get(i).and_then(scalar_to_string);
+                    Some((text(0)?, text(1).unwrap_or_default()))
+                })
+                .collect()),
+            _ => Err(TracError::invalid_response("search.getSearchFilters")),
+        }
+    }
+
+    /// Searches the server, in the kinds of object in `filters`, or in all
+    /// of them if it is empty.
+    pub fn search(
+        &self,
+        query: &str,
+        filters: &[SearchFilter],
+    ) -> Result<Vec<SearchResult>, TracError> {
+        let names: Vec<Value> = filters.iter().map(|f| f.name().into()).collect();
+        let mut request = Request::new("search.performSearch").arg(query);
+        if !names.is_empty() {
+            request = request.arg(Value::Array(names));
+        }
+
+        let results = match self.call(&request)? {
+            Value::Array(results) => results,
+            _ => return Err(TracError::invalid_response("search.performSearch")),
+        };
+        Ok(results
+            .iter()
+            .filter_map(|r| {
+                let text = |i: usize| r.get(i).and_then(scalar_to_string).unwrap_or_default();
+                let href = r.get(0).and_then(scalar_to_string)?;
+                Some(SearchResult {
+                    target: self.search_target(&href),
+                    title: text(1),
+                    date: r
+                        .get(2)
+                        .and_then(|v| v.as_datetime())
+                        .map_or(UNIX_EPOCH, |dt| time::from_datetime(&dt)),
+                    author: text(3),
+                    excerpt: text(4),
+                    href,
+                })
+            })
+            .collect())
+    }
+
+    // Hrefs may be absolute or relative to the server; either way the part
+    // after the Trac path says what they point at.
+    fn search_target(&self, href: &str) -> SearchTarget {
+        let url = match Url::parse(&self.url()).and_then(|base| base.join(href)) {
+            Ok(url) => url,
+            Err(_) => return SearchTarget::Other(href.to_string()),
+        };
+        let other = || SearchTarget::Other(url.to_string());
+        let path = match url
+            .path()
+            .strip_prefix(self.config.path.trim_end_matches('/'))
+        {
+            Some(path) => path.trim_start_matches('/'),
+            None => return other(),
+        };
+
+        let (kind, rest) = path.split_once('/').unwrap_or((path, ""));
+        let rest = percent_decode(rest);
+        match kind {
+            "ticket" => rest.parse().map_or_else(|_| other(), SearchTarget::Ticket),
+            "wiki" if !rest.is_empty() => SearchTarget::Wiki(rest),
+            "wiki" => SearchTarget::Wiki("WikiStart".to_string()),
+            "changeset" if !rest.is_empty() => {
+                let (revision, repository) = match rest.split_once('/') {
+                    Some((revision, repository)) if !repository.is_empty() => {
+                        (revision, Some(repository.to_string()))
+                    }
+                    _ => (rest.trim_end_matches('/'), None),
+                };
+                SearchTarget::Changeset {
+                    revision: revision.to_string(),
+                    repository,
+                }
+            }
+            "milestone" if !rest.is_empty() => SearchTarget::Milestone(rest),
+            _ => other(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn target(href: &str) -> SearchTarget {
+        testing::offline(testing::config()).search_target(href)
+    }
+
+    #[test]
+    fn reads_relative_and_absolute_hrefs() {
+        assert_eq!(target("/trac/ticket/42"), SearchTarget::Ticket(42));
+        assert_eq!(
+            target("https://127.0.0.1:1/trac/ticket/7"),
+            SearchTarget::Ticket(7)
+        );
+        assert_eq!(
+            target("/trac/wiki/Dev/Setup%20Guide"),
+            SearchTarget::Wiki("Dev/Setup Guide".to_string())
+        );
+        assert_eq!(
+            target("/trac/wiki"),
+            SearchTarget::Wiki("WikiStart".to_string())
+        );
+        assert_eq!(
+            target("/trac/milestone/1.0%20beta"),
+            SearchTarget::Milestone("1.0 beta".to_string())
+        );
+    }
+
+    #[test]
+    fn reads_changesets() {
+        assert_eq!(
+            target("/trac/changeset/1234"),
+            SearchTarget::Changeset {
+                revision: "1234".to_string(),
+                repository: None
+            }
+        );
+        assert_eq!(
+            target("/trac/changeset/abc123/tools"),
+            SearchTarget::Changeset {
+                revision: "abc123".to_string(),
+                repository: Some("tools".to_string())
+            }
+        );
+    }
+
+    #[test]
+    fn keeps_anything_else_as_a_url() {
+        assert_eq!(
+            target("/trac/ticket/notanumber"),
+            SearchTarget::Other("https://127.0.0.1:1/trac/ticket/notanumber".to_string())
+        );
+        assert_eq!(
+            target("/elsewhere/ticket/1"),
+            SearchTarget::Other("https://127.0.0.1:1/elsewhere/ticket/1".to_string())
+        );
+        assert_eq!(
+            target("/trac/discussion/topic/3"),
+            SearchTarget::Other("https://127.0.0.1:1/trac/discussion/topic/3".to_string())
+        );
+    }
+
+    #[test]
+    fn decodes_percent_escapes() {
+        assert_eq!(percent_decode("a%20b%2Fc%zz%"), "a b/c%zz%");
+        assert_eq!(percent_decode("caf%C3%A9"), "café");
+    }
+}
@@ -0,0 +1,312 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime};
+
+use crate::{time, QueryCondition, TicketQuery, Trac, TracChange, TracError, TracTicket};
+
+// How far towards its deadline an open target is before it counts as at risk.
+const DEFAULT_AT_RISK: f64 = 0.75;
+
+/// A status a ticket must first reach within some time of being created,
+/// e.g. `accepted` within 4 hours.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlaTarget {
+    pub status: String,
+    pub within: Duration,
+}
+
+/// Deadlines for the tickets matching some conditions, read from an
+/// `[sla:<name>]` section:
+///
+/// ```ini
+/// [sla:critical]
+/// match = priority=critical
+/// accepted = 4h
+/// closed = 3d
+/// at_risk = 0.8
+/// ```
+///
+/// `match` is a query string, every other key names a status and how soon
+/// it must be reached (`30m`, `4h`, `3d`, `1w`, or seconds). `at_risk` is
+/// the fraction of the time allowed after which an unmet target is at risk.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlaPolicy {
+    pub name: String,
+    pub conditions: Vec<QueryCondition>,
+    pub targets: Vec<SlaTarget>,
+    pub at_risk: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SlaStatus {
+    /// Reached before the deadline.
+    Met { reached: SystemTime },
+    /// Not reached yet, with time to spare.
+    Pending,
+    /// Not reached yet, and past the at-risk point.
+    AtRisk,
+    /// Reached late, or not at all and the deadline has passed.
+    Breached { reached: Option<SystemTime> },
+}
+
+/// How one ticket stands against one target of a policy.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlaCheck {
+    pub ticket: i32,
+    pub policy: String,
+    pub target: String,
+    pub deadline: SystemTime,
+    pub status: SlaStatus,
+}
+
+/// Counts of checks in each state.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SlaSummary {
+    pub met: usize,
+    pub pending: usize,
+    pub at_risk: usize,
+    pub breached: usize,
+}
+
+impl SlaSummary {
+    /// Summarises `checks` per policy.
+    pub fn by_policy(checks: &[SlaCheck]) -> BTreeMap<String, SlaSummary> {
+        let mut summaries: BTreeMap<String, SlaSummary> = BTreeMap::new();
+        for check in checks {
+            let summary = summaries.entry(check.policy.to_owned()).or_default();
+            match check.status {
+                SlaStatus::Met { .. } => summary.met += 1,
+                SlaStatus::Pending => summary.pending += 1,
+                SlaStatus::AtRisk => summary.at_risk += 1,
+                SlaStatus::Breached { .. } => summary.breached += 1,
+            }
+        }
+        summaries
+    }
+
+    pub fn total(&self) -> usize {
+        self.met + self.pending + self.at_risk + self.breached
+    }
+}
+
+impl SlaPolicy {
+    pub(crate) fn from_section(
+        name: &str,
+        section: &BTreeMap<String, String>,
+    ) -> Result<Self, TracError> {
+        let mut policy = SlaPolicy {
+            name: name.to_string(),
+            conditions: vec![],
+            targets: vec![],
+            at_risk: DEFAULT_AT_RISK,
+        };
+        for (key, value) in section {
+            match key.as_str() {
+                "match" => policy.conditions = TicketQuery::parse(value)?.conditions,
+                "at_risk" => {
+                    policy.at_risk = value
+                        .parse()
+                        .ok()
+                        .filter(|f: &f64| (0.0..=1.0).contains(f))
+                        .ok_or_else(|| {
+                            TracError::Config(format!(
+                                "invalid value for [sla:{}] at_risk: {} (expected 0 to 1)",
+                                name, value
+                            ))
+                        })?
+                }
+                status => {
+                    let within = time::parse_duration(value).ok_or_else(|| {
+                        TracError::Config(format!(
+                            "invalid duration for [sla:{}] {}: {}",
+                            name, status, value
+                        ))
+                    })?;
+                    policy.targets.push(SlaTarget {
+                        status: status.to_string(),
+                        within,
+                    });
+                }
+            }
+        }
+        Ok(policy)
+    }
+
+    pub fn applies_to(&self, ticket: &TracTicket) -> bool {
+        self.conditions.iter().all(|c| c.matches(ticket))
+    }
+
+    /// Checks each target against the ticket's changelog as of `now`. A
+    /// target is reached the first time the ticket enters its status.
+    pub fn evaluate(
+        &self,
+        ticket: &TracTicket,
+        changes: &[TracChange],
+        now: SystemTime,
+    ) -> Vec<SlaCheck> {
+        self.targets
+            .iter()
+            .map(|target| {
+                let deadline = ticket.created + target.within;
+                let reached = changes
+                    .iter()
+                    .find(|c| c.field == "status" && c.new_value == target.status)
+                    .map(|c| c.time);
+                let at_risk = ticket.created + target.within.mul_f64(self.at_risk);
+                let status = match reached {
+                    Some(reached) if reached <= deadline => SlaStatus::Met { reached },
+                    Some(reached) => SlaStatus::Breached {
+                        reached: Some(reached),
+                    },
+                    None if now > deadline => SlaStatus::Breached { reached: None },
+                    None if now >= at_risk => SlaStatus::AtRisk,
+                    None => SlaStatus::Pending,
+                };
+                SlaCheck {
+                    ticket: ticket.id,
+                    policy: self.name.to_owned(),
+                    target: target.status.to_owned(),
+                    deadline,
+                    status,
+                }
+            })
+            .collect()
+    }
+}
+
+impl TracTicket {
+    /// Checks the ticket against every configured policy that applies to it.
+    pub fn sla(&self, trac: &Trac) -> Result<Vec<SlaCheck>, TracError> {
+        let policies: Vec<&SlaPolicy> = trac
+            .config
+            .sla
+            .iter()
+            .filter(|p| p.applies_to(self))
+            .collect();
+        if policies.is_empty() {
+            return Ok(vec![]);
+        }
+        let changes = self.changelog(trac)?;
+        let now = SystemTime::now();
+        Ok(policies
+            .iter()
+            .flat_map(|p| p.evaluate(self, &changes, now))
+            .collect())
+    }
+}
+
+impl Trac {
+    /// Checks every ticket matching `query` against the configured policies.
+    pub fn sla_report(&self, query: &TicketQuery) -> Result<Vec<SlaCheck>, TracError> {
+        let tickets: Vec<TracTicket> = query
+            .fetch(self)?
+            .into_iter()
+            .filter(|t| self.config.sla.iter().any(|p| p.applies_to(t)))
+            .collect();
+        let changelogs = self.changelogs(&tickets)?;
+        let now = SystemTime::now();
+
+        let mut checks = vec![];
+        for (ticket, changes) in tickets.iter().zip(changelogs) {
+            for policy in self.config.sla.iter().filter(|p| p.applies_to(ticket)) {
+                checks.extend(policy.evaluate(ticket, &changes, now));
+            }
+        }
+        Ok(checks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn section(entries: &[(&str, &str)]) -> BTreeMap<String, String> {
+        entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn status_change(hours: u64, status: &str) -> TracChange {
+        TracChange {
+            time: SystemTime::UNIX_EPOCH + Duration::from_secs(hours * 3600),
+            author: "alice".to_string(),
+            field: "status".to_string(),
+            old_value: "new".to_string(),
+            new_value: status.to_string(),
+            permanent: true,
+        }
+    }
+
+    #[test]
+    fn parses_a_policy() {
+        let policy = SlaPolicy::from_section(
+            "critical",
+            &section(&[
+                ("match", "priority=critical"),
+                ("accepted", "4h"),
+                ("at_risk", "0.5"),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(policy.conditions.len(), 1);
+        assert_eq!(
+            policy.targets,
+            vec![SlaTarget {
+                status: "accepted".to_string(),
+                within: Duration::from_secs(4 * 3600),
+            }]
+        );
+        assert_eq!(policy.at_risk, 0.5);
+    }
+
+    #[test]
+    fn rejects_at_risk_outside_zero_to_one() {
+        for value in ["-0.1", "1.5", "NaN", "inf", "soon"] {
+            let result = SlaPolicy::from_section("p", &section(&[("at_risk", value)]));
+            assert!(
+                matches!(result, Err(TracError::Config(_))),
+                "accepted at_risk = {}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_overflowing_durations() {
+        let result = SlaPolicy::from_section("p", &section(&[("closed", "99999999999999999w")]));
+        assert!(matches!(result, Err(TracError::Config(_))));
+    }
+
+    #[test]
+    fn evaluates_targets() {
+        let policy = SlaPolicy::from_section(
+            "p",
+            &section(&[("accepted", "4h"), ("assigned", "4h"), ("closed", "10h")]),
+        )
+        .unwrap();
+        let mut ticket = testing::ticket(1, &[]);
+        ticket.created = SystemTime::UNIX_EPOCH;
+        let changes = [status_change(2, "accepted"), status_change(5, "assigned")];
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(8 * 3600);
+
+        let statuses: Vec<SlaStatus> = policy
+            .evaluate(&ticket, &changes, now)
+            .into_iter()
+            .map(|check| check.status)
+            .collect();
+        // BTreeMap sections come back sorted by key.
+        assert_eq!(
+            statuses,
+            vec![
+                SlaStatus::Met {
+                    reached: changes[0].time
+                },
+                SlaStatus::Breached {
+                    reached: Some(changes[1].time)
+                },
+                SlaStatus::AtRisk,
+            ]
+        );
+    }
+}
@@ -0,0 +1,300 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+use crate::{time, TicketQuery, Trac, TracError, TracTicket};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldChange {
+    pub field: String,
+    pub old_value: String,
+    pub new_value: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangedTicket {
+    pub id: i32,
+    pub changes: Vec<FieldChange>,
+}
+
+/// How the results of a query differ between two runs.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QueryDelta {
+    /// Tickets the query matches now but did not before.
+    pub added: Vec<i32>,
+    /// Tickets the query no longer matches, e.g. because they were closed
+    /// or moved to another milestone.
+    pub removed: Vec<i32>,
+    /// Tickets matched both times whose recorded fields changed.
+    pub changed: Vec<ChangedTicket>,
+}
+
+impl QueryDelta {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// The tickets a query matched at one point, with their field values, so a
+/// later run can be compared with `diff`.
+///
+/// Only the query's `columns` are recorded if it names any, which keeps
+/// saved snapshots small and leaves out changes nobody looks at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuerySnapshot {
+    pub taken_at: SystemTime,
+    pub tickets: BTreeMap<i32, BTreeMap<String, String>>,
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('t') => out.push('\t'),
+                Some('n') => out.push('\n'),
+                Some('r') => out.push('\r'),
+                Some(other) => out.push(other),
+                None => out.push('\\'),
+            },
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+impl QuerySnapshot {
+    /// Runs `query` and records what it matches.
+    pub fn take(query: &TicketQuery, trac: &Trac) -> Result<Self, TracError> {
+        let taken_at = SystemTime::now();
+        let tickets = query.fetch(trac)?;
+        Ok(Self::from_tickets(&tickets, &query.columns, taken_at))
+    }
+
+    /// Records `tickets`, keeping only `columns` unless it is empty. Internal
+    /// fields such as `_ts` are always left out.
+    pub fn from_tickets(tickets: &[TracTicket], columns: &[String], taken_at: SystemTime) -> Self {
+        let tickets = tickets
+            .iter()
+            .map(|ticket| {
+                let fields = ticket
+                    .fields
+                    .iter()
+                    .filter(|(name, _)| !name.starts_with('_'))
+                    .filter(|(name, _)| columns.is_empty() || columns.contains(name))
+                    .map(|(name, value)| (name.to_owned(), value.to_owned()))
+                    .collect();
+                (ticket.id, fields)
+            })
+            .collect();
+        Self { taken_at, tickets }
+    }
+
+    /// What changed since `older`, taken from the same query. Only fields
+    /// recorded in both snapshots are compared.
+    pub fn diff(&self, older: &QuerySnapshot) -> QueryDelta {
+        let mut delta = QueryDelta::default();
+        for (id, fields) in &self.tickets {
+            let old_fields = match older.tickets.get(id) {
+                Some(old_fields) => old_fields,
+                None => {
+                    delta.added.push(*id);
+                    continue;
+                }
+            };
+            let changes: Vec<FieldChange> = fields
+                .iter()
+                .filter_map(|(name, new_value)| {
+                    let old_value = old_fields.get(name)?;
+                    (old_value != new_value).then(|| FieldChange {
+                        field: name.to_owned(),
+                        old_value: old_value.to_owned(),
+                        new_value: new_value.to_owned(),
+                    })
+                })
+                .collect();
+            if !changes.is_empty() {
+                delta.changed.push(ChangedTicket { id: *id, changes });
+            }
+        }
+        delta.removed = older
+            .tickets
+            .keys()
+            .filter(|id| !self.tickets.contains_key(id))
+            .copied()
+            .collect();
+        delta
+    }
+
+    /// Writes the snapshot to `path`: a `taken_at` line, then a line per
+    /// ticket id followed by tab-separated `id`, field and value lines.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), TracError> {
+        let path = path.as_ref();
+        let mut text = format!("taken_at\t{}\n", time::to_unix(self.taken_at));
+        for (id, fields) in &self.tickets {
+            text.push_str(&format!("{}\n", id));
+            for (name, value) in fields {
+                text.push_str(&format!("{}\t{}\t{}\n", id, name, escape(value)));
+            }
+        }
+        let mut temporary = path.to_path_buf().into_os_string();
+        temporary.push(".tmp");
+        fs::write(&temporary, text)
+            .and_then(|_| fs::rename(&temporary, path))
+            .map_err(|e| TracError::Io(format!("{}: {}", path.display(), e)))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, TracError> {
+        let path = path.as_ref();
+        let invalid = |line: &str| {
+            TracError::Io(format!(
+                "{}: invalid snapshot line '{}'",
+                path.display(),
+                line
+            ))
+        };
+
+        let text = fs::read_to_string(path)
+            .map_err(|e| TracError::Io(format!("{}: {}", path.display(), e)))?;
+        let mut taken_at = None;
+        let mut tickets: BTreeMap<i32, BTreeMap<String, String>> = BTreeMap::new();
+        for line in text.lines().filter(|l| !l.is_empty()) {
+            let parts: Vec<&str> = line.splitn(3, '\t').collect();
+            match parts.as_slice() {
+                ["taken_at", seconds] => {
+                    let seconds = seconds.parse().map_err(|_| invalid(line))?;
+                    taken_at = Some(time::from_unix(seconds));
+                }
+                [id] => {
+                    let id = id.parse().map_err(|_| invalid(line))?;
+                    tickets.entry(id).or_default();
+                }
+                [id, name, value] => {
+                    let id = id.parse().map_err(|_| invalid(line))?;
+                    tickets
+                        .entry(id)
+                        .or_default()
+                        .insert(name.to_string(), unescape(value));
+                }
+                _ => return Err(invalid(line)),
+            }
+        }
+
+        Ok(Self {
+            taken_at: taken_at.ok_or_else(|| invalid("taken_at"))?,
+            tickets,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use std::time::UNIX_EPOCH;
+
+    fn snapshot(tickets: &[(i32, &[(&str, &str)])]) -> QuerySnapshot {
+        QuerySnapshot {
+            taken_at: UNIX_EPOCH,
+            tickets: tickets
+                .iter()
+                .map(|(id, fields)| {
+                    let fields = fields
+                        .iter()
+                        .map(|(k, v)| (k.to_string(), v.to_string()))
+                        .collect();
+                    (*id, fields)
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn diffs_added_removed_and_changed() {
+        let older = snapshot(&[
+            (1, &[("status", "new"), ("owner", "alice")]),
+            (2, &[("status", "new")]),
+            (3, &[("status", "assigned")]),
+        ]);
+        let newer = snapshot(&[
+            (
+                1,
+                &[
+                    ("status", "accepted"),
+                    ("owner", "alice"),
+                    ("milestone", "1.0"),
+                ],
+            ),
+            (3, &[("status", "assigned")]),
+            (4, &[("status", "new")]),
+        ]);
+
+        let delta = newer.diff(&older);
+        assert_eq!(delta.added, [4]);
+        assert_eq!(delta.removed, [2]);
+        // The milestone was not recorded before, so only the status counts.
+        assert_eq!(
+            delta.changed,
+            [ChangedTicket {
+                id: 1,
+                changes: vec![FieldChange {
+                    field: "status".to_string(),
+                    old_value: "new".to_string(),
+                    new_value: "accepted".to_string(),
+                }],
+            }]
+        );
+        assert!(newer.diff(&newer).is_empty());
+    }
+
+    #[test]
+    fn records_only_the_query_columns() {
+        let ticket = testing::ticket(5, &[("status", "new"), ("owner", "bob"), ("_ts", "123")]);
+        let tickets = [ticket];
+        let all = QuerySnapshot::from_tickets(&tickets, &[], UNIX_EPOCH);
+        assert!(all.tickets[&5].contains_key("owner"));
+        assert!(!all.tickets[&5].contains_key("_ts"));
+
+        let columns = ["status".to_string()];
+        let some = QuerySnapshot::from_tickets(&tickets, &columns, UNIX_EPOCH);
+        assert_eq!(some.tickets[&5].keys().collect::<Vec<_>>(), ["status"]);
+    }
+
+    #[test]
+    fn saves_and_loads() {
+        let path = std::env::temp_dir().join(format!("trac-snapshot-{}", std::process::id()));
+        let saved = snapshot(&[
+            (1, &[("description", "line one\n\tand\\two\r\n")]),
+            (2, &[]),
+        ]);
+        saved.save(&path).unwrap();
+        let loaded = QuerySnapshot::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, Ok(saved));
+    }
+
+    #[test]
+    fn rejects_invalid_snapshots() {
+        let path = std::env::temp_dir().join(format!("trac-bad-snapshot-{}", std::process::id()));
+        fs::write(&path, "taken_at\t0\nnot-an-id\n").unwrap();
+        let loaded = QuerySnapshot::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(loaded, Err(TracError::Io(_))));
+    }
+}
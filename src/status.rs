@@ -0,0 +1,171 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use xmlrpc::Request;
+
+use crate::{QueryOp, TicketQuery, Trac, TracError, TracTicket};
+
+/// A ticket status, classified as open or closed by
+/// `TracConfig::closed_statuses`. Statuses sort open before closed, then by
+/// name.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Status {
+    closed: bool,
+    name: String,
+}
+
+impl Status {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_open(&self) -> bool {
+        !self.closed
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl Trac {
+    pub fn status(&self, name: &str) -> Status {
+        Status {
+            closed: self.config.closed_statuses.iter().any(|s| s == name),
+            name: name.to_string(),
+        }
+    }
+
+    /// Every status the server's workflow defines.
+    pub fn statuses(&self) -> Result<Vec<Status>, TracError> {
+        let names = self.call(&Request::new("ticket.status.getAll"))?;
+        let names = names
+            .as_array()
+            .ok_or_else(|| TracError::invalid_response("ticket.status.getAll"))?;
+        Ok(names
+            .iter()
+            .filter_map(|n| n.as_str())
+            .map(|n| self.status(n))
+            .collect())
+    }
+
+    /// Groups `tickets` by status, open statuses first.
+    pub fn group_by_status<'a>(
+        &self,
+        tickets: &'a [TracTicket],
+    ) -> BTreeMap<Status, Vec<&'a TracTicket>> {
+        let mut groups: BTreeMap<Status, Vec<&TracTicket>> = BTreeMap::new();
+        for ticket in tickets {
+            groups
+                .entry(self.status(&ticket.status))
+                .or_default()
+                .push(ticket);
+        }
+        groups
+    }
+
+    /// Splits `tickets` into the open ones and the closed ones.
+    pub fn split_open<'a>(
+        &self,
+        tickets: &'a [TracTicket],
+    ) -> (Vec<&'a TracTicket>, Vec<&'a TracTicket>) {
+        tickets.iter().partition(|t| t.is_open(self))
+    }
+}
+
+impl TicketQuery {
+    /// Limits the query to tickets in an open status.
+    pub fn open(self, trac: &Trac) -> Self {
+        let closed: Vec<&str> = trac
+            .config
+            .closed_statuses
+            .iter()
+            .map(String::as_str)
+            .collect();
+        self.filter("status", QueryOp::IsNot, &closed)
+    }
+
+    /// Limits the query to tickets in a closed status.
+    pub fn closed(self, trac: &Trac) -> Self {
+        let closed: Vec<&str> = trac
+            .config
+            .closed_statuses
+            .iter()
+            .map(String::as_str)
+            .collect();
+        self.filter("status", QueryOp::Is, &closed)
+    }
+}
+
+impl TracTicket {
+    pub fn is_open(&self, trac: &Trac) -> bool {
+        trac.status(&self.status).is_open()
+    }
+
+    pub fn is_closed(&self, trac: &Trac) -> bool {
+        trac.status(&self.status).is_closed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn trac() -> Trac {
+        let mut config = testing::config();
+        config.closed_statuses = vec!["closed".to_string(), "rejected".to_string()];
+        testing::offline(config)
+    }
+
+    #[test]
+    fn classifies_configured_statuses() {
+        let trac = trac();
+        assert!(trac.status("rejected").is_closed());
+        assert!(trac.status("new").is_open());
+    }
+
+    #[test]
+    fn sorts_open_statuses_first() {
+        let trac = trac();
+        let mut statuses = [
+            trac.status("closed"),
+            trac.status("new"),
+            trac.status("assigned"),
+        ];
+        statuses.sort();
+        let names: Vec<&str> = statuses.iter().map(Status::name).collect();
+        assert_eq!(names, ["assigned", "new", "closed"]);
+    }
+
+    #[test]
+    fn queries_use_configured_statuses() {
+        let trac = trac();
+        assert_eq!(
+            TicketQuery::new().open(&trac).to_string(),
+            "status=!closed|!rejected"
+        );
+        assert_eq!(
+            TicketQuery::new().closed(&trac).to_string(),
+            "status=closed|rejected"
+        );
+    }
+
+    #[test]
+    fn splits_tickets() {
+        let trac = trac();
+        let tickets = vec![
+            testing::ticket(1, &[("status", "new")]),
+            testing::ticket(2, &[("status", "rejected")]),
+        ];
+        let (open, closed) = trac.split_open(&tickets);
+        assert_eq!(open[0].id, 1);
+        assert_eq!(closed[0].id, 2);
+    }
+}
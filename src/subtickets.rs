@@ -0,0 +1,107 @@
+use std::collections::BTreeSet;
+
+use crate::{parse_ticket_refs, Trac, TracError, TracTicket};
+
+/// A ticket together with its (transitive) children, as maintained by the
+/// ChildTickets or Subtickets plugins.
+#[derive(Debug)]
+pub struct TicketTree {
+    pub ticket: TracTicket,
+    pub children: Vec<TicketTree>,
+}
+
+impl TicketTree {
+    pub fn ids(&self) -> Vec<i32> {
+        let mut ids = vec![self.ticket.id];
+        for child in &self.children {
+            ids.extend(child.ids());
+        }
+        ids
+    }
+}
+
+impl TracTicket {
+    pub fn parent_ids(&self, trac: &Trac) -> Vec<i32> {
+        parse_ticket_refs(self.field(&trac.config.parent_field))
+    }
+
+    pub fn parent(&self, trac: &Trac) -> Result<Option<TracTicket>, TracError> {
+        match self.parent_ids(trac).first() {
+            Some(id) => trac.get_ticket(*id).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn children(&self, trac: &Trac) -> Result<Vec<TracTicket>, TracError> {
+        // `~` is a substring match, so #12 also finds tickets under #123.
+        let query = format!("{}=~{}", trac.config.parent_field, self.id);
+        let mut children = Vec::new();
+        for ticket in trac.get_tickets(&trac.query(&query)?)? {
+            if ticket.parent_ids(trac).contains(&self.id) {
+                children.push(ticket);
+            }
+        }
+        Ok(children)
+    }
+
+    fn ancestor_ids(&self, trac: &Trac) -> Result<BTreeSet<i32>, TracError> {
+        let mut seen = BTreeSet::new();
+        let mut pending = self.parent_ids(trac);
+        while let Some(id) = pending.pop() {
+            if seen.insert(id) && id != self.id {
+                pending.extend(trac.get_ticket(id)?.parent_ids(trac));
+            }
+        }
+        Ok(seen)
+    }
+
+    /// Makes `child_id` a child of this ticket, refusing if that would
+    /// create a cycle.
+    pub fn add_child(&self, child_id: i32, trac: &Trac) -> Result<TracTicket, TracError> {
+        if child_id == self.id || self.ancestor_ids(trac)?.contains(&child_id) {
+            return Err(TracError::Unsupported(format!(
+                "making #{} a child of #{} would create a cycle",
+                child_id, self.id
+            )));
+        }
+
+        let child = trac.get_ticket(child_id)?;
+        let mut parents = child.parent_ids(trac);
+        if parents.contains(&self.id) {
+            return Ok(child);
+        }
+        parents.push(self.id);
+
+        let value = parents
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<String>>()
+            .join(", ");
+        child
+            .update_builder()
+            .set(&trac.config.parent_field, &value)
+            .submit(trac)
+    }
+
+    /// Builds the tree of all tickets below this one. A ticket reachable
+    /// through more than one path, or through a cycle, appears only once.
+    pub fn tree(self, trac: &Trac) -> Result<TicketTree, TracError> {
+        let mut seen = BTreeSet::new();
+        seen.insert(self.id);
+        build_tree(self, trac, &mut seen)
+    }
+}
+
+fn build_tree(
+    ticket: TracTicket,
+    trac: &Trac,
+    seen: &mut BTreeSet<i32>,
+) -> Result<TicketTree, TracError> {
+    let mut children = Vec::new();
+    for child in ticket.children(trac)? {
+        if seen.insert(child.id) {
+            children.push(build_tree(child, trac, seen)?);
+        }
+    }
+    Ok(TicketTree { ticket, children })
+}
@@ -0,0 +1,258 @@
+use std::collections::BTreeMap;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+use xmlrpc::{Request, Value};
+
+use crate::{
+    time, DeltaSync, MemoryWatermark, SyncDelta, TicketQuery, Trac, TracError, TracTicket,
+};
+
+impl Trac {
+    /// Returns the ids of tickets changed since `when`.
+    pub fn tickets_changed_since(&self, when: SystemTime) -> Result<Vec<i32>, TracError> {
+        let xmlrpc_req =
+            Request::new("ticket.getRecentChanges").arg(Value::DateTime(time::to_datetime(when)));
+
+        match self.call(&xmlrpc_req) {
+            Ok(r) => match r.as_array() {
+                Some(ids) => Ok(ids.iter().filter_map(|v| v.as_i32()).collect()),
+                None => Err(TracError::invalid_response("ticket.getRecentChanges")),
+            },
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// What a `SyncService` last read from the server.
+#[derive(Debug, Default)]
+pub struct SyncSnapshot {
+    pub tickets: BTreeMap<i32, Arc<TracTicket>>,
+    /// When the last successful sync started, by the local clock; `None`
+    /// before the first.
+    pub synced_at: Option<SystemTime>,
+    /// The error from the last sync, if it failed; the tickets are then
+    /// those of the sync before it.
+    pub last_error: Option<TracError>,
+}
+
+enum Command {
+    Refresh,
+    Stop,
+}
+
+/// Keeps the tickets matching a query up to date on a background thread.
+/// The first sync fetches them all; later ones only re-fetch the tickets
+/// the server reports as changed. Syncs go through a `DeltaSync`, so they
+/// follow the server's change times and look back `DEFAULT_OVERLAP`.
+pub struct SyncService {
+    snapshot: Arc<Mutex<Arc<SyncSnapshot>>>,
+    commands: Sender<Command>,
+    thread: Option<JoinHandle<()>>,
+}
+
+// Applies a delta to the tickets of the previous sync. The first delta
+// holds every matching ticket, so it replaces them.
+fn sync_changes(
+    previous: &BTreeMap<i32, Arc<TracTicket>>,
+    delta: SyncDelta,
+) -> BTreeMap<i32, Arc<TracTicket>> {
+    let mut tickets = match delta.since {
+        Some(_) => previous.clone(),
+        None => BTreeMap::new(),
+    };
+    for id in &delta.removed {
+        tickets.remove(id);
+    }
+    for ticket in delta.changed {
+        tickets.insert(ticket.id, Arc::new(ticket));
+    }
+    tickets
+}
+
+impl SyncService {
+    /// Starts syncing every `interval`. `connect` is run on the background
+    /// thread to create the `Trac` it uses, since a `Trac` cannot be moved
+    /// between threads; if it fails, the error is left in the snapshot and
+    /// the thread exits.
+    pub fn start<F>(connect: F, query: TicketQuery, interval: Duration) -> Self
+    where
+        F: FnOnce() -> Result<Trac, TracError> + Send + 'static,
+    {
+        let snapshot = Arc::new(Mutex::new(Arc::new(SyncSnapshot::default())));
+        let (commands, receiver) = mpsc::channel();
+
+        let shared = Arc::clone(&snapshot);
+        let publish = move |next: SyncSnapshot| {
+            if let Ok(mut current) = shared.lock() {
+                *current = Arc::new(next);
+            }
+        };
+        let current = {
+            let shared = Arc::clone(&snapshot);
+            move || shared.lock().map(|s| Arc::clone(&s)).unwrap_or_default()
+        };
+
+        let thread = thread::spawn(move || {
+            let trac = match connect() {
+                Ok(trac) => trac,
+                Err(e) => {
+                    publish(SyncSnapshot {
+                        last_error: Some(e),
+                        ..SyncSnapshot::default()
+                    });
+                    return;
+                }
+            };
+
+            let delta_sync = DeltaSync::new(MemoryWatermark::default());
+            loop {
+                let previous = current();
+                let started = SystemTime::now();
+                let result = delta_sync.fetch(&trac, &query).and_then(|delta| {
+                    delta_sync.commit(&delta)?;
+                    Ok(sync_changes(&previous.tickets, delta))
+                });
+                publish(match result {
+                    Ok(tickets) => SyncSnapshot {
+                        tickets,
+                        synced_at: Some(started),
+                        last_error: None,
+                    },
+                    Err(e) => SyncSnapshot {
+                        tickets: previous.tickets.clone(),
+                        synced_at: previous.synced_at,
+                        last_error: Some(e),
+                    },
+                });
+
+                match receiver.recv_timeout(interval) {
+                    Ok(Command::Refresh) | Err(RecvTimeoutError::Timeout) => continue,
+                    Ok(Command::Stop) | Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+        });
+
+        SyncService {
+            snapshot,
+            commands,
+            thread: Some(thread),
+        }
+    }
+
+    /// The result of the most recent sync. It stays unchanged while held,
+    /// so it can be read without further locking.
+    pub fn snapshot(&self) -> Arc<SyncSnapshot> {
+        self.snapshot
+            .lock()
+            .map(|s| Arc::clone(&s))
+            .unwrap_or_default()
+    }
+
+    /// Syncs again as soon as the current sync, if any, has finished.
+    pub fn refresh(&self) {
+        let _ = self.commands.send(Command::Refresh);
+    }
+
+    /// Stops the service, waiting for a sync in progress to finish.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        let _ = self.commands.send(Command::Stop);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for SyncService {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn tickets(list: &[(i32, &str)]) -> BTreeMap<i32, Arc<TracTicket>> {
+        list.iter()
+            .map(|(id, status)| (*id, Arc::new(testing::ticket(*id, &[("status", *status)]))))
+            .collect()
+    }
+
+    fn delta(since: Option<SystemTime>, changed: &[(i32, &str)], removed: &[i32]) -> SyncDelta {
+        SyncDelta {
+            changed: changed
+                .iter()
+                .map(|(id, status)| testing::ticket(*id, &[("status", *status)]))
+                .collect(),
+            removed: removed.to_vec(),
+            since,
+            watermark: SystemTime::UNIX_EPOCH,
+        }
+    }
+
+    fn statuses(tickets: &BTreeMap<i32, Arc<TracTicket>>) -> Vec<(i32, String)> {
+        tickets
+            .values()
+            .map(|t| (t.id, t.status.to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn first_sync_replaces_everything() {
+        let previous = tickets(&[(1, "new"), (2, "new")]);
+        let merged = sync_changes(&previous, delta(None, &[(3, "new")], &[]));
+        assert_eq!(statuses(&merged), [(3, "new".to_string())]);
+    }
+
+    #[test]
+    fn merges_changes_and_drops_tickets_that_stop_matching() {
+        let previous = tickets(&[(1, "new"), (2, "new"), (3, "assigned")]);
+        // #1 changed and still matches, #2 was closed and no longer matches,
+        // #4 is new to the query and #3 was untouched.
+        let merged = sync_changes(
+            &previous,
+            delta(
+                Some(SystemTime::UNIX_EPOCH),
+                &[(1, "accepted"), (4, "new")],
+                &[2],
+            ),
+        );
+        assert_eq!(
+            statuses(&merged),
+            [
+                (1, "accepted".to_string()),
+                (3, "assigned".to_string()),
+                (4, "new".to_string()),
+            ]
+        );
+        assert!(Arc::ptr_eq(&merged[&3], &previous[&3]));
+    }
+
+    #[test]
+    fn reports_connection_failures() {
+        let service = SyncService::start(
+            || Err(TracError::Config("no server".to_string())),
+            TicketQuery::new(),
+            Duration::from_secs(60),
+        );
+        let deadline = SystemTime::now() + Duration::from_secs(5);
+        while service.snapshot().last_error.is_none() && SystemTime::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let snapshot = service.snapshot();
+        assert_eq!(
+            snapshot.last_error,
+            Some(TracError::Config("no server".to_string()))
+        );
+        assert!(snapshot.synced_at.is_none());
+        service.stop();
+    }
+}
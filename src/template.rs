@@ -0,0 +1,126 @@
+use std::collections::BTreeMap;
+
+use crate::{TicketCreateBuilder, TracError};
+
+/// Expands `{name}` placeholders in `text` using `lookup`. `{{` and `}}`
+/// produce literal braces. Returns the names of any placeholders `lookup`
+/// could not resolve.
+pub(crate) fn substitute<F>(text: &str, lookup: F) -> Result<String, Vec<String>>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut out = String::with_capacity(text.len());
+    let mut missing: Vec<String> = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                out.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                out.push('}');
+            }
+            '{' => {
+                let mut name = String::new();
+                let mut closed = false;
+                for n in chars.by_ref() {
+                    if n == '}' {
+                        closed = true;
+                        break;
+                    }
+                    name.push(n);
+                }
+                if !closed {
+                    out.push('{');
+                    out.push_str(&name);
+                    continue;
+                }
+                match lookup(name.trim()) {
+                    Some(value) => out.push_str(&value),
+                    None => missing.push(name.trim().to_string()),
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+
+    if missing.is_empty() {
+        Ok(out)
+    } else {
+        Err(missing)
+    }
+}
+
+/// A named skeleton for tickets that get filed repeatedly. The summary,
+/// description and field values may contain `{name}` placeholders which are
+/// filled in from the variables given when the ticket is created.
+#[derive(Debug, Clone)]
+pub struct TicketTemplate {
+    pub name: String,
+    pub summary: String,
+    pub description: String,
+    pub fields: BTreeMap<String, String>,
+}
+
+impl TicketTemplate {
+    pub fn new(name: &str, summary: &str, description: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            summary: summary.to_string(),
+            description: description.to_string(),
+            fields: BTreeMap::new(),
+        }
+    }
+
+    pub fn field(mut self, name: &str, value: &str) -> Self {
+        self.fields.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Builds a template from a `[template:<name>]` config section, where
+    /// `summary` and `description` are special and every other key is a
+    /// ticket field.
+    pub(crate) fn from_section(name: &str, section: &BTreeMap<String, String>) -> Self {
+        let mut template = Self::new(name, "", "");
+        for (key, value) in section {
+            match key.as_str() {
+                "summary" => template.summary = value.to_owned(),
+                "description" => template.description = value.to_owned(),
+                _ => {
+                    template.fields.insert(key.to_owned(), value.to_owned());
+                }
+            }
+        }
+        template
+    }
+
+    /// Substitutes `vars` into the template and returns a builder for the
+    /// resulting ticket, which can be further adjusted before submitting.
+    pub fn render(&self, vars: &[(&str, &str)]) -> Result<TicketCreateBuilder, TracError> {
+        let lookup = |name: &str| {
+            vars.iter()
+                .find(|(k, _)| *k == name)
+                .map(|(_, v)| v.to_string())
+        };
+        let expand = |text: &str| {
+            substitute(text, lookup).map_err(|missing| {
+                TracError::Config(format!(
+                    "template '{}' is missing variables: {}",
+                    self.name,
+                    missing.join(", ")
+                ))
+            })
+        };
+
+        let mut builder = TicketCreateBuilder::new(&expand(&self.summary)?)
+            .description(&expand(&self.description)?);
+        for (field, value) in &self.fields {
+            builder = builder.set(field, &expand(value)?);
+        }
+
+        Ok(builder)
+    }
+}
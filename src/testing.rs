@@ -0,0 +1,36 @@
+// Helpers shared by the unit tests.
+
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use xmlrpc::Value;
+
+use crate::{Trac, TracConfig, TracTicket, TracUser};
+
+pub(crate) fn config() -> TracConfig {
+    let user = Rc::new(TracUser {
+        username: "alice".to_string(),
+        password: "secret".to_string(),
+    });
+    TracConfig::new(user, "127.0.0.1:1", "/trac/")
+}
+
+/// A client for a server that refuses connections, for code that only
+/// needs one to read its config, or to see calls fail.
+pub(crate) fn offline(config: TracConfig) -> Trac {
+    Trac::new(Rc::new(config))
+}
+
+/// A ticket as `ticket.get` would return it.
+pub(crate) fn ticket(id: i32, fields: &[(&str, &str)]) -> TracTicket {
+    let fields: BTreeMap<String, Value> = fields
+        .iter()
+        .map(|(name, value)| (name.to_string(), Value::from(*value)))
+        .collect();
+    TracTicket::from_value(&Value::Array(vec![
+        Value::Int(id),
+        Value::Int(0),
+        Value::Int(0),
+        Value::Struct(fields),
+    ]))
+}
@@ -0,0 +1,273 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use iso8601::{Date, DateTime, Time};
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+// Howard Hinnant's days-from-civil algorithm, on the proleptic Gregorian
+// calendar with day 0 at 1970-01-01.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let mp = (i64::from(month) + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn date_to_days(date: &Date) -> i64 {
+    match *date {
+        Date::YMD { year, month, day } => days_from_civil(i64::from(year), month, day),
+        Date::Ordinal { year, ddd } => days_from_civil(i64::from(year), 1, 1) + i64::from(ddd) - 1,
+        Date::Week { year, ww, d } => {
+            // ISO week 1 is the week containing January 4th.
+            let jan4 = days_from_civil(i64::from(year), 1, 4);
+            let weekday = (jan4 + 3).rem_euclid(7);
+            jan4 - weekday + (i64::from(ww) - 1) * 7 + i64::from(d) - 1
+        }
+    }
+}
+
+pub(crate) fn from_unix(seconds: i64) -> SystemTime {
+    if seconds >= 0 {
+        UNIX_EPOCH + Duration::from_secs(seconds as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_secs(seconds.unsigned_abs())
+    }
+}
+
+pub(crate) fn to_unix(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    }
+}
+
+pub(crate) fn from_datetime(dt: &DateTime) -> SystemTime {
+    let t = &dt.time;
+    let offset = i64::from(t.tz_offset_hours) * 3600 + i64::from(t.tz_offset_minutes) * 60;
+    let seconds = date_to_days(&dt.date) * SECONDS_PER_DAY
+        + i64::from(t.hour) * 3600
+        + i64::from(t.minute) * 60
+        + i64::from(t.second)
+        - offset;
+    from_unix(seconds) + Duration::from_millis(u64::from(t.millisecond))
+}
+
+pub(crate) fn to_datetime(time: SystemTime) -> DateTime {
+    let seconds = to_unix(time);
+    let (year, month, day) = civil_from_days(seconds.div_euclid(SECONDS_PER_DAY));
+    let of_day = seconds.rem_euclid(SECONDS_PER_DAY) as u32;
+    DateTime {
+        date: Date::YMD {
+            year: year as i32,
+            month,
+            day,
+        },
+        time: Time {
+            hour: of_day / 3600,
+            minute: of_day % 3600 / 60,
+            second: of_day % 60,
+            millisecond: 0,
+            tz_offset_hours: 0,
+            tz_offset_minutes: 0,
+        },
+    }
+}
+
+/// Formats the UTC calendar date of `time` as `YYYY-MM-DD`.
+pub(crate) fn format_date(time: SystemTime) -> String {
+    let (year, month, day) = civil_from_days(to_unix(time).div_euclid(SECONDS_PER_DAY));
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Formats `time` in UTC as an RFC 3339 timestamp.
+pub(crate) fn format_rfc3339(time: SystemTime) -> String {
+    let seconds = to_unix(time);
+    let of_day = seconds.rem_euclid(SECONDS_PER_DAY);
+    format!(
+        "{}T{:02}:{:02}:{:02}Z",
+        format_date(time),
+        of_day / 3600,
+        of_day % 3600 / 60,
+        of_day % 60
+    )
+}
+
+/// Parses a `YYYY-MM-DD` date as midnight UTC.
+pub(crate) fn parse_date(text: &str) -> Option<SystemTime> {
+    let mut parts = text.trim().splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: u32 = parts.next()?.parse().ok()?;
+    let day: u32 = parts.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    Some(from_unix(
+        days_from_civil(year, month, day) * SECONDS_PER_DAY,
+    ))
+}
+
+/// Parses a duration written as numbers with units, such as `90s`, `4h` or
+/// `1d12h`. The units are `s`, `m`, `h`, `d` and `w`; a bare number is in
+/// seconds.
+pub(crate) fn parse_duration(text: &str) -> Option<Duration> {
+    let text = text.trim();
+    if let Ok(secs) = text.parse() {
+        return Some(Duration::from_secs(secs));
+    }
+    let mut total = 0u64;
+    let mut number = String::new();
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => SECONDS_PER_DAY as u64,
+            'w' => 7 * SECONDS_PER_DAY as u64,
+            _ if c.is_whitespace() => continue,
+            _ => return None,
+        };
+        total = total.checked_add(number.parse::<u64>().ok()?.checked_mul(unit)?)?;
+        number.clear();
+    }
+    if number.is_empty() && !text.is_empty() {
+        Some(Duration::from_secs(total))
+    } else {
+        None
+    }
+}
+
+/// Writes a duration in hours, minutes and seconds, e.g. `1h30m` or `20m`,
+/// which `parse_duration` reads back. Fractions of a second are dropped.
+pub(crate) fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let mut text = String::new();
+    for (count, unit) in [(secs / 3600, 'h'), (secs / 60 % 60, 'm'), (secs % 60, 's')] {
+        if count > 0 {
+            text.push_str(&format!("{}{}", count, unit));
+        }
+    }
+    if text.is_empty() {
+        text.push_str("0s");
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn civil_dates_round_trip() {
+        for days in [-719_468, -1, 0, 1, 59, 60, 10_957, 11_016, 2_932_896] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+        assert_eq!(days_from_civil(1969, 12, 31), -1);
+    }
+
+    #[test]
+    fn converts_week_and_ordinal_dates() {
+        // 2021-W01-1 is Monday 2021-01-04; 2020-W53-7 is 2021-01-03.
+        let week = date_to_days(&Date::Week {
+            year: 2021,
+            ww: 1,
+            d: 1,
+        });
+        assert_eq!(week, days_from_civil(2021, 1, 4));
+        let last = date_to_days(&Date::Week {
+            year: 2020,
+            ww: 53,
+            d: 7,
+        });
+        assert_eq!(last, days_from_civil(2021, 1, 3));
+        let ordinal = date_to_days(&Date::Ordinal {
+            year: 2020,
+            ddd: 60,
+        });
+        assert_eq!(ordinal, days_from_civil(2020, 2, 29));
+    }
+
+    #[test]
+    fn formats_times() {
+        let time = from_unix(951_782_400 + 3_723);
+        assert_eq!(format_date(time), "2000-02-29");
+        assert_eq!(format_rfc3339(time), "2000-02-29T01:02:03Z");
+        assert_eq!(format_rfc3339(from_unix(-1)), "1969-12-31T23:59:59Z");
+        assert_eq!(to_unix(from_unix(-1)), -1);
+    }
+
+    #[test]
+    fn datetimes_round_trip() {
+        let time = from_unix(1_700_000_000);
+        assert_eq!(from_datetime(&to_datetime(time)), time);
+    }
+
+    #[test]
+    fn parses_dates() {
+        assert_eq!(parse_date("2000-02-29"), Some(from_unix(951_782_400)));
+        assert_eq!(parse_date(" 1970-01-01 "), Some(UNIX_EPOCH));
+        assert_eq!(parse_date("2000-13-01"), None);
+        assert_eq!(parse_date("2000-02"), None);
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("90"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("4h"), Some(Duration::from_secs(4 * 3600)));
+        assert_eq!(
+            parse_duration("1d 12h"),
+            Some(Duration::from_secs(36 * 3600))
+        );
+        assert_eq!(parse_duration("1w"), Some(Duration::from_secs(604_800)));
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("4"), Some(Duration::from_secs(4)));
+        assert_eq!(parse_duration("4x"), None);
+        assert_eq!(parse_duration("h"), None);
+        assert_eq!(parse_duration("1h30"), None);
+    }
+
+    #[test]
+    fn formats_durations() {
+        for (secs, text) in [
+            (0, "0s"),
+            (45, "45s"),
+            (20 * 60, "20m"),
+            (90 * 60, "1h30m"),
+            (3 * 3600 + 5, "3h5s"),
+            (100 * 3600, "100h"),
+        ] {
+            let duration = Duration::from_secs(secs);
+            assert_eq!(format_duration(duration), text);
+            assert_eq!(parse_duration(text), Some(duration));
+        }
+    }
+
+    #[test]
+    fn rejects_overflowing_durations() {
+        assert_eq!(parse_duration("99999999999999999w"), None);
+        assert_eq!(parse_duration("18446744073709551615s1s"), None);
+        assert!(parse_duration("99999999999w").is_some());
+    }
+}
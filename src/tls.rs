@@ -0,0 +1,89 @@
+use reqwest::blocking::ClientBuilder;
+
+use crate::TracError;
+
+#[cfg(not(any(feature = "native-tls", feature = "rustls-tls")))]
+compile_error!("enable the `native-tls` or `rustls-tls` feature to select a TLS backend");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TlsVersion {
+    Tls1_0,
+    Tls1_1,
+    Tls1_2,
+    Tls1_3,
+}
+
+impl TlsVersion {
+    pub(crate) fn from_name(name: &str) -> Result<Self, TracError> {
+        match name {
+            "1.0" => Ok(TlsVersion::Tls1_0),
+            "1.1" => Ok(TlsVersion::Tls1_1),
+            "1.2" => Ok(TlsVersion::Tls1_2),
+            "1.3" => Ok(TlsVersion::Tls1_3),
+            _ => Err(TracError::Config(format!("unknown TLS version '{}'", name))),
+        }
+    }
+}
+
+/// TLS settings for the connection to the server. The backend is chosen at
+/// build time: `rustls-tls` if that feature is enabled, which suits static
+/// builds, or otherwise the platform's TLS library through `native-tls`,
+/// the default. rustls only speaks TLS 1.2 and 1.3.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TlsOptions {
+    /// The oldest version accepted; `None` leaves it to the backend.
+    pub min_version: Option<TlsVersion>,
+    /// Protocols offered through ALPN, most preferred first, such as
+    /// `http/1.1`. HTTP/2 (`h2`) is only spoken over rustls.
+    pub alpn: Vec<String>,
+}
+
+#[cfg(feature = "rustls-tls")]
+pub(crate) fn configure(
+    builder: ClientBuilder,
+    options: &TlsOptions,
+) -> Result<ClientBuilder, String> {
+    use rustls::ProtocolVersion;
+
+    if *options == TlsOptions::default() {
+        return Ok(builder.use_rustls_tls());
+    }
+
+    let mut config = rustls::ClientConfig::new();
+    config
+        .root_store
+        .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+    config.alpn_protocols = options.alpn.iter().map(|p| p.as_bytes().to_vec()).collect();
+    if options.min_version == Some(TlsVersion::Tls1_3) {
+        config.versions.retain(|v| *v == ProtocolVersion::TLSv1_3);
+    }
+    Ok(builder.use_preconfigured_tls(config))
+}
+
+#[cfg(all(feature = "native-tls", not(feature = "rustls-tls")))]
+pub(crate) fn configure(
+    builder: ClientBuilder,
+    options: &TlsOptions,
+) -> Result<ClientBuilder, String> {
+    use native_tls_crate::{Protocol, TlsConnector};
+
+    if *options == TlsOptions::default() {
+        return Ok(builder.use_native_tls());
+    }
+
+    let mut tls = TlsConnector::builder();
+    if let Some(version) = options.min_version {
+        tls.min_protocol_version(Some(match version {
+            TlsVersion::Tls1_0 => Protocol::Tlsv10,
+            TlsVersion::Tls1_1 => Protocol::Tlsv11,
+            TlsVersion::Tls1_2 => Protocol::Tlsv12,
+            TlsVersion::Tls1_3 => Protocol::Tlsv13,
+        }));
+    }
+    if !options.alpn.is_empty() {
+        let protocols: Vec<&str> = options.alpn.iter().map(|p| p.as_str()).collect();
+        tls.request_alpns(&protocols);
+    }
+    let connector = tls.build().map_err(|e| e.to_string())?;
+    Ok(builder.use_preconfigured_tls(connector))
+}
@@ -0,0 +1,246 @@
+use std::cell::Cell;
+use std::error::Error;
+use std::time::Duration;
+
+use reqwest::blocking::{RequestBuilder, Response};
+use reqwest::StatusCode;
+use xmlrpc::http::{build_headers, check_response};
+use xmlrpc::{Request, Transport};
+
+use crate::{Trac, TracError};
+
+/// Retries calls that failed with a `TracError::is_retryable` error,
+/// waiting `backoff` and then twice as long each time. A failed call may
+/// still have reached the server, so only read-only calls are retried
+/// unless `CallOptions::retry_writes` is set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    pub retries: u32,
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    pub fn new(retries: u32, backoff: Duration) -> Self {
+        Self { retries, backoff }
+    }
+
+    // The wait before retry number `attempt`, counting from 1.
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+    }
+}
+
+/// How urgently calls are made. Calls are sent one at a time, so this
+/// does not reorder them; instead `Background` work, such as a nightly
+/// sync, paces itself to leave the server to interactive users.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CallPriority {
+    #[default]
+    Normal,
+    /// After each call, waits as long as the call took, so the work takes
+    /// at most half of the server's time.
+    Background,
+}
+
+impl CallPriority {
+    pub(crate) fn from_name(name: &str) -> Result<Self, TracError> {
+        match name {
+            "normal" => Ok(CallPriority::Normal),
+            "background" => Ok(CallPriority::Background),
+            _ => Err(TracError::Config(format!(
+                "unknown call priority '{}'",
+                name
+            ))),
+        }
+    }
+}
+
+/// How calls are made: the defaults are `TracConfig::call`, and
+/// `Trac::with_call_options` overrides them for particular operations.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CallOptions {
+    /// `None` waits as long as the connection stays open.
+    pub timeout: Option<Duration>,
+    /// `None` makes every call once.
+    pub retry: Option<RetryPolicy>,
+    /// Also retry calls that change something, such as `ticket.update`.
+    /// One that timed out may already have been applied, and would then be
+    /// applied twice.
+    pub retry_writes: bool,
+    pub priority: CallPriority,
+}
+
+impl CallOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    pub fn retry_writes(mut self, retry_writes: bool) -> Self {
+        self.retry_writes = retry_writes;
+        self
+    }
+
+    pub fn priority(mut self, priority: CallPriority) -> Self {
+        self.priority = priority;
+        self
+    }
+}
+
+// Trac's read-only RPC methods that are not named get*, list* or query*.
+const READ_ONLY_METHODS: &[&str] = &["changeLog", "performSearch", "wikiToHtml"];
+
+/// Whether calling `method` leaves the server unchanged, so that it is
+/// safe to repeat after a failure.
+pub(crate) fn is_read_only(method: &str) -> bool {
+    if method == "system.multicall" {
+        return false;
+    }
+    if method.starts_with("system.") {
+        return true;
+    }
+    let name = method.rsplit('.').next().unwrap_or(method);
+    ["get", "list", "query"].iter().any(|p| name.starts_with(p))
+        || READ_ONLY_METHODS.contains(&name)
+}
+
+// `Request` keeps its method name private; its multicall form, deprecated
+// only in favour of `Request::new_multicall`, holds it.
+#[allow(deprecated)]
+pub(crate) fn method_name(request: &Request) -> String {
+    request
+        .clone()
+        .into_multicall_struct()
+        .as_struct()
+        .and_then(|s| s.get("methodName"))
+        .and_then(|name| name.as_str())
+        .unwrap_or_default()
+        .to_string()
+}
+
+impl Trac {
+    /// The options calls are currently made with.
+    pub fn call_options(&self) -> CallOptions {
+        match &*self.call_options.borrow() {
+            Some(options) => options.clone(),
+            None => self.config.call.clone(),
+        }
+    }
+
+    /// Runs `f` with every call it makes using `options` instead of the
+    /// configured defaults, e.g. a long timeout for an upload:
+    ///
+    /// ```ignore
+    /// trac.with_call_options(CallOptions::new().timeout(Duration::from_secs(600)), |trac| {
+    ///     ticket.put_attachment("build.log", "", &data, false, trac)
+    /// })
+    /// ```
+    pub fn with_call_options<T, F>(&self, options: CallOptions, f: F) -> T
+    where
+        F: FnOnce(&Trac) -> T,
+    {
+        let previous = self.call_options.replace(Some(options));
+        let result = f(self);
+        *self.call_options.borrow_mut() = previous;
+        result
+    }
+}
+
+/// The stock reqwest transport, except that it records the HTTP status of
+/// the response, which xmlrpc otherwise only reports as error text.
+pub(crate) struct StatusTransport<'a> {
+    builder: RequestBuilder,
+    status: &'a Cell<Option<StatusCode>>,
+}
+
+impl<'a> StatusTransport<'a> {
+    pub(crate) fn new(builder: RequestBuilder, status: &'a Cell<Option<StatusCode>>) -> Self {
+        status.set(None);
+        Self { builder, status }
+    }
+}
+
+impl Transport for StatusTransport<'_> {
+    type Stream = Response;
+
+    fn transmit(self, request: &Request<'_>) -> Result<Response, Box<dyn Error + Send + Sync>> {
+        let mut body = Vec::new();
+        request.write_as_xml(&mut body)?;
+
+        let response = build_headers(self.builder, body.len() as u64)
+            .body(body)
+            .send()?;
+        self.status.set(Some(response.status()));
+        check_response(&response)?;
+
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_are_read_only() {
+        for method in [
+            "ticket.get",
+            "ticket.query",
+            "ticket.changeLog",
+            "ticket.listAttachments",
+            "ticket.milestone.getAll",
+            "search.performSearch",
+            "system.listMethods",
+        ] {
+            assert!(is_read_only(method), "{}", method);
+        }
+    }
+
+    #[test]
+    fn writes_are_not_read_only() {
+        for method in [
+            "ticket.update",
+            "ticket.create",
+            "ticket.putAttachment",
+            "wiki.putPage",
+            "wiki.deletePage",
+            "system.multicall",
+        ] {
+            assert!(!is_read_only(method), "{}", method);
+        }
+    }
+
+    #[test]
+    fn method_name_of_request() {
+        assert_eq!(
+            method_name(&Request::new("ticket.get").arg(1)),
+            "ticket.get"
+        );
+    }
+
+    #[test]
+    fn retry_delay_doubles() {
+        let policy = RetryPolicy::new(3, Duration::from_secs(1));
+        assert_eq!(policy.delay(1), Duration::from_secs(1));
+        assert_eq!(policy.delay(3), Duration::from_secs(4));
+    }
+
+    #[test]
+    fn priority_names() {
+        assert_eq!(
+            CallPriority::from_name("background"),
+            Ok(CallPriority::Background)
+        );
+        assert!(CallPriority::from_name("urgent").is_err());
+    }
+}
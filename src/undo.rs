@@ -0,0 +1,64 @@
+use crate::{Trac, TracChange, TracError, TracTicket};
+
+// Set by the workflow rather than directly, or not real fields at all.
+const NOT_RESTORABLE: &[&str] = &["comment", "status", "resolution"];
+
+impl TracTicket {
+    /// Reverts the most recent change: every field it modified gets its
+    /// previous value back. A ticket the change closed is reopened if the
+    /// workflow offers the profile's `reopen` action; other status changes
+    /// are left alone, as the workflow has no general way back.
+    pub fn undo_last_change(
+        &self,
+        comment: Option<String>,
+        trac: &Trac,
+    ) -> Result<TracTicket, TracError> {
+        let changes = self.changelog(trac)?;
+        let last = match changes.last() {
+            Some(change) => change,
+            None => {
+                return Err(TracError::Unsupported(format!(
+                    "ticket #{} has no changes to undo",
+                    self.id
+                )))
+            }
+        };
+        let group: Vec<&TracChange> = changes
+            .iter()
+            .filter(|c| c.time == last.time && c.author == last.author)
+            .collect();
+
+        let mut update = self.update_builder();
+        let mut reverted = false;
+        for change in &group {
+            if NOT_RESTORABLE.contains(&change.field.as_str()) || change.field.starts_with('_') {
+                continue;
+            }
+            update = update.set(&change.field, &change.old_value);
+            reverted = true;
+        }
+
+        let closed_by_change = group
+            .iter()
+            .any(|c| c.field == "status" && trac.status(&c.new_value).is_closed());
+        if closed_by_change && self.is_closed(trac) {
+            if let Some(reopen) = &trac.config.workflow.reopen {
+                if self.actions(trac).iter().any(|a| &a.name == reopen) {
+                    update = update.action(reopen);
+                    reverted = true;
+                }
+            }
+        }
+
+        if !reverted {
+            return Err(TracError::Unsupported(format!(
+                "the last change to ticket #{} cannot be undone",
+                self.id
+            )));
+        }
+
+        let comment =
+            comment.unwrap_or_else(|| format!("Undid the last change by {}", last.author));
+        update.comment(&comment).submit(trac)
+    }
+}
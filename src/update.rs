@@ -0,0 +1,144 @@
+use crate::{DuplicateGuard, FieldValue, Trac, TracAction, TracError, TracTicket};
+
+/// Extra settings for `ticket.update`. By default, as on the server, no
+/// notification email is sent and the change is recorded as the user
+/// logged in.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UpdateOptions {
+    pub notify: bool,
+    /// Who the change is recorded as. The server ignores this unless the
+    /// user logged in has `TICKET_ADMIN`.
+    pub author: Option<String>,
+}
+
+impl UpdateOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sends the usual notification emails for the change.
+    pub fn notify(mut self, enabled: bool) -> Self {
+        self.notify = enabled;
+        self
+    }
+
+    pub fn as_author(mut self, author: &str) -> Self {
+        self.author = Some(author.to_string());
+        self
+    }
+}
+
+/// Collects attribute changes, an optional workflow action and a comment,
+/// and submits them to the server as a single `ticket.update`.
+#[derive(Debug)]
+pub struct TicketUpdateBuilder<'a> {
+    ticket: &'a TracTicket,
+    attributes: Vec<(String, FieldValue)>,
+    action: Option<TracAction>,
+    comment: Option<String>,
+    guard: Option<DuplicateGuard>,
+    create_missing: Option<bool>,
+    options: UpdateOptions,
+}
+
+impl<'a> TicketUpdateBuilder<'a> {
+    pub(crate) fn new(ticket: &'a TracTicket) -> Self {
+        Self {
+            ticket,
+            attributes: vec![],
+            action: None,
+            comment: None,
+            guard: None,
+            create_missing: None,
+            options: UpdateOptions::new(),
+        }
+    }
+
+    pub fn set(self, field: &str, value: &str) -> Self {
+        self.set_value(field, value)
+    }
+
+    /// Sets a field to a typed value, such as `true` for a checkbox.
+    pub fn set_value<V: Into<FieldValue>>(mut self, field: &str, value: V) -> Self {
+        self.attributes.retain(|(k, _)| k != field);
+        self.attributes.push((field.to_string(), value.into()));
+        self
+    }
+
+    pub fn action(self, name: &str) -> Self {
+        self.with_action(TracAction::new(name))
+    }
+
+    pub fn with_action(mut self, action: TracAction) -> Self {
+        self.action = Some(action);
+        self
+    }
+
+    /// Sets an input value for the action, e.g. the resolution for `resolve`.
+    /// Has no effect until an action has been chosen.
+    pub fn action_input(mut self, name: &str, value: &str) -> Self {
+        self.action = self.action.map(|a| a.with_input(name, value));
+        self
+    }
+
+    pub fn comment(mut self, text: &str) -> Self {
+        self.comment = Some(text.to_string());
+        self
+    }
+
+    /// Guards the comment against being posted twice. If the server already
+    /// has it, `submit` sends nothing and returns the ticket as it stands.
+    pub fn dedupe(mut self, guard: DuplicateGuard) -> Self {
+        self.guard = Some(guard);
+        self
+    }
+
+    /// Sends notification emails for the change; see `UpdateOptions`.
+    pub fn notify(mut self, enabled: bool) -> Self {
+        self.options.notify = enabled;
+        self
+    }
+
+    /// Records the change as made by `author`; see `UpdateOptions`.
+    pub fn as_author(mut self, author: &str) -> Self {
+        self.options.author = Some(author.to_string());
+        self
+    }
+
+    /// Overrides `TracConfig::auto_create` for this update.
+    pub fn create_missing(mut self, enabled: bool) -> Self {
+        self.create_missing = Some(enabled);
+        self
+    }
+
+    pub fn submit(mut self, trac: &Trac) -> Result<TracTicket, TracError> {
+        if let (Some(guard), Some(comment)) = (&self.guard, &self.comment) {
+            let author = match &self.options.author {
+                Some(author) => author,
+                None => &trac.config.user.username,
+            };
+            if self
+                .ticket
+                .duplicate_comment_by(comment, guard, author, trac)?
+            {
+                return trac.get_ticket(self.ticket.id);
+            }
+            self.comment = Some(guard.decorate(comment));
+        }
+
+        if self.create_missing.unwrap_or(trac.config.auto_create) {
+            trac.ensure_all_exist(self.attributes.iter().filter_map(|(k, v)| match v {
+                FieldValue::String(s) => Some((k, s)),
+                _ => None,
+            }))?;
+        }
+
+        self.ticket.update_with(
+            self.attributes,
+            self.action,
+            self.comment,
+            &self.options,
+            trac,
+        )
+    }
+}
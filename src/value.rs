@@ -0,0 +1,163 @@
+use std::fmt;
+use std::time::SystemTime;
+
+use xmlrpc::Value;
+
+use crate::{time, TracTicketField, TracTicketFieldType};
+
+/// A value to set on a ticket field. Trac stores every field as text, so
+/// the value is sent in the form Trac itself writes: checkboxes as `1` or
+/// `0`, numbers in decimal. Times are sent as XML-RPC dates for Trac's
+/// `time` custom fields.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldValue {
+    String(String),
+    Int(i32),
+    Bool(bool),
+    Double(f64),
+    DateTime(SystemTime),
+}
+
+fn parse_bool(text: &str) -> Option<bool> {
+    match text.trim().to_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "" | "0" | "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+impl FieldValue {
+    /// Encodes the value for `field`. Values are sent as strings, since
+    /// Trac's `Ticket.__setitem__` strips them, and a checkbox value such
+    /// as `true` or `"yes"` becomes `"1"`. A time for a field the server
+    /// did not describe, which includes `time` custom fields, stays an
+    /// XML-RPC date.
+    pub(crate) fn encode(&self, field: Option<&TracTicketField>) -> Value {
+        let checkbox = |checked: bool| FieldValue::Bool(checked).to_string();
+        match (field.map(|f| &f.field_type), self) {
+            (Some(TracTicketFieldType::Boolean), FieldValue::String(s)) => {
+                Value::String(parse_bool(s).map_or_else(|| s.to_owned(), checkbox))
+            }
+            (Some(TracTicketFieldType::Boolean), FieldValue::Int(i)) => {
+                Value::String(checkbox(*i != 0))
+            }
+            (None, FieldValue::DateTime(t)) => Value::DateTime(time::to_datetime(*t)),
+            _ => Value::String(self.to_string()),
+        }
+    }
+}
+
+/// Formats the value as Trac stores it: checkboxes as `1` or `0`, times in
+/// RFC 3339.
+impl fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FieldValue::String(s) => write!(f, "{}", s),
+            FieldValue::Int(i) => write!(f, "{}", i),
+            FieldValue::Bool(b) => write!(f, "{}", if *b { "1" } else { "0" }),
+            FieldValue::Double(d) => write!(f, "{}", d),
+            FieldValue::DateTime(t) => write!(f, "{}", time::format_rfc3339(*t)),
+        }
+    }
+}
+
+impl From<&str> for FieldValue {
+    fn from(value: &str) -> Self {
+        FieldValue::String(value.to_string())
+    }
+}
+
+impl From<String> for FieldValue {
+    fn from(value: String) -> Self {
+        FieldValue::String(value)
+    }
+}
+
+impl From<i32> for FieldValue {
+    fn from(value: i32) -> Self {
+        FieldValue::Int(value)
+    }
+}
+
+impl From<bool> for FieldValue {
+    fn from(value: bool) -> Self {
+        FieldValue::Bool(value)
+    }
+}
+
+impl From<f64> for FieldValue {
+    fn from(value: f64) -> Self {
+        FieldValue::Double(value)
+    }
+}
+
+impl From<SystemTime> for FieldValue {
+    fn from(value: SystemTime) -> Self {
+        FieldValue::DateTime(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+
+    fn field(field_type: TracTicketFieldType) -> TracTicketField {
+        TracTicketField {
+            name: "f".to_string(),
+            label: "F".to_string(),
+            field_type,
+            default: None,
+            options: None,
+            order: None,
+        }
+    }
+
+    fn encode(value: impl Into<FieldValue>, field_type: TracTicketFieldType) -> Value {
+        value.into().encode(Some(&field(field_type)))
+    }
+
+    #[test]
+    fn checkboxes_are_sent_as_strings() {
+        assert_eq!(encode(true, TracTicketFieldType::Boolean), Value::from("1"));
+        assert_eq!(
+            encode(false, TracTicketFieldType::Boolean),
+            Value::from("0")
+        );
+        assert_eq!(
+            encode("yes", TracTicketFieldType::Boolean),
+            Value::from("1")
+        );
+        assert_eq!(encode("", TracTicketFieldType::Boolean), Value::from("0"));
+        assert_eq!(encode(2, TracTicketFieldType::Boolean), Value::from("1"));
+    }
+
+    #[test]
+    fn unrecognised_checkbox_text_is_kept() {
+        assert_eq!(
+            encode("maybe", TracTicketFieldType::Boolean),
+            Value::from("maybe")
+        );
+    }
+
+    #[test]
+    fn numbers_are_sent_in_decimal() {
+        assert_eq!(encode(3, TracTicketFieldType::String), Value::from("3"));
+        assert_eq!(encode(1.5, TracTicketFieldType::String), Value::from("1.5"));
+        assert_eq!(FieldValue::from(7).encode(None), Value::from("7"));
+    }
+
+    #[test]
+    fn times_are_dates_only_for_undescribed_fields() {
+        let t = UNIX_EPOCH + Duration::from_secs(86_400);
+        assert_eq!(
+            FieldValue::from(t).encode(None),
+            Value::DateTime(time::to_datetime(t))
+        );
+        assert_eq!(
+            encode(t, TracTicketFieldType::String),
+            Value::from("1970-01-02T00:00:00Z")
+        );
+    }
+}
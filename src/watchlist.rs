@@ -0,0 +1,146 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::comments::comments_from_changelog;
+use crate::{time, Trac, TracChange, TracComment, TracError, TracTicket};
+
+/// A watched ticket that changed, with the changes since the last check.
+#[derive(Debug)]
+pub struct WatchUpdate {
+    pub ticket: TracTicket,
+    pub changes: Vec<TracChange>,
+}
+
+impl WatchUpdate {
+    /// The fields that changed, other than comments, in order of first
+    /// change.
+    pub fn changed_fields(&self) -> Vec<&str> {
+        let mut fields = vec![];
+        for change in &self.changes {
+            let field = change.field.as_str();
+            if field != "comment" && !field.starts_with('_') && !fields.contains(&field) {
+                fields.push(field);
+            }
+        }
+        fields
+    }
+
+    pub fn comments(&self) -> Vec<TracComment> {
+        comments_from_changelog(&self.changes)
+    }
+}
+
+/// A personal set of watched tickets, each with the time it was last seen
+/// changed, kept in a file or only in memory.
+///
+/// The file has one `<id> <unix seconds>` line per ticket. A ticket is
+/// first seen when it is watched, by the local clock; after each check it
+/// is seen as of its last change on the server.
+#[derive(Debug, Clone, Default)]
+pub struct WatchList {
+    path: Option<PathBuf>,
+    seen: BTreeMap<i32, SystemTime>,
+}
+
+impl WatchList {
+    /// A watch list that is not saved anywhere.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads the watch list kept in `path`, which need not exist yet.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, TracError> {
+        let path = path.as_ref().to_path_buf();
+        let invalid = |line: &str| {
+            TracError::Io(format!(
+                "{}: invalid watch list entry '{}'",
+                path.display(),
+                line
+            ))
+        };
+
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(TracError::Io(format!("{}: {}", path.display(), e))),
+        };
+        let mut seen = BTreeMap::new();
+        for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let (id, seconds) = line.split_once(' ').ok_or_else(|| invalid(line))?;
+            let id = id.parse().map_err(|_| invalid(line))?;
+            let seconds = seconds.trim().parse().map_err(|_| invalid(line))?;
+            seen.insert(id, time::from_unix(seconds));
+        }
+
+        Ok(Self {
+            path: Some(path),
+            seen,
+        })
+    }
+
+    /// Writes the list back to its file, if it has one.
+    pub fn save(&self) -> Result<(), TracError> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let text: String = self
+            .seen
+            .iter()
+            .map(|(id, seen)| format!("{} {}\n", id, time::to_unix(*seen)))
+            .collect();
+        let mut temporary = path.clone().into_os_string();
+        temporary.push(".tmp");
+        fs::write(&temporary, text)
+            .and_then(|_| fs::rename(&temporary, path))
+            .map_err(|e| TracError::Io(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Starts watching `id`; changes made from now on are reported. Watching
+    /// a ticket already on the list changes nothing.
+    pub fn watch(&mut self, id: i32) {
+        self.seen.entry(id).or_insert_with(SystemTime::now);
+    }
+
+    pub fn unwatch(&mut self, id: i32) {
+        self.seen.remove(&id);
+    }
+
+    pub fn is_watched(&self, id: i32) -> bool {
+        self.seen.contains_key(&id)
+    }
+
+    pub fn ids(&self) -> Vec<i32> {
+        self.seen.keys().copied().collect()
+    }
+
+    /// Reports the watched tickets changed since they were last seen, and
+    /// marks them seen. The list is saved afterwards if it has a file.
+    /// Tickets that no longer exist are left on the list.
+    pub fn check(&mut self, trac: &Trac) -> Result<Vec<WatchUpdate>, TracError> {
+        let tickets: Vec<TracTicket> = trac
+            .get_tickets(&self.ids())?
+            .into_iter()
+            .filter(|t| self.seen.get(&t.id).is_some_and(|seen| t.changed > *seen))
+            .collect();
+        let changelogs = trac.changelogs(&tickets)?;
+
+        let mut updates = Vec::with_capacity(tickets.len());
+        for (ticket, changes) in tickets.into_iter().zip(changelogs) {
+            let seen = self.seen[&ticket.id];
+            self.seen.insert(ticket.id, ticket.changed);
+            updates.push(WatchUpdate {
+                changes: changes.into_iter().filter(|c| c.time > seen).collect(),
+                ticket,
+            });
+        }
+
+        if !updates.is_empty() {
+            self.save()?;
+        }
+        Ok(updates)
+    }
+}
@@ -0,0 +1,448 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, SystemTime};
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::json::Object;
+use crate::{
+    time, DeltaSync, MemoryWatermark, QueryCondition, TicketQuery, Trac, TracChange, TracError,
+    TracTicket,
+};
+
+const SIGNATURE_HEADER: &str = "X-Trac-Signature";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TicketEventKind {
+    Created,
+    Changed,
+}
+
+impl TicketEventKind {
+    fn name(self) -> &'static str {
+        match self {
+            TicketEventKind::Created => "created",
+            TicketEventKind::Changed => "changed",
+        }
+    }
+}
+
+/// A ticket that was created or changed, with the changes made to it.
+#[derive(Debug)]
+pub struct TicketEvent {
+    pub kind: TicketEventKind,
+    pub ticket: TracTicket,
+    pub changes: Vec<TracChange>,
+}
+
+impl TicketEvent {
+    /// The webhook payload: the event kind, the ticket as it is now, and
+    /// the changes.
+    pub fn to_json(&self, trac: &Trac) -> String {
+        let mut fields = Object::new();
+        for (name, value) in &self.ticket.fields {
+            fields = fields.str(name, value);
+        }
+        let ticket = Object::new()
+            .num("id", self.ticket.id)
+            .str("url", &TracTicket::url(self.ticket.id, trac))
+            .str("created", &time::format_rfc3339(self.ticket.created))
+            .str("changed", &time::format_rfc3339(self.ticket.changed))
+            .raw("fields", fields.build())
+            .build();
+        let changes: Vec<String> = self
+            .changes
+            .iter()
+            .map(|c| {
+                Object::new()
+                    .str("time", &time::format_rfc3339(c.time))
+                    .str("author", &c.author)
+                    .str("field", &c.field)
+                    .str("old", &c.old_value)
+                    .str("new", &c.new_value)
+                    .build()
+            })
+            .collect();
+        Object::new()
+            .str("event", self.kind.name())
+            .raw("ticket", ticket)
+            .raw("changes", format!("[{}]", changes.join(",")))
+            .build()
+    }
+}
+
+/// A destination for ticket events, from a `[webhook:<name>]` section with
+/// a `url` and optionally `events` (`created`, `changed`; both by
+/// default), `match` (a query string the ticket must match), `fields`
+/// (only changes to these fields count; `comment` for comments) and
+/// `secret`. With a secret, each request carries an `X-Trac-Signature:
+/// sha256=<hex>` header, the HMAC-SHA256 of the body.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Webhook {
+    pub name: String,
+    pub url: String,
+    pub events: Vec<TicketEventKind>,
+    pub conditions: Vec<QueryCondition>,
+    pub fields: Vec<String>,
+    pub secret: Option<String>,
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+impl Webhook {
+    pub(crate) fn from_section(
+        name: &str,
+        section: &BTreeMap<String, String>,
+    ) -> Result<Self, TracError> {
+        let url = section.get("url").cloned().ok_or_else(|| {
+            TracError::Config(format!("config is missing [webhook:{}] url", name))
+        })?;
+        let events = match section.get("events") {
+            Some(events) => split_list(events)
+                .iter()
+                .map(|e| match e.as_str() {
+                    "created" => Ok(TicketEventKind::Created),
+                    "changed" => Ok(TicketEventKind::Changed),
+                    _ => Err(TracError::Config(format!(
+                        "unknown event '{}' in [webhook:{}]",
+                        e, name
+                    ))),
+                })
+                .collect::<Result<_, _>>()?,
+            None => vec![TicketEventKind::Created, TicketEventKind::Changed],
+        };
+        let conditions = match section.get("match") {
+            Some(query) => TicketQuery::parse(query)?.conditions,
+            None => vec![],
+        };
+
+        Ok(Webhook {
+            name: name.to_string(),
+            url,
+            events,
+            conditions,
+            fields: section
+                .get("fields")
+                .map(|f| split_list(f))
+                .unwrap_or_default(),
+            secret: section.get("secret").cloned(),
+        })
+    }
+
+    pub fn wants(&self, event: &TicketEvent) -> bool {
+        self.events.contains(&event.kind)
+            && self.conditions.iter().all(|c| c.matches(&event.ticket))
+            && (self.fields.is_empty()
+                || event.kind == TicketEventKind::Created
+                || event.changes.iter().any(|c| self.fields.contains(&c.field)))
+    }
+
+    fn signature(&self, body: &str) -> Option<String> {
+        let secret = self.secret.as_ref()?;
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).ok()?;
+        mac.update(body.as_bytes());
+        let hex: String = mac
+            .finalize()
+            .into_bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        Some(format!("sha256={}", hex))
+    }
+}
+
+/// The outcome of sending one event to one webhook.
+#[derive(Debug)]
+pub struct WebhookDelivery {
+    pub webhook: String,
+    pub ticket: i32,
+    pub result: Result<(), TracError>,
+}
+
+impl Trac {
+    /// Returns the tickets created or changed since `since`, each with the
+    /// changes made after it.
+    pub fn ticket_events_since(&self, since: SystemTime) -> Result<Vec<TicketEvent>, TracError> {
+        let tickets = self.get_tickets(&self.tickets_changed_since(since)?)?;
+        self.ticket_events(tickets, since)
+    }
+
+    fn ticket_events(
+        &self,
+        tickets: Vec<TracTicket>,
+        since: SystemTime,
+    ) -> Result<Vec<TicketEvent>, TracError> {
+        let changelogs = self.changelogs(&tickets)?;
+        Ok(tickets
+            .into_iter()
+            .zip(changelogs)
+            .map(|(ticket, changes)| TicketEvent {
+                kind: if ticket.created > since {
+                    TicketEventKind::Created
+                } else {
+                    TicketEventKind::Changed
+                },
+                changes: changes.into_iter().filter(|c| c.time > since).collect(),
+                ticket,
+            })
+            .collect())
+    }
+
+    /// POSTs each event to every configured webhook that wants it. A failed
+    /// delivery is reported rather than stopping the others.
+    pub fn dispatch_webhooks(&self, events: &[TicketEvent]) -> Vec<WebhookDelivery> {
+        let mut deliveries = vec![];
+        for event in events {
+            let body = event.to_json(self);
+            for webhook in self.config.webhooks.iter().filter(|w| w.wants(event)) {
+                deliveries.push(WebhookDelivery {
+                    webhook: webhook.name.to_owned(),
+                    ticket: event.ticket.id,
+                    result: self.post_webhook(webhook, &body),
+                });
+            }
+        }
+        deliveries
+    }
+
+    fn post_webhook(&self, webhook: &Webhook, body: &str) -> Result<(), TracError> {
+        let mut request = self
+            .client
+            .post(&webhook.url)
+            .header("Content-Type", "application/json")
+            .body(body.to_string());
+        if let Some(signature) = webhook.signature(body) {
+            request = request.header(SIGNATURE_HEADER, signature);
+        }
+        let response = request
+            .send()
+            .map_err(|e| TracError::Transport(format!("webhook {}: {}", webhook.name, e)))?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(TracError::Http {
+                status: response.status().as_u16(),
+                message: format!("webhook {}", webhook.name),
+            })
+        }
+    }
+}
+
+// Where the next poll starts: the delta's watermark, unless a delivery
+// failed, in which case the oldest failed change is fetched again.
+fn next_watermark<I>(watermark: SystemTime, failed: I) -> SystemTime
+where
+    I: IntoIterator<Item = SystemTime>,
+{
+    failed
+        .into_iter()
+        .min()
+        .map_or(watermark, |f| f.min(watermark))
+}
+
+/// Polls for ticket events and sends them to the configured webhooks,
+/// picking up each time where the last poll left off.
+///
+/// Polls follow the server's change times through a `DeltaSync`, looking
+/// back `DEFAULT_OVERLAP` each time. Delivery is at least once: a failed
+/// delivery is tried again on the next poll, and while a delivery of the
+/// same ticket version to the same webhook is skipped, an event may repeat
+/// changes sent in an earlier one, and a new dispatcher resends the
+/// overlap. Receivers should key on the ticket id and its `changed` time.
+#[derive(Debug)]
+pub struct WebhookDispatcher {
+    sync: DeltaSync<MemoryWatermark>,
+    // Webhook, ticket and `changed` time of each successful delivery still
+    // inside the overlap.
+    delivered: BTreeSet<(String, i32, SystemTime)>,
+}
+
+impl WebhookDispatcher {
+    /// Starts from now; earlier changes are not sent.
+    pub fn new() -> Self {
+        Self::since(SystemTime::now())
+    }
+
+    pub fn since(since: SystemTime) -> Self {
+        Self {
+            sync: DeltaSync::new(MemoryWatermark::at(since)),
+            delivered: BTreeSet::new(),
+        }
+    }
+
+    /// How far before the last poll each poll looks again.
+    pub fn overlap(mut self, overlap: Duration) -> Self {
+        self.sync = self.sync.overlap(overlap);
+        self
+    }
+
+    /// Sends the events since the last poll. If fetching them fails, the
+    /// next poll tries the same period again.
+    pub fn poll(&mut self, trac: &Trac) -> Result<Vec<WebhookDelivery>, TracError> {
+        let mut delta = self.sync.fetch(trac, &TicketQuery::new())?;
+        let since = delta.since.unwrap_or(delta.watermark);
+        let from = since.checked_sub(self.sync.overlap).unwrap_or(since);
+        let events = trac.ticket_events(std::mem::take(&mut delta.changed), from)?;
+
+        let mut deliveries = vec![];
+        let mut failed = vec![];
+        for event in &events {
+            let body = event.to_json(trac);
+            for webhook in trac.config.webhooks.iter().filter(|w| w.wants(event)) {
+                let key = (
+                    webhook.name.to_owned(),
+                    event.ticket.id,
+                    event.ticket.changed,
+                );
+                if self.delivered.contains(&key) {
+                    continue;
+                }
+                let result = trac.post_webhook(webhook, &body);
+                match result {
+                    Ok(()) => {
+                        self.delivered.insert(key);
+                    }
+                    Err(_) => failed.push(event.ticket.changed),
+                }
+                deliveries.push(WebhookDelivery {
+                    webhook: webhook.name.to_owned(),
+                    ticket: event.ticket.id,
+                    result,
+                });
+            }
+        }
+
+        delta.watermark = next_watermark(delta.watermark, failed);
+        self.sync.commit(&delta)?;
+        let oldest = delta
+            .watermark
+            .checked_sub(self.sync.overlap)
+            .unwrap_or(delta.watermark);
+        self.delivered.retain(|(_, _, changed)| *changed >= oldest);
+        Ok(deliveries)
+    }
+}
+
+impl Default for WebhookDispatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use std::time::UNIX_EPOCH;
+
+    fn webhook(entries: &[(&str, &str)]) -> Webhook {
+        let section = entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        Webhook::from_section("ci", &section).unwrap()
+    }
+
+    fn event(kind: TicketEventKind, fields: &[(&str, &str)], changed: &[&str]) -> TicketEvent {
+        TicketEvent {
+            kind,
+            ticket: testing::ticket(7, fields),
+            changes: changed
+                .iter()
+                .map(|field| TracChange {
+                    time: UNIX_EPOCH + Duration::from_secs(60),
+                    author: "alice".to_string(),
+                    field: field.to_string(),
+                    old_value: "a".to_string(),
+                    new_value: "b".to_string(),
+                    permanent: true,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn signs_bodies_with_hmac_sha256() {
+        // RFC 4231, test case 2.
+        let signed = webhook(&[("url", "http://hooks"), ("secret", "Jefe")]);
+        assert_eq!(
+            signed.signature("what do ya want for nothing?").as_deref(),
+            Some("sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843")
+        );
+        assert_eq!(webhook(&[("url", "http://hooks")]).signature("body"), None);
+    }
+
+    #[test]
+    fn filters_by_event_match_and_fields() {
+        let hook = webhook(&[
+            ("url", "http://hooks"),
+            ("events", "changed"),
+            ("match", "component=ui"),
+            ("fields", "status, comment"),
+        ]);
+        let ui = [("component", "ui")];
+        assert!(hook.wants(&event(TicketEventKind::Changed, &ui, &["comment"])));
+        assert!(!hook.wants(&event(TicketEventKind::Changed, &ui, &["keywords"])));
+        assert!(!hook.wants(&event(TicketEventKind::Created, &ui, &[])));
+        assert!(!hook.wants(&event(
+            TicketEventKind::Changed,
+            &[("component", "core")],
+            &["status"]
+        )));
+
+        let all = webhook(&[("url", "http://hooks"), ("fields", "status")]);
+        assert!(all.wants(&event(TicketEventKind::Created, &[], &[])));
+    }
+
+    #[test]
+    fn rejects_unknown_events_and_missing_urls() {
+        let section = |entries: &[(&str, &str)]| -> BTreeMap<String, String> {
+            entries
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        assert!(Webhook::from_section("ci", &section(&[("events", "created")])).is_err());
+        assert!(Webhook::from_section(
+            "ci",
+            &section(&[("url", "http://hooks"), ("events", "deleted")])
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn writes_the_payload() {
+        let trac = testing::offline(testing::config());
+        let json = event(
+            TicketEventKind::Changed,
+            &[("status", "new"), ("summary", "Say \"hi\"")],
+            &["status"],
+        )
+        .to_json(&trac);
+        assert_eq!(
+            json,
+            concat!(
+                r#"{"event":"changed","ticket":{"id":7,"url":"https://127.0.0.1:1/trac/ticket/7","#,
+                r#""created":"1970-01-01T00:00:00Z","changed":"1970-01-01T00:00:00Z","#,
+                r#""fields":{"status":"new","summary":"Say \"hi\""}},"#,
+                r#""changes":[{"time":"1970-01-01T00:01:00Z","author":"alice","field":"status","#,
+                r#""old":"a","new":"b"}]}"#
+            )
+        );
+    }
+
+    #[test]
+    fn holds_the_watermark_at_the_oldest_failure() {
+        let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+        assert_eq!(next_watermark(at(100), vec![]), at(100));
+        assert_eq!(next_watermark(at(100), vec![at(80), at(60)]), at(60));
+        assert_eq!(next_watermark(at(100), vec![at(120)]), at(100));
+    }
+}
@@ -0,0 +1,338 @@
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+
+use xmlrpc::{Request, Value};
+
+use crate::server_search::percent_decode;
+use crate::{scalar_to_string, time, Trac, TracError};
+
+/// The latest version of a wiki page, from `wiki.getRecentChanges`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WikiChange {
+    pub name: String,
+    pub author: String,
+    pub version: i32,
+    pub modified: SystemTime,
+    pub comment: String,
+}
+
+impl WikiChange {
+    fn from_value(info: &Value) -> Option<Self> {
+        let text = |key: &str| info.get(key).and_then(scalar_to_string).unwrap_or_default();
+        Some(WikiChange {
+            name: info.get("name").and_then(scalar_to_string)?,
+            author: text("author"),
+            version: info.get("version").and_then(|v| v.as_i32()).unwrap_or(0),
+            modified: time::from_datetime(&info.get("lastModified")?.as_datetime()?),
+            comment: text("comment"),
+        })
+    }
+}
+
+// Decodes the character references Trac's templates produce.
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').and_then(|end| {
+            let c = match &rest[1..end] {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                "nbsp" => '\u{a0}',
+                entity => {
+                    let code = match entity.strip_prefix("#x").or(entity.strip_prefix("#X")) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                        None => entity.strip_prefix('#')?.parse().ok()?,
+                    };
+                    char::from_u32(code)?
+                }
+            };
+            Some((c, end + 1))
+        });
+        match decoded {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+// The text of an HTML fragment, without its tags.
+fn html_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    decode_entities(&text)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Reads the descriptions from an attachment list, where each attachment is
+// a `<dt>` linking to it, followed by a `<dd>` if it has a description.
+fn attachment_descriptions(html: &str) -> BTreeMap<String, String> {
+    let mut descriptions = BTreeMap::new();
+    let mut rest = html;
+    while let Some(start) = rest.find("<dt") {
+        rest = &rest[start..];
+        let end = match rest.find("</dt>") {
+            Some(end) => end,
+            None => break,
+        };
+        let term = &rest[..end];
+        rest = rest[end + "</dt>".len()..].trim_start();
+
+        let filename = term
+            .split("href=\"")
+            .skip(1)
+            .filter_map(|link| link.split('"').next())
+            .find(|href| href.contains("attachment/wiki/"))
+            .and_then(|href| href.split(['?', '#']).next()?.rsplit('/').next())
+            .map(|name| percent_decode(&decode_entities(name)));
+        let description = rest.strip_prefix("<dd").and_then(|dd| {
+            let open = dd.find('>')?;
+            let close = dd.find("</dd>")?;
+            (open < close).then(|| html_text(&dd[open + 1..close]))
+        });
+        if let (Some(filename), Some(description)) = (filename, description) {
+            if !filename.is_empty() && !description.is_empty() {
+                descriptions.insert(filename, description);
+            }
+        }
+    }
+    descriptions
+}
+
+impl Trac {
+    pub fn get_page(&self, name: &str) -> Result<String, TracError> {
+        match self.call(&Request::new("wiki.getPage").arg(name))? {
+            Value::String(content) => Ok(content),
+            _ => Err(TracError::invalid_response("wiki.getPage")),
+        }
+    }
+
+    pub fn page_exists(&self, name: &str) -> Result<bool, TracError> {
+        match self.call(&Request::new("wiki.getPageInfo").arg(name)) {
+            Ok(Value::Struct(_)) => Ok(true),
+            Ok(_) => Ok(false),
+            Err(e) if e.is_not_found() => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Lists the pages modified since `when`, one entry per page for its
+    /// latest version, oldest first.
+    pub fn wiki_changed_since(&self, when: SystemTime) -> Result<Vec<WikiChange>, TracError> {
+        let request =
+            Request::new("wiki.getRecentChanges").arg(Value::DateTime(time::to_datetime(when)));
+        match self.call(&request)? {
+            Value::Array(pages) => {
+                let mut changes: Vec<WikiChange> =
+                    pages.iter().filter_map(WikiChange::from_value).collect();
+                changes.sort_by_key(|c| c.modified);
+                Ok(changes)
+            }
+            _ => Err(TracError::invalid_response("wiki.getRecentChanges")),
+        }
+    }
+
+    pub fn put_page(&self, name: &str, content: &str, comment: &str) -> Result<(), TracError> {
+        let mut attributes = BTreeMap::new();
+        attributes.insert("comment".to_string(), Value::from(comment));
+        self.call(
+            &Request::new("wiki.putPage")
+                .arg(name)
+                .arg(content)
+                .arg(Value::Struct(attributes)),
+        )
+        .map(|_| ())
+    }
+
+    /// Deletes every version of the page, along with its attachments.
+    pub fn delete_page(&self, name: &str) -> Result<(), TracError> {
+        self.call(&Request::new("wiki.deletePage").arg(name))
+            .map(|_| ())
+    }
+
+    /// The file names of the page's attachments.
+    pub fn page_attachments(&self, name: &str) -> Result<Vec<String>, TracError> {
+        match self.call(&Request::new("wiki.listAttachments").arg(name))? {
+            // Each entry is a path of the form `<page>/<filename>`.
+            Value::Array(paths) => Ok(paths
+                .iter()
+                .filter_map(scalar_to_string)
+                .map(|p| match p.strip_prefix(&format!("{}/", name)) {
+                    Some(filename) => filename.to_string(),
+                    None => p,
+                })
+                .collect()),
+            _ => Err(TracError::invalid_response("wiki.listAttachments")),
+        }
+    }
+
+    /// The descriptions of the page's attachments, by file name, leaving
+    /// out those without one. The RPC API has no way to read them, so they
+    /// come from the page's attachment list in the web interface.
+    pub fn page_attachment_descriptions(
+        &self,
+        name: &str,
+    ) -> Result<BTreeMap<String, String>, TracError> {
+        let mut segments = vec!["attachment", "wiki"];
+        segments.extend(name.split('/'));
+        segments.push("");
+        let response = self.web_get(&self.web_url(&segments)?)?;
+        if !response.status().is_success() {
+            return Err(TracError::from_status(response.status()));
+        }
+        let html = response
+            .text()
+            .map_err(|e| TracError::Transport(e.to_string()))?;
+        Ok(attachment_descriptions(&html))
+    }
+
+    /// Moves a page, with its attachments and their descriptions, to
+    /// `new_name`. The RPC API has no rename, so the content is copied and
+    /// the original then deleted, or, with `redirect`, replaced by a link to
+    /// the new page. The new page starts with a fresh history.
+    ///
+    /// If any step fails, the copy at `new_name` is deleted again, leaving
+    /// the original as it was, and the error is returned. Should that delete
+    /// fail too, the error is [`TracError::Unsupported`] naming both failures
+    /// and the page that is left behind.
+    pub fn rename_page(
+        &self,
+        old_name: &str,
+        new_name: &str,
+        redirect: bool,
+    ) -> Result<(), TracError> {
+        if self.page_exists(new_name)? {
+            return Err(TracError::Unsupported(format!(
+                "wiki page '{}' already exists",
+                new_name
+            )));
+        }
+
+        let content = self.get_page(old_name)?;
+        let attachments = self.page_attachments(old_name)?;
+        let descriptions = if attachments.is_empty() {
+            BTreeMap::new()
+        } else {
+            self.page_attachment_descriptions(old_name)?
+        };
+
+        self.put_page(new_name, &content, &format!("Renamed from {}", old_name))?;
+        let moved = self
+            .copy_attachments(old_name, new_name, &attachments, &descriptions)
+            .and_then(|()| {
+                if redirect {
+                    self.put_page(
+                        old_name,
+                        &format!("This page has been renamed to [wiki:{}].", new_name),
+                        &format!("Renamed to {}", new_name),
+                    )
+                } else {
+                    self.delete_page(old_name)
+                }
+            });
+        moved.map_err(|e| match self.delete_page(new_name) {
+            Ok(()) => e,
+            Err(cleanup) => TracError::Unsupported(format!(
+                "renaming '{}' failed ({}), and so did removing the partial copy \
+                 ({}); wiki page '{}' must be deleted by hand",
+                old_name, e, cleanup, new_name
+            )),
+        })
+    }
+
+    fn copy_attachments(
+        &self,
+        old_name: &str,
+        new_name: &str,
+        filenames: &[String],
+        descriptions: &BTreeMap<String, String>,
+    ) -> Result<(), TracError> {
+        for filename in filenames {
+            let path = format!("{}/{}", old_name, filename);
+            let data = match self.call(&Request::new("wiki.getAttachment").arg(path))? {
+                Value::Base64(data) => data,
+                _ => return Err(TracError::invalid_response("wiki.getAttachment")),
+            };
+            let description = descriptions.get(filename).map_or("", String::as_str);
+            self.call(
+                &Request::new("wiki.putAttachmentEx")
+                    .arg(new_name)
+                    .arg(filename.as_str())
+                    .arg(description)
+                    .arg(Value::Base64(data))
+                    .arg(true),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    const LIST: &str = r#"
+<dl class="attachments">
+  <dt><a href="/trac/attachment/wiki/Dev/Setup/notes%20v2.txt" title="View attachment">notes v2.txt</a><a class="trac-rawlink" href="/trac/raw-attachment/wiki/Dev/Setup/notes%20v2.txt" title="Download">&#8203;</a> (<span title="12 bytes">12 bytes</span>) - added by <em>alice</em>.</dt>
+  <dd>Build <strong>notes</strong> &amp; tips</dd>
+  <dt><a href="/trac/attachment/wiki/Dev/Setup/logo.png" title="View attachment">logo.png</a> - added by <em>bob</em>.</dt>
+  <dt><a href="/trac/attachment/wiki/Dev/Setup/a&amp;b.diff">a&amp;b.diff</a></dt>
+  <dd>
+    Fix for &lt;#12&gt;
+  </dd>
+</dl>"#;
+
+    #[test]
+    fn reads_attachment_descriptions() {
+        let descriptions = attachment_descriptions(LIST);
+        assert_eq!(
+            descriptions.into_iter().collect::<Vec<_>>(),
+            [
+                ("a&b.diff".to_string(), "Fix for <#12>".to_string()),
+                ("notes v2.txt".to_string(), "Build notes & tips".to_string()),
+            ]
+        );
+        assert!(attachment_descriptions("<p>No attachments</p>").is_empty());
+    }
+
+    #[test]
+    fn decodes_entities() {
+        assert_eq!(
+            decode_entities("&lt;a&gt; &#65;&#x42; &bogus; &"),
+            "<a> AB &bogus; &"
+        );
+    }
+
+    #[test]
+    fn existence_check_passes_on_transport_errors() {
+        let trac = testing::offline(testing::config());
+        assert!(matches!(
+            trac.page_exists("WikiStart"),
+            Err(TracError::Transport(_))
+        ));
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::BTreeMap;
+
+use crate::{TracAction, TracError};
+
+/// Maps the semantic operations used by the ticket helpers onto the workflow
+/// action names of a particular Trac installation. An operation left as
+/// `None` is not supported by the workflow.
+#[derive(Debug, Clone)]
+pub struct WorkflowProfile {
+    pub request_review: Option<String>,
+    pub approve: Option<String>,
+    pub reject: Option<String>,
+    pub accept: Option<String>,
+    pub accept_no_estimate: Option<String>,
+    pub release: Option<String>,
+    pub reopen: Option<String>,
+    pub close: Option<String>,
+    pub qa_pass: Option<String>,
+    pub qa_fail: Option<String>,
+}
+
+fn action_name(name: &str) -> Option<String> {
+    Some(name.to_string())
+}
+
+impl WorkflowProfile {
+    /// The default workflow shipped with Trac, which has no review steps.
+    pub fn stock() -> Self {
+        Self {
+            request_review: None,
+            approve: None,
+            reject: None,
+            accept: action_name("accept"),
+            accept_no_estimate: action_name("accept"),
+            release: action_name("leave"),
+            reopen: action_name("reopen"),
+            close: action_name("resolve"),
+            qa_pass: None,
+            qa_fail: None,
+        }
+    }
+
+    /// A workflow extended with peer review and estimation steps.
+    pub fn peer_review() -> Self {
+        Self {
+            request_review: action_name("peer_review"),
+            approve: action_name("pass_peer_review"),
+            reject: action_name("reject"),
+            accept: action_name("accept"),
+            accept_no_estimate: action_name("no_estimate_needed"),
+            release: action_name("leave"),
+            reopen: action_name("reopen"),
+            close: action_name("resolve"),
+            qa_pass: None,
+            qa_fail: None,
+        }
+    }
+
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "stock" => Some(Self::stock()),
+            "peer_review" => Some(Self::peer_review()),
+            _ => None,
+        }
+    }
+
+    /// Builds a profile from a `[workflow]` config section. The optional
+    /// `profile` key selects the preset to start from (`stock` by default);
+    /// every other key overrides a single operation, and an empty value
+    /// marks the operation as unsupported.
+    pub(crate) fn from_section(section: &BTreeMap<String, String>) -> Result<Self, TracError> {
+        let mut profile = match section.get("profile") {
+            Some(name) => match Self::preset(name) {
+                Some(p) => p,
+                None => {
+                    return Err(TracError::Config(format!(
+                        "unknown workflow profile '{}'",
+                        name
+                    )))
+                }
+            },
+            None => Self::stock(),
+        };
+
+        for (key, value) in section {
+            let action = if value.is_empty() {
+                None
+            } else {
+                Some(value.to_owned())
+            };
+
+            match key.as_str() {
+                "profile" => continue,
+                "request_review" => profile.request_review = action,
+                "approve" => profile.approve = action,
+                "reject" => profile.reject = action,
+                "accept" => profile.accept = action,
+                "accept_no_estimate" => profile.accept_no_estimate = action,
+                "release" => profile.release = action,
+                "reopen" => profile.reopen = action,
+                "close" => profile.close = action,
+                "qa_pass" => profile.qa_pass = action,
+                "qa_fail" => profile.qa_fail = action,
+                _ => {
+                    return Err(TracError::Config(format!(
+                        "unknown workflow operation '{}'",
+                        key
+                    )))
+                }
+            }
+        }
+
+        Ok(profile)
+    }
+
+    /// The action configured for an operation such as `close`.
+    pub fn operation(&self, operation: &str) -> Option<&str> {
+        let action = match operation {
+            "request_review" => &self.request_review,
+            "approve" => &self.approve,
+            "reject" => &self.reject,
+            "accept" => &self.accept,
+            "accept_no_estimate" => &self.accept_no_estimate,
+            "release" => &self.release,
+            "reopen" => &self.reopen,
+            "close" => &self.close,
+            "qa_pass" => &self.qa_pass,
+            "qa_fail" => &self.qa_fail,
+            _ => return None,
+        };
+        action.as_deref()
+    }
+
+    pub(crate) fn action(operation: &str, name: &Option<String>) -> Result<TracAction, TracError> {
+        match name {
+            Some(n) => Ok(TracAction::new(n)),
+            None => Err(TracError::Unsupported(format!(
+                "workflow profile has no action for '{}'",
+                operation
+            ))),
+        }
+    }
+}
+
+impl Default for WorkflowProfile {
+    fn default() -> Self {
+        Self::stock()
+    }
+}
@@ -0,0 +1,82 @@
+use std::time::{Duration, SystemTime};
+
+use crate::{QueryOp, TicketQuery, Trac, TracError, TracReviewer};
+
+/// The open tickets waiting on one reviewer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReviewerLoad {
+    pub reviewer: String,
+    pub tickets: Vec<i32>,
+    /// The ticket that has waited longest, and since when: the last time
+    /// the reviewer was assigned to it.
+    pub oldest: Option<(i32, SystemTime)>,
+}
+
+impl ReviewerLoad {
+    pub fn open(&self) -> usize {
+        self.tickets.len()
+    }
+
+    /// How long the oldest ticket has been waiting.
+    pub fn longest_wait(&self) -> Option<Duration> {
+        self.oldest
+            .and_then(|(_, since)| SystemTime::now().duration_since(since).ok())
+    }
+}
+
+impl Trac {
+    /// Counts the open tickets whose `reviewer` is each member of `roster`,
+    /// or one of their aliases. Every member gets an entry, in roster
+    /// order, so idle reviewers show up too.
+    pub fn reviewer_workload(
+        &self,
+        roster: &[TracReviewer],
+    ) -> Result<Vec<ReviewerLoad>, TracError> {
+        let mut loads: Vec<ReviewerLoad> = roster
+            .iter()
+            .map(|r| ReviewerLoad {
+                reviewer: r.name.to_owned(),
+                tickets: vec![],
+                oldest: None,
+            })
+            .collect();
+
+        let names: Vec<&str> = roster
+            .iter()
+            .flat_map(|r| std::iter::once(&r.name).chain(&r.aliases))
+            .map(|n| n.as_str())
+            .collect();
+        if names.is_empty() {
+            return Ok(loads);
+        }
+
+        let tickets = TicketQuery::new()
+            .open(self)
+            .filter("reviewer", QueryOp::Is, &names)
+            .order_by("id", false)
+            .fetch(self)?;
+        let changelogs = self.changelogs(&tickets)?;
+
+        for (ticket, changes) in tickets.iter().zip(changelogs) {
+            let member = roster
+                .iter()
+                .position(|r| r.name == ticket.reviewer || r.aliases.contains(&ticket.reviewer));
+            let load = match member {
+                Some(i) => &mut loads[i],
+                None => continue,
+            };
+            let since = changes
+                .iter()
+                .rev()
+                .find(|c| c.field == "reviewer")
+                .map(|c| c.time)
+                .unwrap_or(ticket.created);
+
+            load.tickets.push(ticket.id);
+            if load.oldest.is_none_or(|(_, oldest)| since < oldest) {
+                load.oldest = Some((ticket.id, since));
+            }
+        }
+        Ok(loads)
+    }
+}
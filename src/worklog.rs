@@ -0,0 +1,215 @@
+use std::collections::BTreeSet;
+use std::time::{Duration, SystemTime};
+
+use crate::{time, Trac, TracError, TracTicket};
+
+/// Marks comments written by `log_work`, e.g.
+/// `[worklog] 2026-10-14 1h30m: Reproduced the crash`.
+pub const WORKLOG_PREFIX: &str = "[worklog]";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkLogEntry {
+    pub author: String,
+    /// When the entry was recorded on the ticket.
+    pub logged_at: SystemTime,
+    /// The day the work was done.
+    pub date: SystemTime,
+    pub duration: Duration,
+    pub description: String,
+}
+
+fn duration_hours(duration: Duration) -> f64 {
+    duration.as_secs_f64() / 3600.0
+}
+
+fn hours_duration(hours: f64) -> Option<Duration> {
+    if hours > 0.0 {
+        Duration::try_from_secs_f64(hours * 3600.0).ok()
+    } else {
+        None
+    }
+}
+
+pub fn format_worklog(duration: Duration, description: &str, date: SystemTime) -> String {
+    format!(
+        "{} {} {}: {}",
+        WORKLOG_PREFIX,
+        time::format_date(date),
+        time::format_duration(duration),
+        description
+    )
+}
+
+// Reads `1h30m`-style durations, and the decimal hours (`1.50h`) that older
+// versions wrote. A bare number has no unit and is refused.
+fn parse_logged_duration(text: &str) -> Option<Duration> {
+    if !text.ends_with(|c: char| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    match time::parse_duration(text) {
+        Some(duration) if duration.is_zero() => None,
+        Some(duration) => Some(duration),
+        None => hours_duration(text.strip_suffix('h')?.parse().ok()?),
+    }
+}
+
+/// Parses a comment written by `format_worklog` into the date, duration and
+/// description of the work.
+pub fn parse_worklog(text: &str) -> Option<(SystemTime, Duration, String)> {
+    let rest = text.trim_start().strip_prefix(WORKLOG_PREFIX)?.trim_start();
+    let (date, rest) = rest.split_at(rest.find(' ')?);
+    let rest = rest.trim_start();
+    let colon = rest.find(':')?;
+
+    Some((
+        time::parse_date(date)?,
+        parse_logged_duration(rest[..colon].trim())?,
+        rest[colon + 1..].trim().to_string(),
+    ))
+}
+
+impl TracTicket {
+    /// Records work as a structured comment. If the ticket carries the
+    /// timing plugin's `hours` field, the hours are also added there in the
+    /// same change.
+    pub fn log_work(
+        &self,
+        duration: Duration,
+        description: &str,
+        date: SystemTime,
+        trac: &Trac,
+    ) -> Result<TracTicket, TracError> {
+        let comment = format_worklog(duration, description, date);
+
+        if self.fields.contains_key("hours") {
+            self.add_hours(duration_hours(duration), Some(comment), trac)
+        } else {
+            self.update_builder().comment(&comment).submit(trac)
+        }
+    }
+
+    /// Reads the work log back out of the changelog. Besides `log_work`
+    /// comments, hours entered directly into the timing plugin's field
+    /// (e.g. through the web UI) are included, described by the comment made
+    /// in the same change.
+    pub fn work_log(&self, trac: &Trac) -> Result<Vec<WorkLogEntry>, TracError> {
+        let changes = self.changelog(trac)?;
+        let mut entries = Vec::new();
+        let mut logged_times = BTreeSet::new();
+
+        for change in changes.iter().filter(|c| c.field == "comment") {
+            if let Some((date, duration, description)) = parse_worklog(&change.new_value) {
+                logged_times.insert(time::to_unix(change.time));
+                entries.push(WorkLogEntry {
+                    author: change.author.to_owned(),
+                    logged_at: change.time,
+                    date,
+                    duration,
+                    description,
+                });
+            }
+        }
+
+        for change in changes.iter().filter(|c| c.field == "hours") {
+            if logged_times.contains(&time::to_unix(change.time)) {
+                continue;
+            }
+            let duration = match hours_duration(change.new_value.trim().parse().unwrap_or(0.0)) {
+                Some(d) => d,
+                None => continue,
+            };
+            let description = changes
+                .iter()
+                .find(|c| c.field == "comment" && c.time == change.time)
+                .map(|c| c.new_value.to_owned())
+                .unwrap_or_default();
+
+            entries.push(WorkLogEntry {
+                author: change.author.to_owned(),
+                logged_at: change.time,
+                date: change.time,
+                duration,
+                description,
+            });
+        }
+
+        entries.sort_by_key(|e| e.logged_at);
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(date: &str) -> SystemTime {
+        time::parse_date(date).unwrap()
+    }
+
+    #[test]
+    fn formats_and_parses_entries() {
+        let comment = format_worklog(
+            Duration::from_secs(90 * 60),
+            "Reproduced the crash: it's the cache",
+            day("2026-10-14"),
+        );
+        assert_eq!(
+            comment,
+            "[worklog] 2026-10-14 1h30m: Reproduced the crash: it's the cache"
+        );
+        assert_eq!(
+            parse_worklog(&comment),
+            Some((
+                day("2026-10-14"),
+                Duration::from_secs(90 * 60),
+                "Reproduced the crash: it's the cache".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn keeps_whole_minutes() {
+        let comment = format_worklog(Duration::from_secs(20 * 60), "Triage", day("2026-10-14"));
+        assert_eq!(comment, "[worklog] 2026-10-14 20m: Triage");
+        assert_eq!(
+            parse_worklog(&comment).map(|(_, duration, _)| duration),
+            Some(Duration::from_secs(20 * 60))
+        );
+    }
+
+    #[test]
+    fn reads_decimal_hours() {
+        assert_eq!(
+            parse_worklog("[worklog] 2026-10-14 1.50h: Review").map(|(_, duration, _)| duration),
+            Some(Duration::from_secs(90 * 60))
+        );
+    }
+
+    #[test]
+    fn tolerates_spacing() {
+        assert_eq!(
+            parse_worklog("  [worklog]  2026-01-02   2h :  Review  "),
+            Some((
+                day("2026-01-02"),
+                Duration::from_secs(7200),
+                "Review".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn ignores_other_comments() {
+        for text in [
+            "Fixed in r123",
+            "[worklog] yesterday 1h: Review",
+            "[worklog] 2026-01-02 1: Review",
+            "[worklog] 2026-01-02 0h: Review",
+            "[worklog] 2026-01-02 -1h: Review",
+            "[worklog] 2026-01-02 NaNh: Review",
+            "[worklog] 2026-01-02 1e300h: Review",
+            "[worklog] 2026-01-02 1h Review",
+        ] {
+            assert_eq!(parse_worklog(text), None, "{}", text);
+        }
+    }
+}